@@ -3,20 +3,70 @@
 
 use super::beacon_entries::BeaconEntry;
 use ahash::AHashMap;
-use async_std::sync::RwLock;
+use async_std::future::timeout;
+use async_std::sync::{Arc, RwLock};
 use async_trait::async_trait;
 use bls_signatures::{PublicKey, Serialize, Signature};
 use byteorder::{BigEndian, WriteBytesExt};
 use clock::ChainEpoch;
+use db::Store;
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
 use sha2::Digest;
-use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 /// Default endpoint for the drand beacon node.
 pub const DEFAULT_DRAND_URL: &str = "https://api.drand.sh";
 
+/// Prefix of the keys of the verified beacon entries persisted in the store
+const DRAND_ENTRY_PREFIX: &str = "/drand/";
+
+/// Drand network the beacon entries are fetched from
+#[derive(Clone, Debug, SerdeSerialize, SerdeDeserialize)]
+#[serde(default)]
+pub struct DrandConfig {
+    /// HTTP endpoints of the drand network, tried in order until one of them responds
+    pub servers: Vec<String>,
+    /// Timeout of a request to an endpoint, in seconds
+    pub request_timeout: u64,
+    /// Round period of the drand network in seconds, used until the chain info of the network
+    /// is fetched from an endpoint
+    pub period: u64,
+    /// Unix time of the first round of the drand network, used until the chain info of the
+    /// network is fetched from an endpoint
+    pub genesis_time: u64,
+}
+
+impl Default for DrandConfig {
+    fn default() -> Self {
+        Self {
+            servers: vec![
+                DEFAULT_DRAND_URL.to_owned(),
+                "https://api2.drand.sh".to_owned(),
+                "https://api3.drand.sh".to_owned(),
+                "https://drand.cloudflare.com".to_owned(),
+            ],
+            request_timeout: 10,
+            period: 30,
+            genesis_time: 1_595_431_050,
+        }
+    }
+}
+
+/// Drand network the chain switches to at a network upgrade
+#[derive(Clone, Debug, SerdeSerialize, SerdeDeserialize)]
+pub struct DrandPoint {
+    /// First epoch the entries of the network are used at
+    pub height: ChainEpoch,
+    pub public: DrandPublic,
+    #[serde(default)]
+    pub config: DrandConfig,
+}
+
 /// Coeffiencients of the publicly available Drand keys.
 /// This is shared by all participants on the Drand network.
 #[derive(Clone, Debug, SerdeSerialize, SerdeDeserialize)]
@@ -67,8 +117,11 @@ pub struct BeaconEntryJson {
     previous_signature: String,
 }
 
-pub struct DrandBeacon {
-    url: Cow<'static, str>,
+pub struct DrandBeacon<DB> {
+    servers: Vec<String>,
+    request_timeout: Duration,
+    /// Index of the last server which responded, tried first by the next request
+    server: AtomicUsize,
 
     pub_key: DrandPublic,
     interval: u64,
@@ -78,43 +131,128 @@ pub struct DrandBeacon {
 
     /// Keeps track of computed beacon entries.
     local_cache: RwLock<AHashMap<u64, BeaconEntry>>,
+    /// Store the verified entries are persisted in, so they are not fetched again
+    db: Arc<DB>,
+    /// Prefix of the keys of the persisted entries, unique to the drand network as the rounds
+    /// of different networks overlap
+    key_prefix: String,
 }
 
-impl DrandBeacon {
-    /// Construct a new DrandBeacon.
+impl<DB> DrandBeacon<DB>
+where
+    DB: Store,
+{
+    /// Construct a new DrandBeacon. The chain info of the drand network is fetched from the
+    /// first endpoint which responds, falling back to the configured period and genesis time
+    /// if none of them does, so the node can start while the endpoints are unreachable.
     pub async fn new(
-        url: impl Into<Cow<'static, str>>,
+        config: &DrandConfig,
         pub_key: DrandPublic,
         genesis_ts: u64,
         interval: u64,
+        db: Arc<DB>,
     ) -> Result<Self, Box<dyn error::Error>> {
         if genesis_ts == 0 {
             panic!("Genesis timestamp cannot be 0")
         }
-        let url = url.into();
-        let chain_info: ChainInfo = surf::get(&format!("{}/info", &url)).recv_json().await?;
-        let remote_pub_key = hex::decode(chain_info.public_key)?;
-        if remote_pub_key != pub_key.coefficient {
-            return Err(Box::try_from(
-                "Drand pub key from config is different than one on drand servers",
-            )?);
-        }
-
-        Ok(Self {
-            url,
+        let key_prefix = format!(
+            "{}{}/",
+            DRAND_ENTRY_PREFIX,
+            hex::encode(&pub_key.coefficient[..pub_key.coefficient.len().min(8)])
+        );
+        let mut beacon = Self {
+            servers: config.servers.clone(),
+            request_timeout: Duration::from_secs(config.request_timeout),
+            server: AtomicUsize::new(0),
             pub_key,
-            interval: chain_info.period as u64,
-            drand_gen_time: chain_info.genesis_time as u64,
+            interval: config.period,
+            drand_gen_time: config.genesis_time,
             fil_round_time: interval,
             fil_gen_time: genesis_ts,
             local_cache: Default::default(),
-        })
+            db,
+            key_prefix,
+        };
+
+        match beacon.fetch::<ChainInfo>("info").await {
+            Ok(chain_info) => {
+                let remote_pub_key = hex::decode(chain_info.public_key)?;
+                if remote_pub_key != beacon.pub_key.coefficient {
+                    return Err(Box::try_from(
+                        "Drand pub key from config is different than one on drand servers",
+                    )?);
+                }
+                beacon.interval = chain_info.period as u64;
+                beacon.drand_gen_time = chain_info.genesis_time as u64;
+            }
+            Err(e) => warn!(
+                "Failed to fetch drand chain info, using the configured period and genesis time: {}",
+                e
+            ),
+        }
+        Ok(beacon)
+    }
+
+    /// Gets the path from the drand endpoints, starting with the last one which responded and
+    /// failing over to the next one on errors and timeouts.
+    async fn fetch<T>(&self, path: &str) -> Result<T, Box<dyn error::Error>>
+    where
+        T: DeserializeOwned,
+    {
+        if self.servers.is_empty() {
+            return Err(Box::try_from("No drand servers configured")?);
+        }
+        let start = self.server.load(Ordering::Relaxed);
+        let mut last_err = String::new();
+        for i in 0..self.servers.len() {
+            let idx = (start + i) % self.servers.len();
+            let url = format!("{}/{}", self.servers[idx], path);
+            match timeout(self.request_timeout, surf::get(&url).recv_json::<T>()).await {
+                Ok(Ok(res)) => {
+                    self.server.store(idx, Ordering::Relaxed);
+                    return Ok(res);
+                }
+                Ok(Err(e)) => last_err = e.to_string(),
+                Err(e) => last_err = e.to_string(),
+            }
+            debug!("Drand request to {} failed: {}", url, last_err);
+        }
+        Err(Box::try_from(format!(
+            "All drand servers failed, last error: {}",
+            last_err
+        ))?)
+    }
+
+    fn entry_key(&self, round: u64) -> String {
+        format!("{}{}", self.key_prefix, round)
+    }
+
+    /// Returns the verified entry of the round, from memory or from the store.
+    async fn verified_entry(
+        &self,
+        round: u64,
+    ) -> Result<Option<BeaconEntry>, Box<dyn error::Error>> {
+        if let Some(entry) = self.local_cache.read().await.get(&round) {
+            return Ok(Some(entry.clone()));
+        }
+        match self.db.read(self.entry_key(round))? {
+            Some(bz) => {
+                let entry: BeaconEntry = encoding::from_slice(&bz)?;
+                self.local_cache.write().await.insert(round, entry.clone());
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
     }
 }
+
 /// This struct allows you to talk to a Drand node over GRPC.
 /// Use this to source randomness and to verify Drand beacon entries.
 #[async_trait]
-impl Beacon for DrandBeacon {
+impl<DB> Beacon for DrandBeacon<DB>
+where
+    DB: Store + Send + Sync,
+{
     async fn verify_entry(
         &self,
         curr: &BeaconEntry,
@@ -125,6 +263,11 @@ impl Beacon for DrandBeacon {
             return Ok(true);
         }
 
+        // Entries are only persisted once verified
+        if self.verified_entry(curr.round()).await?.as_ref() == Some(curr) {
+            return Ok(true);
+        }
+
         // Hash the messages
         let mut msg: Vec<u8> = Vec::with_capacity(104);
         msg.extend_from_slice(prev.data());
@@ -137,8 +280,10 @@ impl Beacon for DrandBeacon {
         let sig = Signature::from_bytes(curr.data())?;
         let sig_match = bls_signatures::verify(&sig, &[digest], &[self.pub_key.key()]);
 
-        // Cache the result
+        // Cache and persist the result
         if sig_match && !self.local_cache.read().await.contains_key(&curr.round()) {
+            self.db
+                .write(self.entry_key(curr.round()), encoding::to_vec(curr)?)?;
             self.local_cache
                 .write()
                 .await
@@ -148,11 +293,11 @@ impl Beacon for DrandBeacon {
     }
 
     async fn entry(&self, round: u64) -> Result<BeaconEntry, Box<dyn error::Error>> {
-        match self.local_cache.read().await.get(&round) {
-            Some(cached_entry) => Ok(cached_entry.clone()),
+        let cached = self.verified_entry(round).await?;
+        match cached {
+            Some(cached_entry) => Ok(cached_entry),
             None => {
-                let url = format!("{}/public/{}", self.url, round);
-                let resp: BeaconEntryJson = surf::get(&url).recv_json().await?;
+                let resp: BeaconEntryJson = self.fetch(&format!("public/{}", round)).await?;
                 Ok(BeaconEntry::new(resp.round, hex::decode(resp.signature)?))
             }
         }
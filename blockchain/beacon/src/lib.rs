@@ -4,26 +4,48 @@
 pub mod beacon_entries;
 mod drand;
 mod mock_beacon;
+mod schedule;
 
 pub use beacon_entries::*;
 pub use drand::*;
 pub use mock_beacon::*;
+pub use schedule::*;
 
 use clock::ChainEpoch;
 use std::error::Error;
 
+/// Fetches the beacon entries to include in a block at the epoch, on top of a parent tipset
+/// at parent_epoch whose latest entry is prev.
 pub async fn beacon_entries_for_block<B: Beacon>(
-    beacon: B,
+    schedule: &BeaconSchedule<B>,
     round: ChainEpoch,
+    parent_epoch: ChainEpoch,
     prev: &BeaconEntry,
 ) -> Result<Vec<BeaconEntry>, Box<dyn Error>> {
+    let beacon = schedule.beacon_for_epoch(round);
+    if schedule.is_fork(parent_epoch, round) {
+        // The first block on the new beacon includes two of its entries, the first one being
+        // the prev entry of the second one.
+        let max_round = beacon.max_beacon_round_for_epoch(round);
+        let prev_round = max_round.checked_sub(1).ok_or_else(|| {
+            format!(
+                "Beacon has no round before round {} for the beacon fork at epoch {}",
+                max_round, round
+            )
+        })?;
+        let prev_entry = beacon.entry(prev_round).await?;
+        let entry = beacon.entry(max_round).await?;
+        return Ok(vec![prev_entry, entry]);
+    }
+
     let max_round = beacon.max_beacon_round_for_epoch(round);
     if max_round == prev.round() {
         return Ok(vec![]);
     }
     // TODO: this is a sketchy way to handle the genesis block not having a beacon entry
     let prev_round = if prev.round() == 0 {
-        max_round - 1
+        // max_round is not 0 here, since it differs from the prev round
+        max_round.saturating_sub(1)
     } else {
         prev.round()
     };
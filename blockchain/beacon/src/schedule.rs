@@ -0,0 +1,67 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use crate::Beacon;
+use clock::ChainEpoch;
+use std::error::Error;
+use std::sync::Arc;
+
+/// Beacon used by the chain from a height on
+pub struct BeaconPoint<T> {
+    /// First epoch the beacon is used at
+    pub height: ChainEpoch,
+    pub beacon: Arc<T>,
+}
+
+/// Beacons used by the chain over its epochs, so the chain can switch to another drand network
+/// at a network upgrade.
+pub struct BeaconSchedule<T>(Vec<BeaconPoint<T>>);
+
+impl<T> BeaconSchedule<T>
+where
+    T: Beacon,
+{
+    /// Creates a schedule of the beacon points, which may be given in any order. One of the
+    /// points has to start at height 0, so every epoch has a beacon.
+    pub fn new(mut points: Vec<BeaconPoint<T>>) -> Result<Self, Box<dyn Error>> {
+        points.sort_by_key(|p| p.height);
+        match points.first() {
+            Some(p) if p.height == 0 => (),
+            _ => return Err("Beacon schedule has no beacon at height 0".into()),
+        }
+        if points.windows(2).any(|w| w[0].height == w[1].height) {
+            return Err("Beacon schedule has two beacons at the same height".into());
+        }
+        Ok(Self(points))
+    }
+
+    /// Creates a schedule using the same beacon at every epoch.
+    pub fn single(beacon: Arc<T>) -> Self {
+        Self(vec![BeaconPoint { height: 0, beacon }])
+    }
+
+    /// Returns the point of the beacon used at the epoch.
+    pub fn point_for_epoch(&self, epoch: ChainEpoch) -> &BeaconPoint<T> {
+        // Points are sorted and the first one starts at 0
+        self.0
+            .iter()
+            .rev()
+            .find(|p| p.height <= epoch)
+            .unwrap_or(&self.0[0])
+    }
+
+    /// Returns the beacon used at the epoch.
+    pub fn beacon_for_epoch(&self, epoch: ChainEpoch) -> &T {
+        &self.point_for_epoch(epoch).beacon
+    }
+
+    /// Returns true if the chain switches to another beacon between the epochs.
+    pub fn is_fork(&self, parent_epoch: ChainEpoch, epoch: ChainEpoch) -> bool {
+        self.point_for_epoch(parent_epoch).height != self.point_for_epoch(epoch).height
+    }
+
+    /// Returns the points of the schedule, sorted by height.
+    pub fn points(&self) -> &[BeaconPoint<T>] {
+        &self.0
+    }
+}
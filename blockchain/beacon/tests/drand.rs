@@ -1,17 +1,22 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use beacon::{Beacon, DrandBeacon, DrandPublic, DEFAULT_DRAND_URL};
+use async_std::sync::Arc;
+use beacon::{Beacon, DrandBeacon, DrandConfig, DrandPublic};
+use db::MemoryDB;
 use serde::{Deserialize, Serialize};
 
-async fn new_beacon() -> DrandBeacon {
+fn dist_pub() -> DrandPublic {
     // Current public parameters, subject to change.
     let coeffs =
         hex::decode("922a2e93828ff83345bae533f5172669a26c02dc76d6bf59c80892e12ab1455c229211886f35bb56af6d5bea981024df").unwrap();
-    let dist_pub = DrandPublic {
+    DrandPublic {
         coefficient: coeffs,
-    };
-    DrandBeacon::new(DEFAULT_DRAND_URL, dist_pub, 15904451751, 25)
+    }
+}
+
+async fn new_beacon(db: Arc<MemoryDB>) -> DrandBeacon<MemoryDB> {
+    DrandBeacon::new(&DrandConfig::default(), dist_pub(), 15904451751, 25, db)
         .await
         .unwrap()
 }
@@ -27,23 +32,49 @@ pub struct BeaconEntryJson {
 #[ignore]
 #[async_std::test]
 async fn construct_drand_beacon() {
-    new_beacon().await;
+    new_beacon(Default::default()).await;
+}
+
+#[async_std::test]
+async fn construct_drand_beacon_offline() {
+    let config = DrandConfig {
+        servers: vec!["http://127.0.0.1:1".to_owned()],
+        request_timeout: 1,
+        ..Default::default()
+    };
+    let beacon: DrandBeacon<MemoryDB> =
+        DrandBeacon::new(&config, dist_pub(), 15904451751, 25, Default::default())
+            .await
+            .unwrap();
+    assert!(beacon.entry(2).await.is_err());
 }
 
 #[ignore]
 #[async_std::test]
 async fn ask_and_verify_beacon_entry() {
-    let beacon = new_beacon().await;
+    let db = Arc::new(MemoryDB::default());
+    let beacon = new_beacon(Arc::clone(&db)).await;
 
     let e2 = beacon.entry(2).await.unwrap();
     let e3 = beacon.entry(3).await.unwrap();
     assert!(beacon.verify_entry(&e3, &e2).await.unwrap());
+
+    // Verified entries are read back from the store, without reaching the servers
+    let config = DrandConfig {
+        servers: vec!["http://127.0.0.1:1".to_owned()],
+        request_timeout: 1,
+        ..Default::default()
+    };
+    let offline = DrandBeacon::new(&config, dist_pub(), 15904451751, 25, db)
+        .await
+        .unwrap();
+    assert_eq!(offline.entry(3).await.unwrap(), e3);
 }
 
 #[ignore]
 #[async_std::test]
 async fn ask_and_verify_beacon_entry_fail() {
-    let beacon = new_beacon().await;
+    let beacon = new_beacon(Default::default()).await;
 
     let e2 = beacon.entry(2).await.unwrap();
     let e3 = beacon.entry(3).await.unwrap();
@@ -0,0 +1,20 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use beacon::{Beacon, BeaconEntry, MockBeacon};
+use std::time::Duration;
+
+#[async_std::test]
+async fn verify_mock_entries() {
+    let beacon = MockBeacon::new(Duration::from_secs(1));
+
+    let e4 = beacon.entry(4).await.unwrap();
+    let e5 = beacon.entry(5).await.unwrap();
+    assert!(beacon.verify_entry(&e5, &e4).await.unwrap());
+    let forged = BeaconEntry::new(5, e4.data().to_vec());
+    assert!(!beacon.verify_entry(&forged, &e4).await.unwrap());
+
+    // Entries are deterministic, so separate nodes agree on them
+    let other = MockBeacon::new(Duration::from_secs(1));
+    assert_eq!(other.entry(5).await.unwrap(), e5);
+}
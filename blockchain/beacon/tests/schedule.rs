@@ -0,0 +1,96 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use async_trait::async_trait;
+use beacon::{
+    beacon_entries_for_block, Beacon, BeaconEntry, BeaconPoint, BeaconSchedule, MockBeacon,
+};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn schedule() -> BeaconSchedule<MockBeacon> {
+    let point = |height| BeaconPoint {
+        height,
+        beacon: Arc::new(MockBeacon::new(Duration::from_secs(1))),
+    };
+    BeaconSchedule::new(vec![point(100), point(0)]).unwrap()
+}
+
+#[test]
+fn beacon_for_epoch() {
+    let schedule = schedule();
+    assert_eq!(schedule.point_for_epoch(0).height, 0);
+    assert_eq!(schedule.point_for_epoch(99).height, 0);
+    assert_eq!(schedule.point_for_epoch(100).height, 100);
+    assert_eq!(schedule.point_for_epoch(1000).height, 100);
+
+    assert!(schedule.is_fork(99, 100));
+    assert!(schedule.is_fork(90, 110));
+    assert!(!schedule.is_fork(100, 101));
+}
+
+#[test]
+fn schedule_requires_genesis_beacon() {
+    let point = BeaconPoint {
+        height: 10,
+        beacon: Arc::new(MockBeacon::new(Duration::from_secs(1))),
+    };
+    assert!(BeaconSchedule::new(vec![point]).is_err());
+}
+
+#[async_std::test]
+async fn entries_at_beacon_fork() {
+    let schedule = schedule();
+
+    let prev = BeaconEntry::new(90, vec![]);
+    let entries = beacon_entries_for_block(&schedule, 95, 90, &prev)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 5);
+
+    // The first block on the new beacon has the last two entries of the new beacon
+    let entries = beacon_entries_for_block(&schedule, 100, 95, &entries[4])
+        .await
+        .unwrap();
+    let rounds: Vec<u64> = entries.iter().map(|e| e.round()).collect();
+    assert_eq!(rounds, vec![99, 100]);
+}
+
+/// Beacon whose network has not produced any round yet.
+struct NotStartedBeacon;
+
+#[async_trait]
+impl Beacon for NotStartedBeacon {
+    async fn verify_entry(&self, _: &BeaconEntry, _: &BeaconEntry) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+
+    async fn entry(&self, round: u64) -> Result<BeaconEntry, Box<dyn Error>> {
+        Ok(BeaconEntry::new(round, vec![]))
+    }
+
+    fn max_beacon_round_for_epoch(&self, _: i64) -> u64 {
+        0
+    }
+}
+
+#[async_std::test]
+async fn no_entries_before_first_round_at_beacon_fork() {
+    let schedule = BeaconSchedule::new(vec![
+        BeaconPoint {
+            height: 0,
+            beacon: Arc::new(NotStartedBeacon),
+        },
+        BeaconPoint {
+            height: 100,
+            beacon: Arc::new(NotStartedBeacon),
+        },
+    ])
+    .unwrap();
+
+    let prev = BeaconEntry::new(0, vec![]);
+    assert!(beacon_entries_for_block(&schedule, 100, 95, &prev)
+        .await
+        .is_err());
+}
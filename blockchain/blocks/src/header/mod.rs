@@ -3,7 +3,7 @@
 
 use super::{Error, Ticket, Tipset, TipsetKeys};
 use address::Address;
-use beacon::{self, Beacon, BeaconEntry};
+use beacon::{self, Beacon, BeaconEntry, BeaconSchedule};
 use cid::{multihash::Blake2b256, Cid};
 use clock::ChainEpoch;
 use crypto::{election_proof::ElectionProof, Signature};
@@ -18,7 +18,6 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha2::Digest;
 use std::cmp::Ordering;
 use std::fmt;
-use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use vm::TokenAmount;
 
@@ -383,9 +382,34 @@ impl BlockHeader {
     /// Validates if the current header's Beacon entries are valid to ensure randomness was generated correctly
     pub async fn validate_block_drand<B: Beacon>(
         &self,
-        beacon: Arc<B>,
+        b_schedule: &BeaconSchedule<B>,
+        parent_epoch: ChainEpoch,
         prev_entry: BeaconEntry,
     ) -> Result<(), Error> {
+        let beacon = b_schedule.beacon_for_epoch(self.epoch);
+        if b_schedule.is_fork(parent_epoch, self.epoch) {
+            // The entries of the new beacon can't be chained to the ones of the previous
+            // beacon, so the first block on it includes two entries of the new beacon
+            if self.beacon_entries.len() != 2 {
+                return Err(Error::Validation(format!(
+                    "expected two beacon entries at beacon fork, got: {}",
+                    self.beacon_entries.len()
+                )));
+            }
+            let (prev, curr) = (&self.beacon_entries[0], &self.beacon_entries[1]);
+            if !beacon
+                .verify_entry(curr, prev)
+                .await
+                .map_err(|e| Error::Validation(e.to_string()))?
+            {
+                return Err(Error::Validation(format!(
+                    "beacon entry at beacon fork was invalid: curr:{:?}, prev: {:?}",
+                    curr, prev
+                )));
+            }
+            return Ok(());
+        }
+
         let max_round = beacon.max_beacon_round_for_epoch(self.epoch);
         if max_round == prev_entry.round() {
             if !self.beacon_entries.is_empty() {
@@ -397,7 +421,12 @@ impl BlockHeader {
             return Ok(());
         }
 
-        let last = self.beacon_entries.last().unwrap();
+        let last = self.beacon_entries.last().ok_or_else(|| {
+            Error::Validation(format!(
+                "expected beacon entries up to round {}, got none",
+                max_round
+            ))
+        })?;
         if last.round() != max_round {
             return Err(Error::Validation(format!(
                 "expected final beacon entry in block to be at round {}, got: {}",
@@ -453,8 +482,68 @@ impl BlockHeaderBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::BlockHeader;
+    use crate::{BlockHeader, Error};
+    use address::Address;
+    use async_std::task;
+    use beacon::{Beacon, BeaconEntry, BeaconPoint, BeaconSchedule, MockBeacon};
+    use cid::{multihash::Identity, Cid};
+    use clock::ChainEpoch;
     use encoding::Cbor;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Schedule switching from one mock beacon to another at epoch 10
+    fn two_point_schedule() -> BeaconSchedule<MockBeacon> {
+        let point = |height| BeaconPoint {
+            height,
+            beacon: Arc::new(MockBeacon::new(Duration::from_secs(1))),
+        };
+        BeaconSchedule::new(vec![point(0), point(10)]).unwrap()
+    }
+
+    fn header_with_entries(epoch: ChainEpoch, beacon_entries: Vec<BeaconEntry>) -> BlockHeader {
+        BlockHeader::builder()
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(0))
+            .epoch(epoch)
+            .beacon_entries(beacon_entries)
+            .build_and_validate()
+            .unwrap()
+    }
+
+    #[test]
+    fn validate_drand_at_beacon_fork() {
+        let schedule = two_point_schedule();
+        task::block_on(async {
+            let old_beacon = schedule.beacon_for_epoch(9);
+            let new_beacon = schedule.beacon_for_epoch(10);
+            let prev_entry = old_beacon.entry(9).await.unwrap();
+            let fork_entry = new_beacon.entry(9).await.unwrap();
+            let curr_entry = new_beacon.entry(10).await.unwrap();
+            let fork_entries = vec![fork_entry, curr_entry];
+
+            // The first block on the new beacon includes two of its entries
+            let header = header_with_entries(10, fork_entries.clone());
+            header
+                .validate_block_drand(&schedule, 9, prev_entry.clone())
+                .await
+                .unwrap();
+
+            // Any other number of entries is rejected at the fork
+            for entries in vec![fork_entries[1..].to_vec(), Vec::new()] {
+                let header = header_with_entries(10, entries);
+                match header
+                    .validate_block_drand(&schedule, 9, prev_entry.clone())
+                    .await
+                {
+                    Err(Error::Validation(_)) => (),
+                    res => panic!("expected a validation error, got: {:?}", res),
+                }
+            }
+        });
+    }
 
     #[test]
     fn symmetric_header_encoding() {
@@ -4,16 +4,19 @@
 use super::{Error, TipIndex, TipsetMetadata};
 use actor::{power::State as PowerState, STORAGE_POWER_ACTOR_ADDR};
 use address::Address;
-use beacon::BeaconEntry;
+use beacon::{Beacon, BeaconEntry, BeaconSchedule};
 use blake2b_simd::Params;
 use blocks::{Block, BlockHeader, FullTipset, Tipset, TipsetKeys, TxMeta};
 use byteorder::{BigEndian, WriteBytesExt};
-use cid::multihash::Blake2b256;
-use cid::Cid;
+use cid::multihash::{Blake2b256, Code};
+use cid::{Cid, Codec};
 use clock::ChainEpoch;
 use crypto::DomainSeparationTag;
+use db::Batch;
 use encoding::{blake2b_256, de::DeserializeOwned, from_slice, Cbor};
 use flo_stream::{MessagePublisher, Publisher, Subscriber};
+use forest_car::{CarHeader, CarWriter};
+use forest_ipld::Ipld;
 use ipld_amt::Amt;
 use ipld_blockstore::BlockStore;
 use log::{debug, info, warn};
@@ -22,7 +25,7 @@ use num_bigint::BigInt;
 use num_traits::Zero;
 use serde::Serialize;
 use state_tree::StateTree;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::Write;
 use std::sync::Arc;
 
@@ -79,7 +82,7 @@ where
 
     /// Sets heaviest tipset within ChainStore and store its tipset cids under HEAD_KEY
     pub async fn set_heaviest_tipset(&mut self, ts: Arc<Tipset>) -> Result<(), Error> {
-        self.db.write(HEAD_KEY, ts.key().marshal_cbor()?)?;
+        self.db.write_metadata(HEAD_KEY, ts.key().marshal_cbor()?)?;
         self.heaviest = Some(ts.clone());
         self.publisher.publish(HeadChange::Current(ts)).await;
         Ok(())
@@ -107,11 +110,15 @@ where
         set_genesis(self.blockstore(), header)
     }
 
-    /// Writes tipset block headers to data store and updates heaviest tipset
+    /// Writes tipset block headers to data store and updates heaviest tipset. The headers are
+    /// committed together with the new head, so the head never refers to missing headers.
     pub async fn put_tipset(&mut self, ts: &Tipset) -> Result<(), Error> {
-        persist_objects(self.blockstore(), ts.blocks())?;
+        let mut batch = self.db.begin();
+        for header in ts.blocks() {
+            batch.put(header.cid().to_bytes(), header.marshal_cbor()?);
+        }
         // TODO determine if expanded tipset is required; see https://github.com/filecoin-project/lotus/blob/testnet/3/chain/store/store.go#L236
-        self.update_heaviest(ts).await?;
+        self.update_heaviest(ts, batch).await?;
         Ok(())
     }
 
@@ -182,27 +189,198 @@ where
         Ok(FullTipset::new(blocks).unwrap())
     }
 
-    /// Determines if provided tipset is heavier than existing known heaviest tipset
-    async fn update_heaviest(&mut self, ts: &Tipset) -> Result<(), Error> {
+    /// Exports the chain from the provided tipset to a CAR file. Block headers are exported
+    /// all the way back to genesis, while messages, receipts and state trees are only included
+    /// for the last `recent_roots` epochs (and for genesis).
+    pub fn export<W>(
+        &self,
+        tipset: &Tipset,
+        recent_roots: ChainEpoch,
+        writer: W,
+    ) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let header = CarHeader::new(tipset.cids().to_vec(), 1);
+        let mut writer =
+            CarWriter::new(writer, &header).map_err(|e| Error::Other(e.to_string()))?;
+
+        self.walk_chain(tipset, recent_roots, |cid, data| {
+            writer
+                .write_block(cid, data)
+                .map_err(|e| Error::Other(e.to_string()))
+        })?;
+
+        writer.finish().map_err(|e| Error::Other(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Walks the chain from the provided tipset, calling the closure with the cid and data of
+    /// every block walked. Block headers are walked all the way back to genesis, while
+    /// messages, receipts and state trees are only walked for the last `recent_roots` epochs
+    /// (and for genesis). Returns the cids of all blocks walked.
+    pub(crate) fn walk_chain<F>(
+        &self,
+        tipset: &Tipset,
+        recent_roots: ChainEpoch,
+        mut cb: F,
+    ) -> Result<HashSet<Cid>, Error>
+    where
+        F: FnMut(&Cid, &[u8]) -> Result<(), Error>,
+    {
+        let incl_roots_epoch = tipset.epoch() - recent_roots;
+        let mut seen = HashSet::new();
+        let mut blocks_to_walk: VecDeque<Cid> = tipset.cids().iter().cloned().collect();
+
+        while let Some(next) = blocks_to_walk.pop_front() {
+            if !seen.insert(next.clone()) {
+                continue;
+            }
+            let data = self
+                .blockstore()
+                .get_bytes(&next)
+                .map_err(|e| Error::Other(e.to_string()))?
+                .ok_or_else(|| Error::NotFound("Block header"))?;
+            cb(&next, &data)?;
+
+            let header = BlockHeader::unmarshal_cbor(&data)?;
+            if header.epoch() > incl_roots_epoch {
+                walk_snapshot(self.blockstore(), header.messages(), &mut seen, &mut cb)?;
+            }
+            if header.epoch() > 0 {
+                blocks_to_walk.extend(header.parents().cids().iter().cloned());
+            }
+            if header.epoch() == 0 || header.epoch() > incl_roots_epoch {
+                walk_snapshot(self.blockstore(), header.state_root(), &mut seen, &mut cb)?;
+                walk_snapshot(
+                    self.blockstore(),
+                    header.message_receipts(),
+                    &mut seen,
+                    &mut cb,
+                )?;
+            }
+        }
+        Ok(seen)
+    }
+
+    /// Determines if provided tipset is heavier than existing known heaviest tipset, and
+    /// commits the batch together with the new head if so
+    async fn update_heaviest(&mut self, ts: &Tipset, mut batch: Batch) -> Result<(), Error> {
         match &self.heaviest {
             Some(heaviest) => {
                 let new_weight = weight(self.blockstore(), ts)?;
                 let curr_weight = weight(self.blockstore(), &heaviest)?;
                 if new_weight > curr_weight {
-                    // TODO potentially need to deal with re-orgs here
                     info!("New heaviest tipset");
-                    self.set_heaviest_tipset(Arc::new(ts.clone())).await?;
+                    let new_head = Arc::new(ts.clone());
+                    let (reverts, applies) = reorg_ops(self.blockstore(), heaviest, &new_head)?;
+
+                    batch.put_metadata(HEAD_KEY, new_head.key().marshal_cbor()?);
+                    self.db.commit(batch)?;
+                    self.heaviest = Some(new_head);
+
+                    for ts in reverts {
+                        self.publisher.publish(HeadChange::Revert(ts)).await;
+                    }
+                    for ts in applies.into_iter().rev() {
+                        self.publisher.publish(HeadChange::Apply(ts)).await;
+                    }
+                } else {
+                    self.db.commit(batch)?;
                 }
             }
             None => {
                 info!("set heaviest tipset");
-                self.set_heaviest_tipset(Arc::new(ts.clone())).await?;
+                let new_head = Arc::new(ts.clone());
+                batch.put_metadata(HEAD_KEY, new_head.key().marshal_cbor()?);
+                self.db.commit(batch)?;
+                self.heaviest = Some(new_head.clone());
+                self.publisher.publish(HeadChange::Current(new_head)).await;
             }
         }
         Ok(())
     }
 }
 
+/// Finds the common ancestor of the `from` and `to` tipsets and returns the tipsets to be
+/// reverted and applied to move the head between them. Both vectors are ordered from the
+/// respective head back towards the common ancestor, which is not included.
+#[allow(clippy::type_complexity)]
+pub fn reorg_ops<DB>(
+    db: &DB,
+    from: &Arc<Tipset>,
+    to: &Arc<Tipset>,
+) -> Result<(Vec<Arc<Tipset>>, Vec<Arc<Tipset>>), Error>
+where
+    DB: BlockStore,
+{
+    let mut left = from.clone();
+    let mut right = to.clone();
+    let mut left_chain = Vec::new();
+    let mut right_chain = Vec::new();
+
+    while left.key() != right.key() {
+        if left.epoch() > right.epoch() {
+            let parent = Arc::new(tipset_from_keys(db, left.parents())?);
+            left_chain.push(left);
+            left = parent;
+        } else {
+            let parent = Arc::new(tipset_from_keys(db, right.parents())?);
+            right_chain.push(right);
+            right = parent;
+        }
+    }
+
+    Ok((left_chain, right_chain))
+}
+
+/// Walks the DAG behind `root`, calling the closure with every block not yet in `seen`.
+/// Identity Cids and Filecoin piece/sector commitments are skipped as they are not stored.
+fn walk_snapshot<DB, F>(
+    db: &DB,
+    root: &Cid,
+    seen: &mut HashSet<Cid>,
+    cb: &mut F,
+) -> Result<(), Error>
+where
+    DB: BlockStore,
+    F: FnMut(&Cid, &[u8]) -> Result<(), Error>,
+{
+    let mut stack = vec![root.clone()];
+    while let Some(cid) = stack.pop() {
+        if cid.hash.algorithm() == Code::Identity
+            || cid.codec == Codec::FilCommitmentSealed
+            || cid.codec == Codec::FilCommitmentUnsealed
+        {
+            continue;
+        }
+        if !seen.insert(cid.clone()) {
+            continue;
+        }
+        let data = db
+            .get_bytes(&cid)
+            .map_err(|e| Error::Other(e.to_string()))?
+            .ok_or_else(|| Error::UndefinedKey(cid.to_string()))?;
+        cb(&cid, &data)?;
+
+        if cid.codec == Codec::DagCBOR {
+            let ipld: Ipld = from_slice(&data)?;
+            push_links(&ipld, &mut stack);
+        }
+    }
+    Ok(())
+}
+
+/// Collects all links contained within the Ipld node.
+fn push_links(ipld: &Ipld, links: &mut Vec<Cid>) {
+    match ipld {
+        Ipld::Link(c) => links.push(c.clone()),
+        Ipld::List(arr) => arr.iter().for_each(|item| push_links(item, links)),
+        Ipld::Map(map) => map.values().for_each(|v| push_links(v, links)),
+        _ => (),
+    }
+}
+
 /// Returns messages for a given tipset from db
 pub fn unsigned_messages_for_tipset<DB>(db: &DB, h: &Tipset) -> Result<Vec<UnsignedMessage>, Error>
 where
@@ -270,7 +448,7 @@ fn set_genesis<DB>(db: &DB, header: BlockHeader) -> Result<Cid, Error>
 where
     DB: BlockStore,
 {
-    db.write(GENESIS_KEY, header.marshal_cbor()?)?;
+    db.write_metadata(GENESIS_KEY, header.marshal_cbor()?)?;
     Ok(db
         .put(&header, Blake2b256)
         .map_err(|e| Error::Other(e.to_string()))?)
@@ -289,12 +467,37 @@ where
     Ok(())
 }
 
-/// Finds the latest beacon entry given a tipset up to 20 tipsets behind
-pub fn latest_beacon_entry<DB>(db: &DB, ts: &Tipset) -> Result<BeaconEntry, Error>
+/// Finds the latest beacon entry of the beacon used at the tipset, given a tipset up to 20
+/// tipsets behind. Entries from before the chain switched to that beacon are not considered.
+pub fn latest_beacon_entry<DB, B>(
+    db: &DB,
+    schedule: &BeaconSchedule<B>,
+    ts: &Tipset,
+) -> Result<BeaconEntry, Error>
+where
+    DB: BlockStore,
+    B: Beacon,
+{
+    find_latest_beacon_entry(db, ts, schedule.point_for_epoch(ts.epoch()).height)
+}
+
+/// Finds the latest beacon entry given a tipset up to 20 tipsets behind, without going back
+/// past the tipsets at the min_epoch.
+fn find_latest_beacon_entry<DB>(
+    db: &DB,
+    ts: &Tipset,
+    min_epoch: ChainEpoch,
+) -> Result<BeaconEntry, Error>
 where
     DB: BlockStore,
 {
     let check_for_beacon_entry = |ts: &Tipset| {
+        if ts.epoch() < min_epoch {
+            return Err(Error::Other(format!(
+                "no beacon entry since the beacon switch at epoch {}",
+                min_epoch
+            )));
+        }
         let cbe = ts.min_ticket_block().beacon_entries();
         if let Some(entry) = cbe.last() {
             return Ok(Some(entry.clone()));
@@ -367,7 +570,7 @@ pub fn get_beacon_randomness<DB: BlockStore>(
 
     let rand_ts = tipset_by_height(db, search_height, ts, true)?;
 
-    let be = latest_beacon_entry(db, &rand_ts)?;
+    let be = find_latest_beacon_entry(db, &rand_ts, 0)?;
 
     draw_randomness(be.data(), pers, round, entropy)
 }
@@ -395,7 +598,7 @@ pub fn get_heaviest_tipset<DB>(db: &DB) -> Result<Option<Tipset>, Error>
 where
     DB: BlockStore,
 {
-    match db.read(HEAD_KEY)? {
+    match db.read_metadata(HEAD_KEY)? {
         Some(bz) => {
             let keys: Vec<Cid> = from_slice(&bz)?;
             Ok(Some(tipset_from_keys(db, &TipsetKeys::new(keys))?))
@@ -483,7 +686,7 @@ pub fn genesis<DB>(db: &DB) -> Result<Option<BlockHeader>, Error>
 where
     DB: BlockStore,
 {
-    Ok(match db.read(GENESIS_KEY)? {
+    Ok(match db.read_metadata(GENESIS_KEY)? {
         Some(bz) => Some(BlockHeader::unmarshal_cbor(&bz)?),
         None => None,
     })
@@ -593,7 +796,7 @@ where
 }
 
 /// Returns the weight of provided tipset
-fn weight<DB>(db: &DB, ts: &Tipset) -> Result<BigInt, String>
+pub fn weight<DB>(db: &DB, ts: &Tipset) -> Result<BigInt, String>
 where
     DB: BlockStore,
 {
@@ -631,7 +834,9 @@ where
 mod tests {
     use super::*;
     use address::Address;
+    use beacon::{BeaconPoint, MockBeacon};
     use cid::multihash::Identity;
+    use std::time::Duration;
 
     #[test]
     fn genesis_test() {
@@ -652,4 +857,120 @@ mod tests {
         cs.set_genesis(gen_block.clone()).unwrap();
         assert_eq!(cs.genesis().unwrap(), Some(gen_block));
     }
+
+    fn child_header(parent: &BlockHeader, miner: u64) -> BlockHeader {
+        BlockHeader::builder()
+            .epoch(parent.epoch() + 1)
+            .parents(TipsetKeys::new(vec![parent.cid().clone()]))
+            .weight((2 as u32).into())
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(miner))
+            .build_and_validate()
+            .unwrap()
+    }
+
+    #[test]
+    fn reorg_ops_test() {
+        let db = db::MemoryDB::default();
+        let gen = BlockHeader::builder()
+            .epoch(0)
+            .weight((2 as u32).into())
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(0))
+            .build_and_validate()
+            .unwrap();
+        let a1 = child_header(&gen, 1);
+        let a2 = child_header(&a1, 1);
+        let b1 = child_header(&gen, 2);
+        persist_objects(&db, &[gen, a1.clone(), a2.clone(), b1.clone()]).unwrap();
+
+        let to_ts = |h: &BlockHeader| Arc::new(Tipset::new(vec![h.clone()]).unwrap());
+        let (reverts, applies) = reorg_ops(&db, &to_ts(&a2), &to_ts(&b1)).unwrap();
+        assert_eq!(reverts, vec![to_ts(&a2), to_ts(&a1)]);
+        assert_eq!(applies, vec![to_ts(&b1)]);
+
+        let (reverts, applies) = reorg_ops(&db, &to_ts(&a1), &to_ts(&a2)).unwrap();
+        assert!(reverts.is_empty());
+        assert_eq!(applies, vec![to_ts(&a2)]);
+    }
+
+    #[test]
+    fn latest_beacon_entry_test() {
+        let db = db::MemoryDB::default();
+        let gen = BlockHeader::builder()
+            .epoch(0)
+            .weight((2 as u32).into())
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(0))
+            .build_and_validate()
+            .unwrap();
+        let entry = BeaconEntry::new(1, vec![1]);
+        let a1 = BlockHeader::builder()
+            .epoch(1)
+            .parents(TipsetKeys::new(vec![gen.cid().clone()]))
+            .weight((2 as u32).into())
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(0))
+            .beacon_entries(vec![entry.clone()])
+            .build_and_validate()
+            .unwrap();
+        let a2 = child_header(&a1, 0);
+        persist_objects(&db, &[gen, a1]).unwrap();
+        let ts = Tipset::new(vec![a2]).unwrap();
+
+        let beacon = || Arc::new(MockBeacon::new(Duration::from_secs(1)));
+        let single = BeaconSchedule::single(beacon());
+        assert_eq!(latest_beacon_entry(&db, &single, &ts).unwrap(), entry);
+
+        // the entry is from the beacon used before the switch at epoch 2
+        let switched = BeaconSchedule::new(vec![
+            BeaconPoint {
+                height: 0,
+                beacon: beacon(),
+            },
+            BeaconPoint {
+                height: 2,
+                beacon: beacon(),
+            },
+        ])
+        .unwrap();
+        assert!(latest_beacon_entry(&db, &switched, &ts).is_err());
+    }
+
+    #[test]
+    fn export_genesis_test() {
+        let db = db::MemoryDB::default();
+
+        let cs = ChainStore::new(Arc::new(db));
+        let gen_block = BlockHeader::builder()
+            .epoch(0)
+            .weight((2 as u32).into())
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(0))
+            .build_and_validate()
+            .unwrap();
+        cs.set_genesis(gen_block.clone()).unwrap();
+        let ts = Tipset::new(vec![gen_block]).unwrap();
+
+        let mut buf = Vec::new();
+        cs.export(&ts, 0, &mut buf).unwrap();
+
+        let import_db = db::MemoryDB::default();
+        let roots = forest_car::load_car(&import_db, buf.as_slice()).unwrap();
+        assert_eq!(roots, ts.cids().to_vec());
+        assert_eq!(
+            tipset_from_keys(&import_db, &TipsetKeys::new(roots)).unwrap(),
+            ts
+        );
+    }
 }
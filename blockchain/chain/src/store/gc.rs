@@ -0,0 +1,151 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::{ChainStore, Error};
+use blocks::Tipset;
+use cid::Cid;
+use clock::ChainEpoch;
+use db::IterableStore;
+use ipld_blockstore::BlockStore;
+use log::info;
+use std::convert::TryFrom;
+
+/// Number of unreachable keys deleted together while sweeping
+const SWEEP_BATCH_SIZE: usize = 1000;
+
+/// Outcome of a garbage collection of the chain store
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GcStats {
+    /// Number of blocks reachable from the head, which were kept
+    pub marked: usize,
+    /// Number of unreachable blocks deleted
+    pub swept: usize,
+}
+
+impl<DB> ChainStore<DB>
+where
+    DB: BlockStore + IterableStore,
+{
+    /// Deletes the blocks that are not reachable from the provided tipset. Block headers are
+    /// kept all the way back to genesis, while messages, receipts and state trees are only kept
+    /// for the last `recent_roots` epochs (and for genesis), as with exported snapshots.
+    ///
+    /// Only keys that are Cids are swept, so chain metadata stored under other keys is kept.
+    /// Blocks written while collecting may not be reachable from the tipset yet, so this must
+    /// not run while the node is syncing.
+    pub fn collect_garbage(
+        &self,
+        tipset: &Tipset,
+        recent_roots: ChainEpoch,
+    ) -> Result<GcStats, Error> {
+        let marked = self.walk_chain(tipset, recent_roots, |_, _| Ok(()))?;
+        info!("Marked {} reachable blocks", marked.len());
+
+        // Unreachable keys are deleted in batches while iterating, to bound the memory used
+        let mut unreachable = Vec::with_capacity(SWEEP_BATCH_SIZE);
+        let mut swept = 0;
+        self.blockstore().for_each_key(|key| {
+            match Cid::try_from(key) {
+                // Keys are only swept if they are exactly the bytes of a Cid
+                Ok(cid) if !marked.contains(&cid) && cid.to_bytes() == key => {
+                    unreachable.push(key.to_vec())
+                }
+                _ => (),
+            }
+            if unreachable.len() >= SWEEP_BATCH_SIZE {
+                self.blockstore().bulk_delete(&unreachable)?;
+                swept += unreachable.len();
+                unreachable.clear();
+            }
+            Ok(())
+        })?;
+        self.blockstore().bulk_delete(&unreachable)?;
+        swept += unreachable.len();
+        info!("Swept {} unreachable blocks", swept);
+
+        Ok(GcStats {
+            marked: marked.len(),
+            swept,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persist_objects;
+    use address::Address;
+    use blocks::{BlockHeader, TipsetKeys};
+    use cid::multihash::Identity;
+    use db::{MemoryDB, Store};
+    use std::sync::Arc;
+
+    fn header(parents: &[&BlockHeader], epoch: ChainEpoch, miner: u64) -> BlockHeader {
+        BlockHeader::builder()
+            .epoch(epoch)
+            .parents(TipsetKeys::new(
+                parents.iter().map(|p| p.cid().clone()).collect(),
+            ))
+            .weight((2 as u32).into())
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(Cid::new_from_cbor(&[], Identity))
+            .miner_address(Address::new_id(miner))
+            .build_and_validate()
+            .unwrap()
+    }
+
+    #[test]
+    fn collect_unreachable_blocks() {
+        let db = Arc::new(MemoryDB::default());
+        let cs = ChainStore::new(db.clone());
+
+        let gen = header(&[], 0, 0);
+        let a1 = header(&[&gen], 1, 1);
+        let a2 = header(&[&a1], 2, 1);
+        // Fork which is not reachable from the head
+        let b1 = header(&[&gen], 1, 2);
+        persist_objects(db.as_ref(), &[&gen, &a1, &a2, &b1]).unwrap();
+        cs.set_genesis(gen.clone()).unwrap();
+
+        let head = Tipset::new(vec![a2.clone()]).unwrap();
+        let stats = cs.collect_garbage(&head, 0).unwrap();
+        assert_eq!(
+            stats,
+            GcStats {
+                marked: 3,
+                swept: 1
+            }
+        );
+
+        for h in &[&gen, &a1, &a2] {
+            assert!(db.exists(h.cid().to_bytes()).unwrap());
+        }
+        assert!(!db.exists(b1.cid().to_bytes()).unwrap());
+        // Metadata is never swept
+        assert_eq!(cs.genesis().unwrap(), Some(gen));
+    }
+
+    #[test]
+    fn sweep_in_batches() {
+        let db = Arc::new(MemoryDB::default());
+        let cs = ChainStore::new(db.clone());
+
+        let gen = header(&[], 0, 0);
+        persist_objects(db.as_ref(), &[&gen]).unwrap();
+        cs.set_genesis(gen.clone()).unwrap();
+        let garbage: Vec<u64> = (0..SWEEP_BATCH_SIZE as u64 * 2 + 1).collect();
+        persist_objects(db.as_ref(), &garbage).unwrap();
+
+        let head = Tipset::new(vec![gen.clone()]).unwrap();
+        let stats = cs.collect_garbage(&head, 0).unwrap();
+        assert_eq!(
+            stats,
+            GcStats {
+                marked: 1,
+                swept: garbage.len()
+            }
+        );
+        assert!(db.exists(gen.cid().to_bytes()).unwrap());
+    }
+}
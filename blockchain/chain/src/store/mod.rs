@@ -4,9 +4,11 @@
 pub mod base_fee;
 mod chain_store;
 mod errors;
+mod gc;
 mod tip_index;
 
 pub use self::base_fee::*;
 pub use self::chain_store::*;
 pub use self::errors::*;
+pub use self::gc::*;
 pub use self::tip_index::*;
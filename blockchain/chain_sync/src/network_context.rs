@@ -4,13 +4,15 @@
 use async_std::future;
 use async_std::sync::Sender;
 use blocks::{FullTipset, Tipset, TipsetKeys};
-use flo_stream::Subscriber;
+use cid::Cid;
+use encoding::de::DeserializeOwned;
 use forest_libp2p::{
     blocksync::{BlockSyncRequest, BlockSyncResponse, BLOCKS, MESSAGES},
     hello::HelloRequest,
-    NetworkEvent, NetworkMessage,
+    MessageAcceptance, MessageId, NetworkMessage,
 };
 use futures::channel::oneshot::channel as oneshot_channel;
+use ipld_blockstore::BlockStore;
 use libp2p::core::PeerId;
 use log::trace;
 use std::time::Duration;
@@ -19,20 +21,15 @@ use std::time::Duration;
 const RPC_TIMEOUT: u64 = 20;
 
 /// Context used in chain sync to handle network requests
+#[derive(Clone)]
 pub struct SyncNetworkContext {
     /// Channel to send network messages through p2p service
     network_send: Sender<NetworkMessage>,
-
-    /// Receiver channel for network events
-    pub receiver: Subscriber<NetworkEvent>,
 }
 
 impl SyncNetworkContext {
-    pub fn new(network_send: Sender<NetworkMessage>, receiver: Subscriber<NetworkEvent>) -> Self {
-        Self {
-            network_send,
-            receiver,
-        }
+    pub fn new(network_send: Sender<NetworkMessage>) -> Self {
+        Self { network_send }
     }
 
     /// Send a blocksync request for only block headers (ignore messages)
@@ -103,6 +100,35 @@ impl SyncNetworkContext {
         }
     }
 
+    /// Retrieves an object from the blockstore, requesting it over bitswap if it is not stored
+    /// locally yet
+    pub async fn bitswap_get<DB, T>(&self, db: &DB, cid: Cid) -> Result<T, String>
+    where
+        DB: BlockStore,
+        T: DeserializeOwned,
+    {
+        if let Some(obj) = db.get(&cid).map_err(|e| e.to_string())? {
+            return Ok(obj);
+        }
+
+        let (tx, rx) = oneshot_channel();
+        self.network_send
+            .send(NetworkMessage::BitswapRequest {
+                cid: cid.clone(),
+                response_channel: tx,
+            })
+            .await;
+        match future::timeout(Duration::from_secs(RPC_TIMEOUT), rx).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return Err(format!("Bitswap request failed: {}", e)),
+            Err(_) => return Err("Bitswap request timed out".to_string()),
+        }
+
+        db.get(&cid)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Bitswap block not found in store: {}", cid))
+    }
+
     /// Send a hello request to the network (does not await response)
     pub async fn hello_request(&mut self, peer_id: PeerId, request: HelloRequest) {
         trace!("Sending Hello Message {:?}", request);
@@ -111,4 +137,21 @@ impl SyncNetworkContext {
             .send(NetworkMessage::HelloRequest { peer_id, request })
             .await;
     }
+
+    /// Reports the result of the validation of a gossip message, so valid messages are
+    /// forwarded and the peers forwarding invalid ones are penalized
+    pub async fn report_validation(
+        &self,
+        message_id: MessageId,
+        propagation_source: PeerId,
+        acceptance: MessageAcceptance,
+    ) {
+        self.network_send
+            .send(NetworkMessage::ValidationResult {
+                message_id,
+                propagation_source,
+                acceptance,
+            })
+            .await;
+    }
 }
@@ -5,7 +5,7 @@ use super::peer_manager::PeerManager;
 use async_std::prelude::*;
 use async_std::sync::Receiver;
 use async_std::task;
-use flo_stream::{MessagePublisher, Publisher};
+use flo_stream::{MessagePublisher, Publisher, Subscriber};
 use forest_libp2p::hello::HelloResponse;
 use forest_libp2p::NetworkEvent;
 use log::trace;
@@ -28,6 +28,11 @@ impl NetworkHandler {
         }
     }
 
+    /// Subscribes to the network events handled, alongside the syncer
+    pub(crate) fn subscribe(&mut self) -> Subscriber<NetworkEvent> {
+        self.event_send.subscribe()
+    }
+
     pub(crate) fn spawn(&self, peer_manager: Arc<PeerManager>) {
         let mut receiver = self.receiver.clone();
         let mut event_send = self.event_send.republish();
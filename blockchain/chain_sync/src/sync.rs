@@ -12,10 +12,10 @@ use super::sync_state::{SyncStage, SyncState};
 use super::{Error, SyncNetworkContext};
 use address::{Address, Protocol};
 use amt::Amt;
-use async_std::sync::{Receiver, RwLock, Sender};
+use async_std::sync::{channel, Receiver, RwLock, Sender};
 use async_std::task;
-use beacon::{Beacon, BeaconEntry};
-use blocks::{Block, BlockHeader, FullTipset, Tipset, TipsetKeys, TxMeta};
+use beacon::{Beacon, BeaconEntry, BeaconSchedule};
+use blocks::{Block, BlockHeader, FullTipset, GossipBlock, Tipset, TipsetKeys, TxMeta};
 use chain::{persist_objects, ChainStore};
 use cid::{multihash::Blake2b256, Cid};
 use commcid::cid_to_replica_commitment_v1;
@@ -25,13 +25,17 @@ use crypto::DomainSeparationTag;
 use encoding::{Cbor, Error as EncodingError};
 use fil_types::SectorInfo;
 use filecoin_proofs_api::{post::verify_winning_post, ProverId, PublicReplicaInfo, SectorId};
-use flo_stream::{MessagePublisher, Publisher};
+use flo_stream::{MessagePublisher, Publisher, Subscriber};
 use forest_libp2p::{
-    hello::HelloRequest, BlockSyncRequest, NetworkEvent, NetworkMessage, MESSAGES,
+    hello::HelloRequest, BlockSyncRequest, MessageAcceptance, NetworkEvent, NetworkMessage,
+    MESSAGES, PUBSUB_BLOCK_STR,
 };
 use futures::{
     executor::block_on,
+    future::{try_join_all, FutureExt},
+    select,
     stream::{FuturesUnordered, StreamExt},
+    try_join,
 };
 use ipld_blockstore::BlockStore;
 use libp2p::core::PeerId;
@@ -47,6 +51,9 @@ use std::convert::{TryFrom, TryInto};
 use std::sync::Arc;
 use vm::TokenAmount;
 
+/// Number of assembled gossip blocks buffered until the syncer handles them
+const GOSSIP_BLOCK_BUFFER: usize = 30;
+
 /// Struct that handles the ChainSync logic. This handles incoming network events such as
 /// gossipsub messages, Hello protocol requests, as well as sending and receiving BlockSync
 /// messages to be able to do the initial sync.
@@ -55,8 +62,8 @@ pub struct ChainSyncer<DB, TBeacon> {
     // TODO should be a vector once syncing done async and ideally not wrap each state in mutex.
     state: Arc<RwLock<SyncState>>,
 
-    /// Drand randomness beacon
-    beacon: Arc<TBeacon>,
+    /// Drand randomness beacons used by the chain over its epochs
+    beacon: Arc<BeaconSchedule<TBeacon>>,
 
     /// manages retrieving and updates state objects
     state_manager: Arc<StateManager<DB>>,
@@ -73,6 +80,9 @@ pub struct ChainSyncer<DB, TBeacon> {
     /// Context to be able to send requests to p2p network
     network: SyncNetworkContext,
 
+    /// Network events received by the syncer
+    net_events: Subscriber<NetworkEvent>,
+
     /// the known genesis tipset
     genesis: Tipset,
 
@@ -100,7 +110,7 @@ where
 {
     pub fn new(
         chain_store: ChainStore<DB>,
-        beacon: Arc<TBeacon>,
+        beacon: Arc<BeaconSchedule<TBeacon>>,
         network_send: Sender<NetworkMessage>,
         network_rx: Receiver<NetworkEvent>,
         genesis: Tipset,
@@ -109,7 +119,8 @@ where
 
         // Split incoming channel to handle blocksync requests
         let mut event_send = Publisher::new(30);
-        let network = SyncNetworkContext::new(network_send, event_send.subscribe());
+        let network = SyncNetworkContext::new(network_send);
+        let net_events = event_send.subscribe();
 
         let peer_manager = Arc::new(PeerManager::default());
 
@@ -121,6 +132,7 @@ where
             state_manager,
             chain_store,
             network,
+            net_events,
             genesis,
             bad_blocks: Arc::new(BadBlockCache::default()),
             net_handler,
@@ -140,50 +152,161 @@ where
         self.state.clone()
     }
 
+    /// Returns a subscription to the network events received by the syncer, so other
+    /// services can handle them as well. Subscribers must keep up with the events, as the
+    /// syncer waits for them to be received.
+    pub fn network_events(&mut self) -> Subscriber<NetworkEvent> {
+        self.net_handler.subscribe()
+    }
+
     /// Spawns a network handler and begins the syncing process.
     pub async fn start(mut self) -> Result<(), Error> {
         self.net_handler.spawn(Arc::clone(&self.peer_manager));
 
-        while let Some(event) = self.network.receiver.next().await {
-            match event {
-                NetworkEvent::HelloRequest { request, channel } => {
-                    let source = channel.peer.clone();
-                    debug!(
-                        "Message inbound, heaviest tipset cid: {:?}",
-                        request.heaviest_tip_set
-                    );
-                    match self
-                        .fetch_tipset(source.clone(), &TipsetKeys::new(request.heaviest_tip_set))
-                        .await
-                    {
-                        Ok(fts) => {
-                            if let Err(e) = self.inform_new_head(source.clone(), &fts).await {
-                                warn!("Failed to sync with provided tipset: {}", e);
-                            };
-                        }
-                        Err(e) => {
-                            warn!("Failed to fetch full tipset from peer ({}): {}", source, e);
-                        }
+        // Gossip blocks are assembled on their own tasks, which send them back to be synced
+        let (gossip_send, gossip_recv) = channel(GOSSIP_BLOCK_BUFFER);
+        let mut gossip_recv = gossip_recv.fuse();
+        loop {
+            select! {
+                event = self.net_events.next().fuse() => match event {
+                    Some(event) => self.handle_network_event(event, &gossip_send).await,
+                    None => break,
+                },
+                block = gossip_recv.next() => if let Some((source, fts)) = block {
+                    if let Err(e) = self.inform_new_head(source, &fts).await {
+                        warn!("Failed to process gossip block: {}", e);
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_network_event(
+        &mut self,
+        event: NetworkEvent,
+        gossip_send: &Sender<(PeerId, FullTipset)>,
+    ) {
+        match event {
+            NetworkEvent::HelloRequest { request, channel } => {
+                let source = channel.peer.clone();
+                debug!(
+                    "Message inbound, heaviest tipset cid: {:?}",
+                    request.heaviest_tip_set
+                );
+                match self
+                    .fetch_tipset(source.clone(), &TipsetKeys::new(request.heaviest_tip_set))
+                    .await
+                {
+                    Ok(fts) => {
+                        if let Err(e) = self.inform_new_head(source.clone(), &fts).await {
+                            warn!("Failed to sync with provided tipset: {}", e);
+                        };
+                    }
+                    Err(e) => {
+                        warn!("Failed to fetch full tipset from peer ({}): {}", source, e);
                     }
                 }
-                NetworkEvent::PeerDialed { peer_id } => {
-                    let heaviest = self.chain_store.heaviest_tipset().unwrap();
+            }
+            NetworkEvent::PeerDialed { peer_id } => {
+                let heaviest = self.chain_store.heaviest_tipset().unwrap();
+                self.network
+                    .hello_request(
+                        peer_id,
+                        HelloRequest {
+                            heaviest_tip_set: heaviest.cids().to_vec(),
+                            heaviest_tipset_height: heaviest.epoch(),
+                            heaviest_tipset_weight: heaviest.weight().clone(),
+                            genesis_hash: self.genesis.blocks()[0].cid().clone(),
+                        },
+                    )
+                    .await
+            }
+            NetworkEvent::PubsubMessage {
+                source,
+                topics,
+                message,
+                message_id,
+                propagation_source,
+            } => {
+                if !topics
+                    .iter()
+                    .any(|t| t.as_str().starts_with(PUBSUB_BLOCK_STR))
+                {
+                    return;
+                }
+                let block = match self.validate_gossip_block(&message).await {
+                    Ok(block) => block,
+                    Err(e) => {
+                        warn!("Rejected gossip block: {}", e);
+                        self.network
+                            .report_validation(
+                                message_id,
+                                propagation_source,
+                                MessageAcceptance::Reject,
+                            )
+                            .await;
+                        return;
+                    }
+                };
+                // Blocks that cannot be checked against the local chain are not propagated
+                if let Err(e) = self.validate_gossip_header(&block.header) {
+                    debug!("Ignoring gossip block {}: {}", block.header.cid(), e);
                     self.network
-                        .hello_request(
-                            peer_id,
-                            HelloRequest {
-                                heaviest_tip_set: heaviest.cids().to_vec(),
-                                heaviest_tipset_height: heaviest.epoch(),
-                                heaviest_tipset_weight: heaviest.weight().clone(),
-                                genesis_hash: self.genesis.blocks()[0].cid().clone(),
-                            },
+                        .report_validation(
+                            message_id,
+                            propagation_source,
+                            MessageAcceptance::Ignore,
                         )
-                        .await
+                        .await;
+                    return;
                 }
-                _ => (),
+                self.network
+                    .report_validation(
+                        message_id,
+                        propagation_source.clone(),
+                        MessageAcceptance::Accept,
+                    )
+                    .await;
+                let source = source.unwrap_or(propagation_source);
+                let network = self.network.clone();
+                let db = self.chain_store.db.clone();
+                let gossip_send = gossip_send.clone();
+                task::spawn(async move {
+                    match assemble_gossip_block(network, db.as_ref(), source.clone(), block).await {
+                        Ok(fts) => gossip_send.send((source, fts)).await,
+                        Err(e) => warn!("Failed to process gossip block: {}", e),
+                    }
+                });
             }
+            _ => (),
         }
-        Ok(())
+    }
+
+    /// Decodes a block received over gossipsub, failing if it is known to be bad.
+    async fn validate_gossip_block(&self, bz: &[u8]) -> Result<GossipBlock, Error> {
+        let block = GossipBlock::unmarshal_cbor(bz)?;
+        if let Some(reason) = self.bad_blocks.peek(block.header.cid()).await {
+            return Err(Error::Other(format!("Block marked as bad: {}", reason)));
+        }
+        Ok(block)
+    }
+
+    /// Checks the header of a block received over gossipsub before it is propagated: its
+    /// parents must be known, its timestamp valid for its epoch and it must be signed by the
+    /// worker of its miner.
+    fn validate_gossip_header(&self, header: &BlockHeader) -> Result<(), Error> {
+        let parent_tipset = self.chain_store.tipset_from_keys(header.parents())?;
+        header.validate_timestamps(&parent_tipset)?;
+        let work_addr = self
+            .state_manager
+            .get_miner_work_addr(header.state_root(), header.miner_address())?;
+        header
+            .signature()
+            .as_ref()
+            .ok_or_else(|| Error::Validation("Signature is nil in header".to_owned()))?
+            .verify(&header.to_signing_bytes()?, &work_addr)
+            .map_err(|e| Error::Blockchain(blocks::Error::InvalidSignature(e)))
     }
 
     /// Performs syncing process
@@ -696,11 +819,12 @@ where
 
         let prev_beacon = chain::latest_beacon_entry(
             self.chain_store.blockstore(),
-            &self.chain_store.tipset_from_keys(header.parents())?,
+            &self.beacon,
+            &parent_tipset,
         )?;
 
         header
-            .validate_block_drand(Arc::clone(&self.beacon), prev_beacon)
+            .validate_block_drand(&self.beacon, parent_tipset.epoch(), prev_beacon)
             .await?;
 
         let power_result = self
@@ -1042,14 +1166,72 @@ fn cids_from_messages<T: Cbor>(messages: &[T]) -> Result<Vec<Cid>, EncodingError
     messages.iter().map(Cbor::cid).collect()
 }
 
+/// Assembles a block received over gossipsub with its messages. Messages not in the store are
+/// requested over bitswap, falling back to a blocksync request to the peer that published the
+/// block.
+async fn assemble_gossip_block<DB>(
+    mut network: SyncNetworkContext,
+    db: &DB,
+    source: PeerId,
+    block: GossipBlock,
+) -> Result<FullTipset, Error>
+where
+    DB: BlockStore,
+{
+    let GossipBlock {
+        header,
+        bls_messages,
+        secpk_messages,
+    } = block;
+    debug!(
+        "Received gossip block at epoch {}: {}",
+        header.epoch(),
+        header.cid()
+    );
+
+    let fetched = try_join!(
+        try_join_all(
+            bls_messages
+                .into_iter()
+                .map(|c| network.bitswap_get::<_, UnsignedMessage>(db, c))
+        ),
+        try_join_all(
+            secpk_messages
+                .into_iter()
+                .map(|c| network.bitswap_get::<_, SignedMessage>(db, c))
+        )
+    );
+    let block = match fetched {
+        Ok((bls_messages, secp_messages)) => Block {
+            header,
+            bls_messages,
+            secp_messages,
+        },
+        Err(e) => {
+            debug!("Failed to fetch gossip block messages over bitswap: {}", e);
+            let tsk = TipsetKeys::new(vec![header.cid().clone()]);
+            network
+                .blocksync_fts(source, &tsk)
+                .await
+                .map_err(Error::Other)?
+                .into_blocks()
+                .into_iter()
+                .next()
+                .ok_or(Error::NoBlocks)?
+        }
+    };
+
+    Ok(FullTipset::new(vec![block])?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use async_std::sync::channel;
     use async_std::sync::Sender;
-    use beacon::MockBeacon;
+    use beacon::{BeaconSchedule, MockBeacon};
     use blocks::BlockHeader;
-    use db::MemoryDB;
+    use db::{MemoryDB, Store};
     use forest_libp2p::NetworkEvent;
     use std::sync::Arc;
     use test_utils::{construct_blocksync_response, construct_messages, construct_tipset};
@@ -1069,7 +1251,9 @@ mod tests {
         let gen = dummy_header();
         chain_store.set_genesis(gen.clone()).unwrap();
 
-        let beacon = Arc::new(MockBeacon::new(Duration::from_secs(1)));
+        let beacon = Arc::new(BeaconSchedule::single(Arc::new(MockBeacon::new(
+            Duration::from_secs(1),
+        ))));
 
         let genesis_ts = Tipset::new(vec![gen]).unwrap();
         (
@@ -1144,6 +1328,83 @@ mod tests {
         });
     }
 
+    fn gossip_block(bls: &UnsignedMessage, secp: &SignedMessage) -> GossipBlock {
+        GossipBlock {
+            header: dummy_header(),
+            bls_messages: vec![bls.cid().unwrap()],
+            secpk_messages: vec![secp.cid().unwrap()],
+        }
+    }
+
+    #[test]
+    fn assemble_gossip_block_from_stored_messages() {
+        let db = MemoryDB::default();
+        let (bls, secp) = construct_messages();
+        db.put(&bls, Blake2b256).unwrap();
+        db.put(&secp, Blake2b256).unwrap();
+        let (network_send, network_rx) = channel(20);
+
+        task::block_on(async move {
+            let fts = assemble_gossip_block(
+                SyncNetworkContext::new(network_send),
+                &db,
+                PeerId::random(),
+                gossip_block(&bls, &secp),
+            )
+            .await
+            .unwrap();
+            let block = &fts.blocks()[0];
+            assert_eq!(block.bls_msgs(), &[bls]);
+            assert_eq!(block.secp_msgs(), &[secp]);
+            // nothing was requested over the network
+            assert!(network_rx.is_empty());
+        });
+    }
+
+    #[test]
+    fn assemble_gossip_block_fetches_missing_messages() {
+        let db = Arc::new(MemoryDB::default());
+        let remote = MemoryDB::default();
+        let (bls, secp) = construct_messages();
+        remote.put(&bls, Blake2b256).unwrap();
+        remote.put(&secp, Blake2b256).unwrap();
+        let (network_send, network_rx) = channel(20);
+
+        // answers bitswap requests with the blocks of the remote store
+        let local = Arc::clone(&db);
+        task::spawn(async move {
+            while let Ok(msg) = network_rx.recv().await {
+                match msg {
+                    NetworkMessage::BitswapRequest {
+                        cid,
+                        response_channel,
+                    } => {
+                        let bz = remote.read(cid.to_bytes()).unwrap().unwrap();
+                        local.write(cid.to_bytes(), bz).unwrap();
+                        response_channel.send(()).unwrap();
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        });
+
+        task::block_on(async move {
+            let fts = assemble_gossip_block(
+                SyncNetworkContext::new(network_send),
+                db.as_ref(),
+                PeerId::random(),
+                gossip_block(&bls, &secp),
+            )
+            .await
+            .unwrap();
+            let block = &fts.blocks()[0];
+            assert_eq!(block.bls_msgs(), &[bls.clone()]);
+            assert_eq!(block.secp_msgs(), &[secp.clone()]);
+            assert!(db.exists(bls.cid().unwrap().to_bytes()).unwrap());
+            assert!(db.exists(secp.cid().unwrap().to_bytes()).unwrap());
+        });
+    }
+
     #[test]
     fn compute_msg_meta_given_msgs_test() {
         let db = Arc::new(MemoryDB::default());
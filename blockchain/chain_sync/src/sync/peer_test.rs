@@ -4,7 +4,7 @@
 use super::*;
 use async_std::sync::channel;
 use async_std::task;
-use beacon::MockBeacon;
+use beacon::{BeaconSchedule, MockBeacon};
 use blocks::BlockHeader;
 use db::MemoryDB;
 use forest_libp2p::{hello::HelloRequest, rpc::ResponseChannel};
@@ -30,7 +30,9 @@ fn peer_manager_update() {
     chain_store.set_genesis(dummy_header.clone()).unwrap();
 
     let genesis_ts = Tipset::new(vec![dummy_header]).unwrap();
-    let beacon = Arc::new(MockBeacon::new(Duration::from_secs(1)));
+    let beacon = Arc::new(BeaconSchedule::single(Arc::new(MockBeacon::new(
+        Duration::from_secs(1),
+    ))));
     let cs = ChainSyncer::new(
         chain_store,
         beacon,
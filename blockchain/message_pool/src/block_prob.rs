@@ -0,0 +1,103 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+/// Maximum number of blocks in a tipset considered for message selection
+pub(crate) const MAX_BLOCKS: usize = 15;
+/// Expected number of winning blocks per epoch
+const EXPECTED_WINNERS: f64 = 5.0;
+
+/// Natural logarithm of `n!`
+fn ln_factorial(n: usize) -> f64 {
+    (2..=n).map(|i| (i as f64).ln()).sum()
+}
+
+/// Probabilities of there being `i + 1` other winners in the epoch, given there is at least one,
+/// following a Poisson distribution.
+fn no_winners_prob_assuming_more_than_one() -> Vec<f64> {
+    let cond = (EXPECTED_WINNERS.exp() - 1.0).ln();
+    (1..=MAX_BLOCKS)
+        .map(|x| (EXPECTED_WINNERS.ln() * x as f64 - ln_factorial(x) - cond).exp())
+        .collect()
+}
+
+fn binomial_coefficient(mut n: f64, k: f64) -> f64 {
+    if k > n {
+        return f64::NAN;
+    }
+    let mut r = 1.0;
+    let mut d = 1.0;
+    while d <= k {
+        r *= n;
+        r /= d;
+        n -= 1.0;
+        d += 1.0;
+    }
+    r
+}
+
+/// Returns, for each position `i` in a tipset, the probability that a block with the given
+/// ticket quality ends up at that position, i.e. that its messages will be executed after the
+/// messages of `i` other blocks.
+pub(crate) fn block_probabilities(tq: f64) -> Vec<f64> {
+    let no_winners = no_winners_prob_assuming_more_than_one();
+    let p = 1.0 - tq;
+    let bino_pdf = |x: f64, trials: f64| -> f64 {
+        if x > trials {
+            return 0.0;
+        }
+        if p == 0.0 {
+            return if x == 0.0 { 1.0 } else { 0.0 };
+        }
+        if (p - 1.0).abs() < f64::EPSILON {
+            return if (x - trials).abs() < f64::EPSILON {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let coef = binomial_coefficient(trials, x);
+        if coef.is_infinite() {
+            return 0.0;
+        }
+        coef * p.powf(x) * (1.0 - p).powf(trials - x)
+    };
+
+    (0..MAX_BLOCKS)
+        .map(|place| {
+            no_winners
+                .iter()
+                .enumerate()
+                .map(|(other_winners, p_case)| {
+                    p_case * bino_pdf(place as f64, other_winners as f64)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_probability_bounds() {
+        let bp = block_probabilities(1.0);
+        assert_eq!(bp.len(), MAX_BLOCKS);
+        // The best ticket is always first
+        assert!(bp[0] > 0.99);
+
+        for tq in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            let bp = block_probabilities(*tq);
+            assert!(bp.iter().all(|p| *p >= 0.0 && *p <= 1.0));
+            let sum: f64 = bp.iter().sum();
+            assert!((sum - 1.0).abs() < 0.01, "probabilities sum to {}", sum);
+        }
+    }
+
+    #[test]
+    fn better_tickets_go_first() {
+        let low = block_probabilities(0.2);
+        let high = block_probabilities(0.8);
+        assert!(high[0] > low[0]);
+    }
+}
@@ -0,0 +1,35 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use serde::Deserialize;
+
+/// Limits and replacement rules of the message pool
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MpoolConfig {
+    /// Percentage by which a message has to raise the gas premium of the pending message with
+    /// the same sender and sequence to replace it
+    pub replace_by_fee_percent: u64,
+    /// Maximum number of pending messages of a single sender. Local senders are not limited.
+    pub max_sender_msgs: usize,
+    /// Maximum number of pending messages. When exceeded, the messages of the non-local
+    /// senders paying the lowest premiums are evicted.
+    pub max_tx_pool_size: usize,
+    /// Interval in seconds between republishes of the pending local messages, which are also
+    /// republished after every head change
+    pub republish_interval: u64,
+    /// Maximum number of local messages republished at once
+    pub republish_max_msgs: usize,
+}
+
+impl Default for MpoolConfig {
+    fn default() -> Self {
+        Self {
+            replace_by_fee_percent: 25,
+            max_sender_msgs: 1000,
+            max_tx_pool_size: 5000,
+            republish_interval: 100,
+            republish_max_msgs: 500,
+        }
+    }
+}
@@ -13,6 +13,10 @@ pub enum Error {
     MessageTooBig,
     #[error("gas price is lower than min gas price")]
     GasPriceTooLow,
+    #[error("Sender has too many pending messages")]
+    TooManyPendingMessages,
+    #[error("Message pool is full")]
+    MessagePoolFull,
     #[error("Cannot send more Filecoin than will ever exist")]
     MessageValueTooHigh,
     #[error("Message sequence too low")]
@@ -25,6 +29,8 @@ pub enum Error {
     InvalidFromAddr,
     #[error("Message with sequence already in mempool")]
     DuplicateSequence,
+    #[error("Invalid message signature: {0}")]
+    InvalidSignature(String),
     #[error("{0}")]
     Other(String),
 }
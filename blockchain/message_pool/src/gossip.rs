@@ -0,0 +1,152 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::errors::Error;
+use super::msgpool::{MessagePool, Provider};
+use async_std::sync::{Arc, Sender};
+use encoding::Cbor;
+use forest_libp2p::{MessageAcceptance, NetworkEvent, NetworkMessage, PUBSUB_MSG_STR};
+use futures::{Stream, StreamExt};
+use log::{debug, trace};
+use message::{Message, SignedMessage};
+
+/// Adds the messages published on the messages gossip topic to the message pool. The result
+/// of the validation of every message is reported back to the network service, so only valid
+/// messages are forwarded and the peers forwarding invalid ones are penalized.
+pub async fn handle_gossip_messages<T, S>(
+    mpool: Arc<MessagePool<T>>,
+    mut events: S,
+    network_send: Sender<NetworkMessage>,
+) where
+    T: Provider + std::marker::Send + std::marker::Sync + 'static,
+    S: Stream<Item = NetworkEvent> + Unpin,
+{
+    while let Some(event) = events.next().await {
+        if let NetworkEvent::PubsubMessage {
+            topics,
+            message,
+            message_id,
+            propagation_source,
+            ..
+        } = event
+        {
+            if !topics
+                .iter()
+                .any(|t| t.as_str().starts_with(PUBSUB_MSG_STR))
+            {
+                continue;
+            }
+            let acceptance = mpool.add_gossip_message(&message).await;
+            network_send
+                .send(NetworkMessage::ValidationResult {
+                    message_id,
+                    propagation_source,
+                    acceptance,
+                })
+                .await;
+        }
+    }
+}
+
+impl<T> MessagePool<T>
+where
+    T: Provider + std::marker::Send + std::marker::Sync + 'static,
+{
+    /// Decodes a message received over gossipsub and adds it to the pool if it is valid.
+    async fn add_gossip_message(&self, bz: &[u8]) -> MessageAcceptance {
+        let msg = match SignedMessage::unmarshal_cbor(bz) {
+            Ok(msg) => msg,
+            Err(e) => {
+                debug!("Failed to decode gossip message: {}", e);
+                return MessageAcceptance::Reject;
+            }
+        };
+        match self.add(&msg).await {
+            Ok(()) => {
+                trace!("Added gossip message from {}", msg.from());
+                MessageAcceptance::Accept
+            }
+            Err(e) => {
+                debug!("Failed to add gossip message from {}: {}", msg.from(), e);
+                acceptance(&e)
+            }
+        }
+    }
+}
+
+/// Returns whether a message that failed to be added to the pool is invalid. Messages that
+/// may become valid, or that failed to be added because of the local state of the pool, are
+/// ignored rather than rejected.
+fn acceptance(err: &Error) -> MessageAcceptance {
+    match err {
+        Error::MessageTooBig
+        | Error::MessageValueTooHigh
+        | Error::InvalidSignature(_)
+        | Error::InvalidToAddr
+        | Error::InvalidFromAddr => MessageAcceptance::Reject,
+        _ => MessageAcceptance::Ignore,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_provider::TestApi;
+    use crate::MpoolConfig;
+    use address::Address;
+    use async_std::task;
+    use crypto::SignatureType;
+    use key_management::{MemKeyStore, Wallet};
+    use message::UnsignedMessage;
+    use num_bigint::BigInt;
+
+    #[test]
+    fn gossip_message_acceptance() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let from = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let umsg = UnsignedMessage::builder()
+            .to(Address::new_id(1001))
+            .from(from)
+            .gas_limit(10_000)
+            .gas_fee_cap(BigInt::from(200))
+            .gas_premium(BigInt::from(100))
+            .build()
+            .unwrap();
+        let sig = wallet.sign(&from, &umsg.marshal_cbor().unwrap()).unwrap();
+        let smsg = SignedMessage::new_from_parts(umsg.clone(), sig).unwrap();
+
+        // Message signed by another key than the sender's, encoded as a signed message
+        let other = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let bad_sig = wallet.sign(&other, &umsg.marshal_cbor().unwrap()).unwrap();
+        let bad_bz = encoding::to_vec(&(&umsg, &bad_sig)).unwrap();
+
+        task::block_on(async move {
+            let mpool = MessagePool::new(
+                TestApi::default(),
+                "mptest".to_owned(),
+                MpoolConfig::default(),
+            )
+            .await
+            .unwrap();
+
+            let bz = smsg.marshal_cbor().unwrap();
+            assert_eq!(
+                mpool.add_gossip_message(&bz).await,
+                MessageAcceptance::Accept
+            );
+            // Receiving the same message again is not the fault of the peer
+            assert_eq!(
+                mpool.add_gossip_message(&bz).await,
+                MessageAcceptance::Ignore
+            );
+            assert_eq!(
+                mpool.add_gossip_message(&bad_bz).await,
+                MessageAcceptance::Reject
+            );
+            assert_eq!(
+                mpool.add_gossip_message(&[0x01, 0x02]).await,
+                MessageAcceptance::Reject
+            );
+        })
+    }
+}
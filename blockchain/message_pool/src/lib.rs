@@ -1,8 +1,16 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+mod block_prob;
+mod config;
 mod errors;
+mod gossip;
+mod local_store;
 mod msgpool;
+mod republish;
+mod selection;
 
+pub use self::config::MpoolConfig;
 pub use self::errors::*;
+pub use self::gossip::handle_gossip_messages;
 pub use self::msgpool::*;
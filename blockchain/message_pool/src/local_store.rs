@@ -0,0 +1,97 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::errors::Error;
+use cid::Cid;
+use db::Store;
+use encoding::Cbor;
+use message::SignedMessage;
+use std::sync::{Arc, Mutex};
+
+/// Prefix of the keys of the persisted local messages, followed by the message cid
+const LOCAL_MSG_PREFIX: &str = "/mpool/local/";
+/// Key of the index of the cids of the persisted local messages, as the store can't be
+/// iterated by prefix
+const LOCAL_MSG_INDEX: &str = "/mpool/local";
+
+fn local_msg_key(cid: &Cid) -> String {
+    format!("{}{}", LOCAL_MSG_PREFIX, cid)
+}
+
+fn read_index<DB: Store>(db: &DB) -> Result<Vec<Cid>, Error> {
+    match db
+        .read_metadata(LOCAL_MSG_INDEX)
+        .map_err(|e| Error::Other(e.to_string()))?
+    {
+        Some(bz) => Ok(encoding::from_slice(&bz).map_err(|e| Error::Other(e.to_string()))?),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn encode_index(index: &[Cid]) -> Result<Vec<u8>, Error> {
+    encoding::to_vec(&index).map_err(|e| Error::Other(e.to_string()))
+}
+
+/// Store of the messages pushed by the node, persisted so they survive restarts. The messages
+/// and their index are written together, and the read-modify-write updates of the index are
+/// serialized by the lock of the store.
+pub(crate) struct LocalStore<DB> {
+    db: Arc<DB>,
+    index_lock: Mutex<()>,
+}
+
+impl<DB: Store> LocalStore<DB> {
+    pub fn new(db: Arc<DB>) -> Self {
+        Self {
+            db,
+            index_lock: Mutex::new(()),
+        }
+    }
+
+    /// Persists a message pushed by the node.
+    pub fn put(&self, msg: &SignedMessage) -> Result<(), Error> {
+        let cid = msg.cid()?;
+        let _guard = self.index_lock.lock().unwrap();
+        let mut batch = self.db.begin();
+        batch.put_metadata(local_msg_key(&cid), msg.marshal_cbor()?);
+        let mut index = read_index(self.db.as_ref())?;
+        if !index.contains(&cid) {
+            index.push(cid);
+            batch.put_metadata(LOCAL_MSG_INDEX, encode_index(&index)?);
+        }
+        self.db
+            .commit(batch)
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
+    /// Deletes a persisted local message.
+    pub fn delete(&self, msg: &SignedMessage) -> Result<(), Error> {
+        let cid = msg.cid()?;
+        let _guard = self.index_lock.lock().unwrap();
+        let mut batch = self.db.begin();
+        let mut index = read_index(self.db.as_ref())?;
+        if let Some(pos) = index.iter().position(|c| c == &cid) {
+            index.remove(pos);
+            batch.put_metadata(LOCAL_MSG_INDEX, encode_index(&index)?);
+        }
+        batch.delete_metadata(local_msg_key(&cid));
+        self.db
+            .commit(batch)
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
+    /// Loads all persisted local messages.
+    pub fn load(&self) -> Result<Vec<SignedMessage>, Error> {
+        let mut msgs = Vec::new();
+        for cid in read_index(self.db.as_ref())? {
+            if let Some(bz) = self
+                .db
+                .read_metadata(local_msg_key(&cid))
+                .map_err(|e| Error::Other(e.to_string()))?
+            {
+                msgs.push(SignedMessage::unmarshal_cbor(&bz)?);
+            }
+        }
+        Ok(msgs)
+    }
+}
@@ -1,9 +1,13 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use super::config::MpoolConfig;
 use super::errors::Error;
+use super::local_store::LocalStore;
+use super::republish::republish_pending_messages;
 use address::Address;
-use async_std::sync::{Arc, RwLock};
+use async_std::stream;
+use async_std::sync::{Arc, RwLock, Sender};
 use async_std::task;
 use blocks::{BlockHeader, Tipset, TipsetKeys};
 use blockstore::BlockStore;
@@ -12,8 +16,10 @@ use cid::multihash::Blake2b256;
 use cid::Cid;
 use crypto::{Signature, SignatureType};
 use encoding::Cbor;
-use flo_stream::Subscriber;
-use futures::StreamExt;
+use flo_stream::{MessagePublisher, Publisher, Subscriber};
+use forest_libp2p::{NetworkMessage, Topic, PUBSUB_MSG_STR};
+use futures::channel::{mpsc, oneshot};
+use futures::{select, FutureExt, StreamExt};
 use log::{error, warn};
 use lru::LruCache;
 use message::{Message, SignedMessage, UnsignedMessage};
@@ -21,17 +27,24 @@ use num_bigint::BigInt;
 use state_tree::StateTree;
 use std::borrow::BorrowMut;
 use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use vm::ActorState;
 
-const REPLACE_BY_FEE_RATIO: f32 = 1.25;
-const RBF_NUM: u64 = ((REPLACE_BY_FEE_RATIO - 1f32) * 256f32) as u64;
-const RBF_DENOM: u64 = 256;
+/// Capacity of the buffer of each subscriber to message pool updates
+const UPDATE_SINK_CAP: usize = 200;
+
+/// Update of the pending messages, published to subscribers of the message pool
+#[derive(Clone, Debug)]
+pub enum MpoolUpdate {
+    Add(SignedMessage),
+    Remove(SignedMessage),
+}
 
 /// Simple struct that contains a hashmap of messages where k: a message from address, v: a message
 /// which corresponds to that address
 #[derive(Clone, Default)]
 pub struct MsgSet {
-    msgs: HashMap<u64, SignedMessage>,
+    pub(crate) msgs: HashMap<u64, SignedMessage>,
     next_sequence: u64,
 }
 
@@ -46,27 +59,34 @@ impl MsgSet {
 
     /// Add a signed message to the MsgSet. Increase next_sequence if the message has a sequence greater
     /// than any existing message sequence.
-    pub fn add(&mut self, m: SignedMessage) -> Result<(), Error> {
-        if self.msgs.is_empty() || m.sequence() >= self.next_sequence {
-            self.next_sequence = m.sequence() + 1;
-        }
+    ///
+    /// A message with the sequence of a pending message replaces it if it raises the gas premium
+    /// by at least `rbf_percent` percent, in which case the replaced message is returned. New
+    /// sequences are rejected once the set holds `max_msgs` messages.
+    pub fn add(
+        &mut self,
+        m: SignedMessage,
+        rbf_percent: u64,
+        max_msgs: Option<usize>,
+    ) -> Result<Option<SignedMessage>, Error> {
         if let Some(exms) = self.msgs.get(&m.sequence()) {
-            if m.cid()? != exms.cid()? {
-                let premium = exms.message().gas_premium();
-                let rbf_num = BigInt::from(RBF_NUM);
-                let rbf_denom = BigInt::from(RBF_DENOM);
-                let min_price = premium + ((premium * &rbf_num) / rbf_denom) + 1u8;
-                if m.message().gas_premium() <= &min_price {
-                    warn!("message gas price is below min gas price");
-                    return Err(Error::GasPriceTooLow);
-                }
-            } else {
+            if m.cid()? == exms.cid()? {
                 warn!("try to add message with duplicate sequence increase gas premium");
                 return Err(Error::DuplicateSequence);
             }
+            let premium = exms.message().gas_premium();
+            let min_premium = premium + (premium * rbf_percent) / 100u64 + 1u8;
+            if m.message().gas_premium() < &min_premium {
+                warn!("replacement message gas premium is below {}", min_premium);
+                return Err(Error::GasPriceTooLow);
+            }
+        } else if max_msgs.map_or(false, |max| self.msgs.len() >= max) {
+            return Err(Error::TooManyPendingMessages);
         }
-        self.msgs.insert(m.sequence(), m);
-        Ok(())
+        if self.msgs.is_empty() || m.sequence() >= self.next_sequence {
+            self.next_sequence = m.sequence() + 1;
+        }
+        Ok(self.msgs.insert(m.sequence(), m))
     }
 }
 
@@ -93,12 +113,19 @@ pub trait Provider {
     fn load_tipset(&self, tsk: &TipsetKeys) -> Result<Tipset, Error>;
     /// Computes the base fee
     fn chain_compute_base_fee(&self, ts: &Tipset) -> Result<BigInt, Error>;
+    /// Persist a message pushed by the node, so it is reloaded after a restart
+    fn put_local_message(&self, msg: &SignedMessage) -> Result<(), Error>;
+    /// Delete a persisted local message, once it is included on chain
+    fn delete_local_message(&self, msg: &SignedMessage) -> Result<(), Error>;
+    /// Load the persisted local messages
+    fn load_local_messages(&self) -> Result<Vec<SignedMessage>, Error>;
 }
 
 /// This is the mpool provider struct that will let us access and add messages to messagepool.
 /// future
 pub struct MpoolProvider<DB> {
     cs: ChainStore<DB>,
+    local_store: LocalStore<DB>,
 }
 
 impl<'db, DB> MpoolProvider<DB>
@@ -109,7 +136,8 @@ where
     where
         DB: BlockStore,
     {
-        MpoolProvider { cs }
+        let local_store = LocalStore::new(Arc::clone(&cs.db));
+        MpoolProvider { cs, local_store }
     }
 }
 
@@ -161,12 +189,22 @@ where
     fn chain_compute_base_fee(&self, ts: &Tipset) -> Result<BigInt, Error> {
         chain::compute_base_fee(self.cs.blockstore(), ts).map_err(|err| err.into())
     }
+    fn put_local_message(&self, msg: &SignedMessage) -> Result<(), Error> {
+        self.local_store.put(msg)
+    }
+    fn delete_local_message(&self, msg: &SignedMessage) -> Result<(), Error> {
+        self.local_store.delete(msg)
+    }
+    fn load_local_messages(&self) -> Result<Vec<SignedMessage>, Error> {
+        self.local_store.load()
+    }
 }
 
 /// This is the Provider implementation that will be used for the mpool RPC
 pub struct MpoolRpcProvider<DB> {
     subscriber: Subscriber<HeadChange>,
     db: Arc<DB>,
+    local_store: LocalStore<DB>,
 }
 
 impl<DB> MpoolRpcProvider<DB>
@@ -177,7 +215,12 @@ where
     where
         DB: BlockStore,
     {
-        MpoolRpcProvider { subscriber, db }
+        let local_store = LocalStore::new(Arc::clone(&db));
+        MpoolRpcProvider {
+            subscriber,
+            db,
+            local_store,
+        }
     }
 }
 
@@ -231,21 +274,32 @@ where
     fn chain_compute_base_fee(&self, ts: &Tipset) -> Result<BigInt, Error> {
         chain::compute_base_fee(self.db.as_ref(), ts).map_err(|err| err.into())
     }
+    fn put_local_message(&self, msg: &SignedMessage) -> Result<(), Error> {
+        self.local_store.put(msg)
+    }
+    fn delete_local_message(&self, msg: &SignedMessage) -> Result<(), Error> {
+        self.local_store.delete(msg)
+    }
+    fn load_local_messages(&self) -> Result<Vec<SignedMessage>, Error> {
+        self.local_store.load()
+    }
 }
 
 /// This is the main MessagePool struct
 pub struct MessagePool<T: 'static> {
-    local_addrs: Arc<RwLock<Vec<Address>>>,
-    pending: Arc<RwLock<HashMap<Address, MsgSet>>>,
+    pub(crate) local_addrs: Arc<RwLock<Vec<Address>>>,
+    pub(crate) pending: Arc<RwLock<HashMap<Address, MsgSet>>>,
     pub cur_tipset: Arc<RwLock<Tipset>>,
-    api: Arc<RwLock<T>>,
+    pub(crate) api: Arc<RwLock<T>>,
     pub min_gas_price: BigInt,
-    pub max_tx_pool_size: i64,
+    pub config: MpoolConfig,
     pub network_name: String,
     bls_sig_cache: Arc<RwLock<LruCache<Cid, Signature>>>,
     sig_val_cache: Arc<RwLock<LruCache<Cid, ()>>>,
-    // TODO look into adding a cap to local_msgs
     local_msgs: Arc<RwLock<HashSet<SignedMessage>>>,
+    publisher: Arc<RwLock<Publisher<MpoolUpdate>>>,
+    /// Dropped along with the pool, which stops the republish task
+    _republish_stop: Option<oneshot::Sender<()>>,
 }
 
 impl<T> MessagePool<T>
@@ -253,7 +307,37 @@ where
     T: Provider + std::marker::Send + std::marker::Sync + 'static,
 {
     /// Create a new message pool
-    pub async fn new(mut api: T, network_name: String) -> Result<MessagePool<T>, Error>
+    pub async fn new(
+        api: T,
+        network_name: String,
+        config: MpoolConfig,
+    ) -> Result<MessagePool<T>, Error>
+    where
+        T: Provider,
+    {
+        Self::new_inner(api, network_name, None, config).await
+    }
+
+    /// Create a new message pool whose pending local messages are periodically republished
+    /// through the network sender, until the pool is dropped.
+    pub async fn new_with_republish(
+        api: T,
+        network_name: String,
+        network_sender: Sender<NetworkMessage>,
+        config: MpoolConfig,
+    ) -> Result<MessagePool<T>, Error>
+    where
+        T: Provider,
+    {
+        Self::new_inner(api, network_name, Some(network_sender), config).await
+    }
+
+    async fn new_inner(
+        mut api: T,
+        network_name: String,
+        network_sender: Option<Sender<NetworkMessage>>,
+        config: MpoolConfig,
+    ) -> Result<MessagePool<T>, Error>
     where
         T: Provider,
     {
@@ -267,6 +351,7 @@ where
         let sig_val_cache = Arc::new(RwLock::new(LruCache::new(32000)));
         let api_mutex = Arc::new(RwLock::new(api));
         let local_msgs = Arc::new(RwLock::new(HashSet::new()));
+        let publisher = Arc::new(RwLock::new(Publisher::new(UPDATE_SINK_CAP)));
 
         let mut mp = MessagePool {
             local_addrs,
@@ -274,11 +359,13 @@ where
             cur_tipset: tipset,
             api: api_mutex,
             min_gas_price: Default::default(),
-            max_tx_pool_size: 5000,
+            config,
             network_name,
             bls_sig_cache,
             sig_val_cache,
             local_msgs,
+            publisher,
+            _republish_stop: None,
         };
 
         mp.load_local().await?;
@@ -289,34 +376,94 @@ where
         let bls_sig_cache = mp.bls_sig_cache.clone();
         let pending = mp.pending.clone();
         let cur_tipset = mp.cur_tipset.clone();
+        let publisher = mp.publisher.clone();
+        let config = mp.config.clone();
+        let local_msgs = mp.local_msgs.clone();
+        // Triggers a republish after head changes, pending triggers are coalesced
+        let (mut repub_trigger, repub_trigger_rx) = mpsc::channel::<()>(1);
 
         task::spawn(async move {
+            while let Some(ts) = subscriber.next().await {
+                let (revert, apply) = match ts {
+                    HeadChange::Revert(tipset) => (vec![tipset.as_ref().clone()], Vec::new()),
+                    HeadChange::Current(tipset) | HeadChange::Apply(tipset) => {
+                        (Vec::new(), vec![tipset.as_ref().clone()])
+                    }
+                };
+                head_change(
+                    api.as_ref(),
+                    bls_sig_cache.as_ref(),
+                    pending.as_ref(),
+                    cur_tipset.as_ref(),
+                    publisher.as_ref(),
+                    local_msgs.as_ref(),
+                    &config,
+                    revert,
+                    apply,
+                )
+                .await
+                .unwrap_or_else(|err| warn!("Error changing head: {:?}", err));
+                let _ = repub_trigger.try_send(());
+            }
+        });
+
+        let network_sender = match network_sender {
+            Some(network_sender) => network_sender,
+            None => return Ok(mp),
+        };
+        let (republish_stop, republish_stop_rx) = oneshot::channel();
+        mp._republish_stop = Some(republish_stop);
+        let api = mp.api.clone();
+        let pending = mp.pending.clone();
+        let cur_tipset = mp.cur_tipset.clone();
+        let local_addrs = mp.local_addrs.clone();
+        let topic = Topic::new(format!("{}/{}", PUBSUB_MSG_STR, mp.network_name));
+        let interval = Duration::from_secs(mp.config.republish_interval);
+        let max_msgs = mp.config.republish_max_msgs;
+
+        task::spawn(async move {
+            let mut interval = stream::interval(interval).fuse();
+            let mut repub_trigger_rx = repub_trigger_rx.fuse();
+            let mut republish_stop_rx = republish_stop_rx.fuse();
             loop {
-                if let Some(ts) = subscriber.next().await {
-                    let ts = match ts {
-                        HeadChange::Current(tipset)
-                        | HeadChange::Revert(tipset)
-                        | HeadChange::Apply(tipset) => tipset,
-                    };
-                    head_change(
-                        api.as_ref(),
-                        bls_sig_cache.as_ref(),
-                        pending.as_ref(),
-                        cur_tipset.as_ref(),
-                        Vec::new(),
-                        vec![ts.as_ref().clone()],
-                    )
-                    .await
-                    .unwrap_or_else(|err| warn!("Error changing head: {:?}", err));
+                select! {
+                    _ = interval.next() => (),
+                    trigger = repub_trigger_rx.next() => if trigger.is_none() {
+                        break;
+                    },
+                    _ = republish_stop_rx => break,
+                }
+                if let Err(err) = republish_pending_messages(
+                    api.as_ref(),
+                    pending.as_ref(),
+                    cur_tipset.as_ref(),
+                    local_addrs.as_ref(),
+                    &network_sender,
+                    &topic,
+                    max_msgs,
+                )
+                .await
+                {
+                    warn!("Error republishing messages: {:?}", err);
                 }
             }
         });
         Ok(mp)
     }
 
-    /// Add a signed message to local_addrs and local_msgs
+    /// Subscribe to additions and removals of pending messages
+    pub async fn subscribe(&self) -> Subscriber<MpoolUpdate> {
+        self.publisher.write().await.subscribe()
+    }
+
+    /// Add a signed message to local_addrs and local_msgs, and persist it so it is reloaded
+    /// after a restart
     async fn add_local(&self, m: SignedMessage) -> Result<(), Error> {
-        self.local_addrs.write().await.push(*m.from());
+        self.api.read().await.put_local_message(&m)?;
+        let mut local_addrs = self.local_addrs.write().await;
+        if !local_addrs.contains(m.from()) {
+            local_addrs.push(*m.from());
+        }
         self.local_msgs.write().await.insert(m);
         Ok(())
     }
@@ -365,7 +512,7 @@ where
         let umsg = msg.message().marshal_cbor()?;
         msg.signature()
             .verify(umsg.as_slice(), msg.from())
-            .map_err(Error::Other)?;
+            .map_err(Error::InvalidSignature)?;
 
         self.sig_val_cache.write().await.put(cid, ());
 
@@ -394,13 +541,75 @@ where
     /// in the hashmap does not yet exist, create a new mset that will correspond to the from message
     /// and push it to the pending phashmap
     async fn add_helper(&self, msg: SignedMessage) -> Result<(), Error> {
+        let local = self.local_addrs.read().await.contains(msg.from());
+        let (from, sequence) = (*msg.from(), msg.sequence());
         add_helper(
             self.api.as_ref(),
             self.bls_sig_cache.as_ref(),
             self.pending.as_ref(),
+            self.publisher.as_ref(),
+            &self.config,
             msg,
+            // Local senders are not limited, so the node can always send its own messages
+            if local {
+                None
+            } else {
+                Some(self.config.max_sender_msgs)
+            },
         )
-        .await
+        .await?;
+        self.evict_excess(&from, sequence).await
+    }
+
+    /// Evicts pending messages while the pool holds more than `max_tx_pool_size` messages. The
+    /// messages of the non-local sender whose cheapest message pays the lowest gas premium are
+    /// evicted first, as the rest of the messages of a sender can't be included before it.
+    /// Fails if the message just added, identified by its sender and sequence, is evicted.
+    async fn evict_excess(&self, from: &Address, sequence: u64) -> Result<(), Error> {
+        let max_size = self.config.max_tx_pool_size;
+        let local_addrs = self.local_addrs.read().await.clone();
+        let mut pending = self.pending.write().await;
+        let mut size: usize = pending.values().map(|mset| mset.msgs.len()).sum();
+        if size <= max_size {
+            return Ok(());
+        }
+
+        let mut senders: Vec<(Address, BigInt)> = pending
+            .iter()
+            .filter(|(addr, _)| !local_addrs.contains(addr))
+            .filter_map(|(addr, mset)| {
+                let min_premium = mset.msgs.values().map(|m| m.gas_premium()).min()?;
+                Some((*addr, min_premium.clone()))
+            })
+            .collect();
+        senders.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let mut evicted = Vec::new();
+        for (addr, _) in senders {
+            if size <= max_size {
+                break;
+            }
+            if let Some(mset) = pending.remove(&addr) {
+                size -= mset.msgs.len();
+                evicted.extend(mset.msgs.into_iter().map(|(_, m)| m));
+            }
+        }
+        drop(pending);
+
+        if !evicted.is_empty() {
+            warn!("Evicted {} messages from full message pool", evicted.len());
+        }
+        let added_evicted = evicted
+            .iter()
+            .any(|m| m.from() == from && m.sequence() == sequence);
+        let mut publisher = self.publisher.write().await;
+        for msg in evicted {
+            publisher.publish(MpoolUpdate::Remove(msg)).await;
+        }
+        if added_evicted {
+            return Err(Error::MessagePoolFull);
+        }
+        Ok(())
     }
 
     /// Get the sequence for a given address, return Error if there is a failure to retrieve sequence
@@ -424,7 +633,11 @@ where
     }
 
     /// Get the state of the base_sequence for a given address in cur_ts
-    async fn get_state_sequence(&self, addr: &Address, cur_ts: &Tipset) -> Result<u64, Error> {
+    pub(crate) async fn get_state_sequence(
+        &self,
+        addr: &Address,
+        cur_ts: &Tipset,
+    ) -> Result<u64, Error> {
         let api = self.api.read().await;
         let actor = api.state_get_actor(&addr, cur_ts)?;
         let mut base_sequence = actor.sequence;
@@ -447,14 +660,24 @@ where
 
     /// Get the state balance for the actor that corresponds to the supplied address and tipset,
     /// if this actor does not exist, return an error
-    async fn get_state_balance(&self, addr: &Address, ts: &Tipset) -> Result<BigInt, Error> {
+    pub(crate) async fn get_state_balance(
+        &self,
+        addr: &Address,
+        ts: &Tipset,
+    ) -> Result<BigInt, Error> {
         let actor = self.api.read().await.state_get_actor(&addr, &ts)?;
         Ok(actor.balance)
     }
 
     /// Remove a message given a sequence and address from the messagepool
     pub async fn remove(&mut self, from: &Address, sequence: u64) -> Result<(), Error> {
-        remove(from, self.pending.as_ref(), sequence).await
+        remove(
+            from,
+            self.pending.as_ref(),
+            self.publisher.as_ref(),
+            sequence,
+        )
+        .await
     }
 
     /// Return a tuple that contains a vector of all signed messages and the current tipset for
@@ -533,24 +756,30 @@ where
         }
     }
 
-    /// Load local messages into pending. As of  right now messages are not deleted from self's
-    /// local_message field, possibly implement this in the future?
+    /// Load the persisted local messages into pending. Messages that were included on chain
+    /// while the node was not running are deleted.
     pub async fn load_local(&mut self) -> Result<(), Error> {
-        let mut local_msgs = self.local_msgs.write().await;
-        let mut rm_vec = Vec::new();
-        let msg_vec: Vec<SignedMessage> = local_msgs.iter().cloned().collect();
-
-        for k in msg_vec {
-            self.add(&k).await.unwrap_or_else(|err| {
-                if err == Error::SequenceTooLow {
-                    warn!("error adding message: {:?}", err);
-                    rm_vec.push(k);
+        let msg_vec = self.api.read().await.load_local_messages()?;
+        {
+            let mut local_addrs = self.local_addrs.write().await;
+            for m in msg_vec.iter() {
+                if !local_addrs.contains(m.from()) {
+                    local_addrs.push(*m.from());
                 }
-            })
+            }
         }
 
-        for item in rm_vec {
-            local_msgs.remove(&item);
+        let mut local_msgs = self.local_msgs.write().await;
+        for k in msg_vec {
+            match self.add(&k).await {
+                Err(Error::SequenceTooLow) => {
+                    self.api.read().await.delete_local_message(&k)?;
+                    continue;
+                }
+                Err(err) => warn!("error adding local message: {:?}", err),
+                Ok(()) => (),
+            }
+            local_msgs.insert(k);
         }
 
         Ok(())
@@ -561,6 +790,7 @@ where
 pub async fn remove(
     from: &Address,
     pending: &RwLock<HashMap<Address, MsgSet>>,
+    publisher: &RwLock<Publisher<MpoolUpdate>>,
     sequence: u64,
 ) -> Result<(), Error> {
     let mut pending = pending.write().await;
@@ -568,7 +798,7 @@ pub async fn remove(
     let mset = pending
         .get_mut(from)
         .ok_or_else(|| Error::InvalidFromAddr)?;
-    mset.msgs.remove(&sequence);
+    let removed = mset.msgs.remove(&sequence);
 
     if mset.msgs.is_empty() {
         pending.remove(from);
@@ -584,6 +814,15 @@ pub async fn remove(
         }
         mset.next_sequence = max_sequence + 1;
     }
+    drop(pending);
+
+    if let Some(msg) = removed {
+        publisher
+            .write()
+            .await
+            .publish(MpoolUpdate::Remove(msg))
+            .await;
+    }
     Ok(())
 }
 
@@ -606,7 +845,10 @@ async fn add_helper<T>(
     api: &RwLock<T>,
     bls_sig_cache: &RwLock<LruCache<Cid, Signature>>,
     pending: &RwLock<HashMap<Address, MsgSet>>,
+    publisher: &RwLock<Publisher<MpoolUpdate>>,
+    config: &MpoolConfig,
     msg: SignedMessage,
+    max_sender_msgs: Option<usize>,
 ) -> Result<(), Error>
 where
     T: Provider,
@@ -627,27 +869,40 @@ where
     api.read().await.put_message(&msg)?;
 
     let mut pending = pending.write().await;
-    let msett = pending.get_mut(msg.message().from());
-    match msett {
-        Some(mset) => mset.add(msg)?,
-        None => {
-            let mut mset = MsgSet::new();
-            let from = *msg.message().from();
-            mset.add(msg)?;
-            pending.insert(from, mset);
-        }
+    let replaced = pending.entry(*msg.message().from()).or_default().add(
+        msg.clone(),
+        config.replace_by_fee_percent,
+        max_sender_msgs,
+    );
+    // Don't keep an empty set for a sender whose first message was rejected
+    if pending
+        .get(msg.message().from())
+        .map_or(false, |mset| mset.msgs.is_empty())
+    {
+        pending.remove(msg.message().from());
     }
+    let replaced = replaced?;
+    drop(pending);
 
+    let mut publisher = publisher.write().await;
+    if let Some(replaced) = replaced {
+        publisher.publish(MpoolUpdate::Remove(replaced)).await;
+    }
+    publisher.publish(MpoolUpdate::Add(msg)).await;
     Ok(())
 }
 
 /// This function will revert and/or apply tipsets to the message pool. This function should be
 /// called every time that there is a head change in the message pool
+#[allow(clippy::too_many_arguments)]
 pub async fn head_change<T>(
     api: &RwLock<T>,
     bls_sig_cache: &RwLock<LruCache<Cid, Signature>>,
     pending: &RwLock<HashMap<Address, MsgSet>>,
     cur_tipset: &RwLock<Tipset>,
+    publisher: &RwLock<Publisher<MpoolUpdate>>,
+    local_msgs: &RwLock<HashSet<SignedMessage>>,
+    config: &MpoolConfig,
     revert: Vec<Tipset>,
     apply: Vec<Tipset>,
 ) -> Result<(), Error>
@@ -675,22 +930,57 @@ where
         }
     }
 
+    let mut included: HashSet<(Address, u64)> = HashSet::new();
     for ts in apply {
         for b in ts.blocks() {
             let (msgs, smsgs) = api.read().await.messages_for_block(b)?;
+            included.extend(smsgs.iter().map(|m| (*m.from(), m.sequence())));
+            included.extend(msgs.iter().map(|m| (*m.from(), m.sequence())));
 
             for msg in smsgs {
-                rm(msg.from(), pending, msg.sequence(), rmsgs.borrow_mut()).await?;
+                rm(
+                    msg.from(),
+                    pending,
+                    publisher,
+                    msg.sequence(),
+                    rmsgs.borrow_mut(),
+                )
+                .await?;
             }
             for msg in msgs {
-                rm(msg.from(), pending, msg.sequence(), rmsgs.borrow_mut()).await?;
+                rm(
+                    msg.from(),
+                    pending,
+                    publisher,
+                    msg.sequence(),
+                    rmsgs.borrow_mut(),
+                )
+                .await?;
             }
         }
         *cur_tipset.write().await = ts;
     }
+
+    // Local messages, or the messages replacing them, included on chain no longer need to be
+    // persisted
+    if !included.is_empty() {
+        let mut local_msgs = local_msgs.write().await;
+        let (done, remaining): (HashSet<SignedMessage>, HashSet<SignedMessage>) = local_msgs
+            .drain()
+            .partition(|m| included.contains(&(*m.from(), m.sequence())));
+        *local_msgs = remaining;
+        drop(local_msgs);
+        for m in done {
+            api.read().await.delete_local_message(&m)?;
+        }
+    }
+
     for (_, hm) in rmsgs {
         for (_, msg) in hm {
-            if let Err(e) = add_helper(api, bls_sig_cache, pending, msg).await {
+            // Messages of reverted blocks were already accepted, so they are not limited
+            if let Err(e) =
+                add_helper(api, bls_sig_cache, pending, publisher, config, msg, None).await
+            {
                 error!("Failed to readd message from reorg to mpool: {}", e);
             }
         }
@@ -703,6 +993,7 @@ where
 async fn rm(
     from: &Address,
     pending: &RwLock<HashMap<Address, MsgSet>>,
+    publisher: &RwLock<Publisher<MpoolUpdate>>,
     sequence: u64,
     rmsgs: &mut HashMap<Address, HashMap<u64, SignedMessage>>,
 ) -> Result<(), Error> {
@@ -710,9 +1001,9 @@ async fn rm(
         if temp.get_mut(&sequence).is_some() {
             temp.remove(&sequence);
         }
-        remove(from, pending, sequence).await?;
+        remove(from, pending, publisher, sequence).await?;
     } else {
-        remove(from, pending, sequence).await?;
+        remove(from, pending, publisher, sequence).await?;
     }
     Ok(())
 }
@@ -734,6 +1025,7 @@ pub mod test_provider {
     use address::Address;
     use blocks::{BlockHeader, Tipset};
     use cid::Cid;
+    use db::MemoryDB;
     use flo_stream::{MessagePublisher, Publisher, Subscriber};
     use message::{SignedMessage, UnsignedMessage};
 
@@ -741,8 +1033,10 @@ pub mod test_provider {
     pub struct TestApi {
         bmsgs: HashMap<Cid, Vec<SignedMessage>>,
         state_sequence: HashMap<Address, u64>,
+        balances: HashMap<Address, BigInt>,
         tipsets: Vec<Tipset>,
         publisher: Publisher<HeadChange>,
+        local_store: LocalStore<MemoryDB>,
     }
 
     impl Default for TestApi {
@@ -751,8 +1045,10 @@ pub mod test_provider {
             TestApi {
                 bmsgs: HashMap::new(),
                 state_sequence: HashMap::new(),
+                balances: HashMap::new(),
                 tipsets: Vec::new(),
                 publisher: Publisher::new(1),
+                local_store: LocalStore::new(Default::default()),
             }
         }
     }
@@ -763,6 +1059,20 @@ pub mod test_provider {
             self.state_sequence.insert(*addr, sequence);
         }
 
+        /// Create a new TestApi persisting local messages in the given store, to simulate
+        /// restarts of the message pool
+        pub fn with_local_db(local_db: Arc<MemoryDB>) -> Self {
+            Self {
+                local_store: LocalStore::new(local_db),
+                ..Default::default()
+            }
+        }
+
+        /// Set the balance for an Address for TestApi
+        pub fn set_state_balance(&mut self, addr: &Address, balance: BigInt) {
+            self.balances.insert(*addr, balance);
+        }
+
         /// Set the block messages for TestApi
         pub fn set_block_messages(&mut self, h: &BlockHeader, msgs: Vec<SignedMessage>) {
             self.bmsgs.insert(h.cid().clone(), msgs);
@@ -788,18 +1098,30 @@ pub mod test_provider {
             Ok(Cid::default())
         }
 
+        fn put_local_message(&self, msg: &SignedMessage) -> Result<(), Errors> {
+            self.local_store.put(msg)
+        }
+
+        fn delete_local_message(&self, msg: &SignedMessage) -> Result<(), Errors> {
+            self.local_store.delete(msg)
+        }
+
+        fn load_local_messages(&self) -> Result<Vec<SignedMessage>, Errors> {
+            self.local_store.load()
+        }
+
         fn state_get_actor(&self, addr: &Address, _ts: &Tipset) -> Result<ActorState, Errors> {
             let s = self.state_sequence.get(addr);
             let mut sequence = 0;
             if let Some(sq) = s {
                 sequence = *sq;
             }
-            let actor = ActorState::new(
-                Cid::default(),
-                Cid::default(),
-                BigInt::from(9_000_000 as u64),
-                sequence,
-            );
+            let balance = self
+                .balances
+                .get(addr)
+                .cloned()
+                .unwrap_or_else(|| BigInt::from(9_000_000 as u64));
+            let actor = ActorState::new(Cid::default(), Cid::default(), balance, sequence);
             Ok(actor)
         }
 
@@ -867,6 +1189,7 @@ pub mod tests {
     use blocks::{BlockHeader, Ticket, Tipset};
     use cid::Cid;
     use crypto::{election_proof::ElectionProof, SignatureType, VRFProof};
+    use db::MemoryDB;
     use key_management::{MemKeyStore, Wallet};
     use message::{SignedMessage, UnsignedMessage};
     use num_bigint::BigInt;
@@ -892,6 +1215,25 @@ pub mod tests {
         SignedMessage::new_from_parts(umsg, sig).unwrap()
     }
 
+    fn create_smsg_with_premium(
+        from: &Address,
+        wallet: &mut Wallet<MemKeyStore>,
+        sequence: u64,
+        premium: u64,
+    ) -> SignedMessage {
+        let umsg: UnsignedMessage = UnsignedMessage::builder()
+            .to(Address::new_id(1001))
+            .from(*from)
+            .sequence(sequence)
+            .gas_fee_cap(BigInt::from(premium))
+            .gas_premium(BigInt::from(premium))
+            .build()
+            .unwrap();
+        let message_cbor = Cbor::marshal_cbor(&umsg).unwrap();
+        let sig = wallet.sign(&from, message_cbor.as_slice()).unwrap();
+        SignedMessage::new_from_parts(umsg, sig).unwrap()
+    }
+
     fn mock_block(weight: u64, ticket_sequence: u64) -> BlockHeader {
         let addr = Address::new_id(1234561);
         let c =
@@ -956,7 +1298,9 @@ pub mod tests {
         tma.set_state_sequence(&sender, 0);
 
         task::block_on(async move {
-            let mpool = MessagePool::new(tma, "mptest".to_string()).await.unwrap();
+            let mpool = MessagePool::new(tma, "mptest".to_string(), MpoolConfig::default())
+                .await
+                .unwrap();
 
             let mut smsg_vec = Vec::new();
             for i in 0..4 {
@@ -981,12 +1325,17 @@ pub mod tests {
             let bls_sig_cache = mpool.bls_sig_cache.clone();
             let pending = mpool.pending.clone();
             let cur_tipset = mpool.cur_tipset.clone();
+            let publisher = mpool.publisher.clone();
+            let local_msgs = mpool.local_msgs.clone();
 
             head_change(
                 api.as_ref(),
                 bls_sig_cache.as_ref(),
                 pending.as_ref(),
                 cur_tipset.as_ref(),
+                publisher.as_ref(),
+                local_msgs.as_ref(),
+                &MpoolConfig::default(),
                 Vec::new(),
                 vec![Tipset::new(vec![a]).unwrap()],
             )
@@ -1019,7 +1368,9 @@ pub mod tests {
         }
 
         task::block_on(async move {
-            let mpool = MessagePool::new(tma, "mptest".to_string()).await.unwrap();
+            let mpool = MessagePool::new(tma, "mptest".to_string(), MpoolConfig::default())
+                .await
+                .unwrap();
 
             let mut api_temp = mpool.api.write().await;
             api_temp.set_block_messages(&a, vec![smsg_vec[0].clone()]);
@@ -1038,12 +1389,17 @@ pub mod tests {
             let bls_sig_cache = mpool.bls_sig_cache.clone();
             let pending = mpool.pending.clone();
             let cur_tipset = mpool.cur_tipset.clone();
+            let publisher = mpool.publisher.clone();
+            let local_msgs = mpool.local_msgs.clone();
 
             head_change(
                 api.as_ref(),
                 bls_sig_cache.as_ref(),
                 pending.as_ref(),
                 cur_tipset.as_ref(),
+                publisher.as_ref(),
+                local_msgs.as_ref(),
+                &MpoolConfig::default(),
                 Vec::new(),
                 vec![Tipset::new(vec![a]).unwrap()],
             )
@@ -1058,12 +1414,17 @@ pub mod tests {
             let bls_sig_cache = mpool.bls_sig_cache.clone();
             let pending = mpool.pending.clone();
             let cur_tipset = mpool.cur_tipset.clone();
+            let publisher = mpool.publisher.clone();
+            let local_msgs = mpool.local_msgs.clone();
 
             head_change(
                 api.as_ref(),
                 bls_sig_cache.as_ref(),
                 pending.as_ref(),
                 cur_tipset.as_ref(),
+                publisher.as_ref(),
+                local_msgs.as_ref(),
+                &MpoolConfig::default(),
                 Vec::new(),
                 vec![Tipset::new(vec![b.clone()]).unwrap()],
             )
@@ -1079,6 +1440,9 @@ pub mod tests {
                 bls_sig_cache.as_ref(),
                 pending.as_ref(),
                 cur_tipset.as_ref(),
+                publisher.as_ref(),
+                local_msgs.as_ref(),
+                &MpoolConfig::default(),
                 vec![Tipset::new(vec![b]).unwrap()],
                 Vec::new(),
             )
@@ -1103,7 +1467,9 @@ pub mod tests {
         tma.set_state_sequence(&sender, 0);
 
         task::block_on(async move {
-            let mpool = MessagePool::new(tma, "mptest".to_string()).await.unwrap();
+            let mpool = MessagePool::new(tma, "mptest".to_string(), MpoolConfig::default())
+                .await
+                .unwrap();
 
             let mut smsg_vec = Vec::new();
             for i in 0..3 {
@@ -1137,4 +1503,165 @@ pub mod tests {
             assert_eq!(cur_ts, tipset);
         })
     }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let sender = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        task::block_on(async move {
+            let mpool = MessagePool::new(
+                TestApi::default(),
+                "mptest".to_string(),
+                MpoolConfig::default(),
+            )
+            .await
+            .unwrap();
+
+            mpool
+                .add(&create_smsg_with_premium(&sender, &mut wallet, 0, 100))
+                .await
+                .unwrap();
+            // Replacement has to raise the premium by at least 25%
+            assert_eq!(
+                mpool
+                    .add(&create_smsg_with_premium(&sender, &mut wallet, 0, 120))
+                    .await,
+                Err(Error::GasPriceTooLow)
+            );
+            let replacement = create_smsg_with_premium(&sender, &mut wallet, 0, 126);
+            mpool.add(&replacement).await.unwrap();
+
+            let pending = mpool.pending.read().await;
+            assert_eq!(
+                pending.get(&sender).unwrap().msgs.get(&0),
+                Some(&replacement)
+            );
+        })
+    }
+
+    #[test]
+    fn test_pool_limits() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let a = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let b = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let c = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let d = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        let config = MpoolConfig {
+            max_sender_msgs: 2,
+            max_tx_pool_size: 3,
+            ..Default::default()
+        };
+        task::block_on(async move {
+            let mpool = MessagePool::new(TestApi::default(), "mptest".to_string(), config)
+                .await
+                .unwrap();
+
+            mpool
+                .add(&create_smsg_with_premium(&a, &mut wallet, 0, 100))
+                .await
+                .unwrap();
+            mpool
+                .add(&create_smsg_with_premium(&a, &mut wallet, 1, 100))
+                .await
+                .unwrap();
+            assert_eq!(
+                mpool
+                    .add(&create_smsg_with_premium(&a, &mut wallet, 2, 100))
+                    .await,
+                Err(Error::TooManyPendingMessages)
+            );
+            // Replacing a pending message doesn't count against the limit
+            mpool
+                .add(&create_smsg_with_premium(&a, &mut wallet, 1, 200))
+                .await
+                .unwrap();
+
+            // Exceeding the pool size evicts the sender paying the lowest premium
+            mpool
+                .add(&create_smsg_with_premium(&b, &mut wallet, 0, 200))
+                .await
+                .unwrap();
+            mpool
+                .add(&create_smsg_with_premium(&c, &mut wallet, 0, 300))
+                .await
+                .unwrap();
+            let pending = mpool.pending.read().await;
+            assert!(pending.get(&a).is_none());
+            assert!(pending.get(&b).is_some());
+            assert!(pending.get(&c).is_some());
+            drop(pending);
+
+            // A message paying a lower premium than the rest of the full pool is not added
+            mpool
+                .add(&create_smsg_with_premium(&d, &mut wallet, 0, 50))
+                .await
+                .unwrap();
+            assert_eq!(
+                mpool
+                    .add(&create_smsg_with_premium(&d, &mut wallet, 1, 50))
+                    .await,
+                Err(Error::MessagePoolFull)
+            );
+            let pending = mpool.pending.read().await;
+            assert!(pending.get(&d).is_none());
+            assert!(pending.get(&b).is_some());
+            assert!(pending.get(&c).is_some());
+        })
+    }
+
+    #[test]
+    fn test_local_messages_persisted() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let sender = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let target = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let local_db = Arc::new(MemoryDB::default());
+
+        task::block_on(async move {
+            let api = TestApi::with_local_db(local_db.clone());
+            let mpool = MessagePool::new(api, "mptest".to_string(), MpoolConfig::default())
+                .await
+                .unwrap();
+            let msgs: Vec<SignedMessage> = (0..2)
+                .map(|i| create_smsg(&target, &sender, wallet.borrow_mut(), i))
+                .collect();
+            for m in msgs.iter() {
+                mpool.push(m.clone()).await.unwrap();
+            }
+
+            // Local messages are reloaded when the pool is restarted
+            let api = TestApi::with_local_db(local_db.clone());
+            let mpool = MessagePool::new(api, "mptest".to_string(), MpoolConfig::default())
+                .await
+                .unwrap();
+            assert_eq!(mpool.get_sequence(&sender).await.unwrap(), 2);
+
+            // and deleted once included on chain
+            let a = mock_block(1, 1);
+            mpool
+                .api
+                .write()
+                .await
+                .set_block_messages(&a, vec![msgs[0].clone()]);
+            let local_msgs = mpool.local_msgs.clone();
+            head_change(
+                mpool.api.as_ref(),
+                mpool.bls_sig_cache.as_ref(),
+                mpool.pending.as_ref(),
+                mpool.cur_tipset.as_ref(),
+                mpool.publisher.as_ref(),
+                local_msgs.as_ref(),
+                &MpoolConfig::default(),
+                Vec::new(),
+                vec![Tipset::new(vec![a]).unwrap()],
+            )
+            .await
+            .unwrap();
+
+            let stored = LocalStore::new(local_db).load().unwrap();
+            assert_eq!(stored, vec![msgs[1].clone()]);
+            assert_eq!(local_msgs.read().await.len(), 1);
+        })
+    }
 }
@@ -0,0 +1,188 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::errors::Error;
+use super::msgpool::{MsgSet, Provider};
+use address::Address;
+use async_std::sync::{RwLock, Sender};
+use blocks::Tipset;
+use encoding::Cbor;
+use forest_libp2p::{NetworkMessage, Topic};
+use log::debug;
+use message::{Message, SignedMessage};
+use std::collections::HashMap;
+
+/// Messages are republished as long as their fee cap covers this fraction of the base fee, as
+/// the base fee may drop before they are included.
+const BASE_FEE_LOWER_BOUND_FACTOR: u64 = 10;
+
+/// Republishes the pending messages of the local senders over gossipsub, so messages that were
+/// not picked up by miners are not stuck in the pool. Only the messages that can be included
+/// in order from the sequence of the sender are republished, up to `max_msgs` messages.
+pub(crate) async fn republish_pending_messages<T>(
+    api: &RwLock<T>,
+    pending: &RwLock<HashMap<Address, MsgSet>>,
+    cur_tipset: &RwLock<Tipset>,
+    local_addrs: &RwLock<Vec<Address>>,
+    network_sender: &Sender<NetworkMessage>,
+    topic: &Topic,
+    max_msgs: usize,
+) -> Result<usize, Error>
+where
+    T: Provider,
+{
+    let ts = cur_tipset.read().await.clone();
+    let base_fee = api.read().await.chain_compute_base_fee(&ts)?;
+    let lower_bound = base_fee / BASE_FEE_LOWER_BOUND_FACTOR;
+    let local_addrs = local_addrs.read().await.clone();
+
+    let mut msgs: Vec<SignedMessage> = Vec::new();
+    let pending = pending.read().await;
+    for addr in local_addrs.iter() {
+        let mset = match pending.get(addr) {
+            Some(mset) => mset,
+            None => continue,
+        };
+        let mut sequence = api.read().await.state_get_actor(addr, &ts)?.sequence;
+        // Messages after a gap, or after a message that can't cover the base fee, can't be
+        // included before it
+        while let Some(msg) = mset.msgs.get(&sequence) {
+            if msgs.len() >= max_msgs || msg.gas_fee_cap() < &lower_bound {
+                break;
+            }
+            msgs.push(msg.clone());
+            sequence += 1;
+        }
+    }
+    drop(pending);
+
+    for msg in msgs.iter() {
+        network_sender
+            .send(NetworkMessage::PubsubMessage {
+                topic: topic.clone(),
+                message: msg.marshal_cbor()?,
+            })
+            .await;
+    }
+    debug!("Republished {} local messages", msgs.len());
+    Ok(msgs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_provider::TestApi;
+    use crate::{MessagePool, MpoolConfig};
+    use async_std::future;
+    use async_std::sync::channel;
+    use async_std::task;
+    use crypto::SignatureType;
+    use key_management::{MemKeyStore, Wallet};
+    use message::UnsignedMessage;
+    use num_bigint::BigInt;
+    use std::time::Duration;
+
+    fn create_smsg(
+        from: &Address,
+        wallet: &mut Wallet<MemKeyStore>,
+        sequence: u64,
+        fee_cap: u64,
+    ) -> SignedMessage {
+        let umsg = UnsignedMessage::builder()
+            .to(Address::new_id(1001))
+            .from(*from)
+            .sequence(sequence)
+            .gas_limit(1000)
+            .gas_fee_cap(BigInt::from(fee_cap))
+            .build()
+            .unwrap();
+        let sig = wallet.sign(from, &umsg.marshal_cbor().unwrap()).unwrap();
+        SignedMessage::new_from_parts(umsg, sig).unwrap()
+    }
+
+    #[test]
+    fn republish_local_chains() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let a = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let b = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let remote = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        task::block_on(async move {
+            // No republish task, so only the messages republished below reach the receiver
+            let mpool = MessagePool::new(
+                TestApi::default(),
+                "mptest".to_owned(),
+                MpoolConfig::default(),
+            )
+            .await
+            .unwrap();
+            let (network_send, network_rx) = channel(50);
+
+            let chain = vec![
+                create_smsg(&a, &mut wallet, 0, 100),
+                create_smsg(&a, &mut wallet, 1, 100),
+            ];
+            for m in chain.iter() {
+                mpool.push(m.clone()).await.unwrap();
+            }
+            // Message after a gap in sequence can't be included yet
+            mpool
+                .push(create_smsg(&a, &mut wallet, 3, 100))
+                .await
+                .unwrap();
+            // Fee cap far below the base fee of 100
+            mpool
+                .push(create_smsg(&b, &mut wallet, 0, 5))
+                .await
+                .unwrap();
+            // Messages of other nodes are left to them
+            mpool
+                .add(&create_smsg(&remote, &mut wallet, 0, 100))
+                .await
+                .unwrap();
+
+            let topic = Topic::new("/fil/msgs/mptest".to_owned());
+            let count = republish_pending_messages(
+                mpool.api.as_ref(),
+                mpool.pending.as_ref(),
+                mpool.cur_tipset.as_ref(),
+                mpool.local_addrs.as_ref(),
+                &network_send,
+                &topic,
+                10,
+            )
+            .await
+            .unwrap();
+            assert_eq!(count, 2);
+
+            for m in chain {
+                match network_rx.recv().await.unwrap() {
+                    NetworkMessage::PubsubMessage { message, .. } => {
+                        assert_eq!(SignedMessage::unmarshal_cbor(&message).unwrap(), m)
+                    }
+                    _ => panic!("expected pubsub message"),
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn republish_stops_with_pool() {
+        task::block_on(async move {
+            let (network_send, network_rx) = channel(50);
+            let mpool = MessagePool::new_with_republish(
+                TestApi::default(),
+                "mptest".to_owned(),
+                network_send,
+                MpoolConfig::default(),
+            )
+            .await
+            .unwrap();
+
+            // The republish task drops its network sender once it stops
+            drop(mpool);
+            let closed = future::timeout(Duration::from_secs(5), network_rx.recv()).await;
+            assert!(closed.unwrap().is_err());
+        })
+    }
+}
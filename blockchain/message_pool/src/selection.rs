@@ -0,0 +1,322 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::block_prob::block_probabilities;
+use super::errors::Error;
+use super::msgpool::{MessagePool, MsgSet, Provider};
+use address::Address;
+use blocks::Tipset;
+use chain::BLOCK_GAS_LIMIT;
+use log::warn;
+use message::{Message, SignedMessage};
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// Link in the chain of pending messages of a sender. The messages of a link are included in a
+/// block together and only after the messages of all previous links of the sender.
+#[derive(Clone, Debug)]
+struct MsgChainLink {
+    from: Address,
+    /// Position of the link in the sender's chain
+    index: usize,
+    msgs: Vec<SignedMessage>,
+    gas_reward: BigInt,
+    gas_limit: i64,
+    /// Gas reward per unit of gas
+    gas_perf: f64,
+    /// Gas performance weighted by the probability of the link being included
+    eff_perf: f64,
+}
+
+impl MsgChainLink {
+    fn new(from: Address, msg: SignedMessage, gas_reward: BigInt) -> Self {
+        let mut link = Self {
+            from,
+            index: 0,
+            gas_limit: msg.gas_limit(),
+            msgs: vec![msg],
+            gas_reward,
+            gas_perf: 0.0,
+            eff_perf: 0.0,
+        };
+        link.gas_perf = link.compute_gas_perf();
+        link
+    }
+
+    fn compute_gas_perf(&self) -> f64 {
+        if self.gas_limit == 0 {
+            return 0.0;
+        }
+        self.gas_reward.to_f64().unwrap_or(0.0) / self.gas_limit as f64
+    }
+
+    /// Appends the messages of the next link of the chain to this one.
+    fn merge(&mut self, next: MsgChainLink) {
+        self.msgs.extend(next.msgs);
+        self.gas_reward += next.gas_reward;
+        self.gas_limit += next.gas_limit;
+        self.gas_perf = self.compute_gas_perf();
+    }
+}
+
+/// Returns the reward the block producer gets for including the message, given the base fee.
+fn gas_reward(msg: &SignedMessage, base_fee: &BigInt) -> BigInt {
+    let max_premium = msg.gas_fee_cap() - base_fee;
+    let premium = if &max_premium < msg.gas_premium() {
+        max_premium
+    } else {
+        msg.gas_premium().clone()
+    };
+    premium * msg.gas_limit()
+}
+
+fn by_perf_desc(a: f64, b: f64) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+impl<T> MessagePool<T>
+where
+    T: Provider + std::marker::Send + std::marker::Sync + 'static,
+{
+    /// Selects the pending messages to include in a block mined on top of the tipset. The
+    /// ticket quality, between 0 and 1, is used to estimate the position of the block in the
+    /// next tipset, which determines how likely the block's messages are to be executed before
+    /// those of other blocks.
+    pub async fn select_messages(&self, ts: &Tipset, tq: f64) -> Result<Vec<SignedMessage>, Error> {
+        let base_fee = self.api.read().await.chain_compute_base_fee(ts)?;
+        let pending = self.pending.read().await.clone();
+
+        let mut chains = Vec::new();
+        for (from, mset) in pending.iter() {
+            match self.create_message_chain(from, mset, &base_fee, ts).await {
+                Ok(chain) => chains.extend(chain),
+                Err(e) => warn!("Failed to create message chain for {}: {}", from, e),
+            }
+        }
+        if chains.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Sorting is stable and the links of a chain never increase in performance, so the links
+        // of every sender remain in order
+        chains.sort_by(|a, b| by_perf_desc(a.gas_perf, b.gas_perf));
+
+        // Weight the performance of the links by the probability of their messages being
+        // executed, which is lower the further back in the tipset they will likely end up
+        let bp = block_probabilities(tq.max(0.0).min(1.0));
+        let mut gas_used = 0;
+        let mut parent_perf: HashMap<Address, f64> = HashMap::new();
+        for link in chains.iter_mut() {
+            let partition = (gas_used / BLOCK_GAS_LIMIT) as usize;
+            link.eff_perf = link.gas_perf * bp.get(partition).copied().unwrap_or(0.0);
+            // A link depends on its parent, so it can't be worth more than the parent
+            if let Some(perf) = parent_perf.get(&link.from) {
+                link.eff_perf = link.eff_perf.min(*perf);
+            }
+            parent_perf.insert(link.from, link.eff_perf);
+            gas_used += link.gas_limit;
+        }
+        chains.sort_by(|a, b| by_perf_desc(a.eff_perf, b.eff_perf));
+
+        let mut gas_limit = BLOCK_GAS_LIMIT;
+        let mut selected = Vec::new();
+        let mut next_link: HashMap<Address, usize> = HashMap::new();
+        let mut skipped: HashSet<Address> = HashSet::new();
+        for link in chains {
+            if skipped.contains(&link.from) {
+                continue;
+            }
+            // Links can only be included after the previous links of the sender
+            if next_link.get(&link.from).copied().unwrap_or(0) != link.index {
+                skipped.insert(link.from);
+                continue;
+            }
+            if link.gas_limit <= gas_limit {
+                gas_limit -= link.gas_limit;
+                next_link.insert(link.from, link.index + 1);
+                selected.extend(link.msgs);
+                continue;
+            }
+
+            // The link doesn't fit in the block, so include as many of its messages as fit and
+            // skip the remaining messages of the sender, which depend on the others
+            for msg in link.msgs {
+                if msg.gas_limit() > gas_limit {
+                    break;
+                }
+                gas_limit -= msg.gas_limit();
+                selected.push(msg);
+            }
+            skipped.insert(link.from);
+        }
+
+        Ok(selected)
+    }
+
+    /// Creates the chain of pending messages of a sender that can be included on top of the
+    /// tipset. The chain stops at the first gap in sequence, at the first message the sender
+    /// can't pay for and at the first message that doesn't cover the base fee.
+    async fn create_message_chain(
+        &self,
+        from: &Address,
+        mset: &MsgSet,
+        base_fee: &BigInt,
+        ts: &Tipset,
+    ) -> Result<Vec<MsgChainLink>, Error> {
+        let mut sequence = self.get_state_sequence(from, ts).await?;
+        let mut balance = self.get_state_balance(from, ts).await?;
+
+        let mut msgs: Vec<&SignedMessage> = mset
+            .msgs
+            .values()
+            .filter(|m| m.sequence() >= sequence)
+            .collect();
+        msgs.sort_by_key(|m| m.sequence());
+
+        let mut chain: Vec<MsgChainLink> = Vec::new();
+        for msg in msgs {
+            if msg.sequence() != sequence || msg.gas_limit() > BLOCK_GAS_LIMIT {
+                break;
+            }
+            let required = msg.required_funds();
+            if balance < required {
+                break;
+            }
+            let reward = gas_reward(msg, base_fee);
+            if reward < BigInt::zero() {
+                break;
+            }
+            sequence += 1;
+            balance -= required;
+
+            chain.push(MsgChainLink::new(*from, msg.clone(), reward));
+            // Merge links that perform better than their parent into it, so the performance of
+            // the links never increases along the chain
+            while chain.len() > 1
+                && chain[chain.len() - 2].gas_perf < chain[chain.len() - 1].gas_perf
+            {
+                let last = chain.pop().unwrap();
+                chain.last_mut().unwrap().merge(last);
+            }
+        }
+
+        for (i, link) in chain.iter_mut().enumerate() {
+            link.index = i;
+        }
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_provider::TestApi;
+    use crate::MpoolConfig;
+    use async_std::task;
+    use crypto::SignatureType;
+    use encoding::Cbor;
+    use key_management::{MemKeyStore, Wallet};
+    use message::UnsignedMessage;
+
+    fn create_smsg(
+        from: &Address,
+        wallet: &mut Wallet<MemKeyStore>,
+        sequence: u64,
+        gas_limit: i64,
+        gas_premium: u64,
+    ) -> SignedMessage {
+        let umsg = UnsignedMessage::builder()
+            .to(Address::new_id(1001))
+            .from(*from)
+            .sequence(sequence)
+            .gas_limit(gas_limit)
+            .gas_fee_cap(BigInt::from(gas_premium + 100))
+            .gas_premium(BigInt::from(gas_premium))
+            .build()
+            .unwrap();
+        let sig = wallet.sign(from, &umsg.marshal_cbor().unwrap()).unwrap();
+        SignedMessage::new_from_parts(umsg, sig).unwrap()
+    }
+
+    #[test]
+    fn select_by_premium_in_sequence_order() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let a = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let b = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        task::block_on(async move {
+            let mpool = MessagePool::new(
+                TestApi::default(),
+                "mptest".to_owned(),
+                MpoolConfig::default(),
+            )
+            .await
+            .unwrap();
+            let ts = mpool.cur_tipset.read().await.clone();
+
+            // Sender b pays higher premiums, but its second message depends on a low paying one
+            let msgs = vec![
+                create_smsg(&a, &mut wallet, 0, 10_000, 20),
+                create_smsg(&a, &mut wallet, 1, 10_000, 20),
+                create_smsg(&b, &mut wallet, 0, 10_000, 10),
+                create_smsg(&b, &mut wallet, 1, 10_000, 50),
+            ];
+            for m in &msgs {
+                mpool.add(m).await.unwrap();
+            }
+            // Message with a gap in sequence can't be included
+            mpool
+                .add(&create_smsg(&a, &mut wallet, 3, 10_000, 100))
+                .await
+                .unwrap();
+
+            let selected = mpool.select_messages(&ts, 1.0).await.unwrap();
+            let order: Vec<(Address, u64)> =
+                selected.iter().map(|m| (*m.from(), m.sequence())).collect();
+            assert_eq!(order, vec![(b, 0), (b, 1), (a, 0), (a, 1)]);
+        })
+    }
+
+    #[test]
+    fn select_respects_block_gas_limit() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let a = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let b = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        task::block_on(async move {
+            let mut api = TestApi::default();
+            api.set_state_balance(&a, BigInt::from(10u64.pow(15)));
+            api.set_state_balance(&b, BigInt::from(10u64.pow(15)));
+            let mpool = MessagePool::new(api, "mptest".to_owned(), MpoolConfig::default())
+                .await
+                .unwrap();
+            let ts = mpool.cur_tipset.read().await.clone();
+
+            // Both messages of a fill the block, so the message of b doesn't fit anymore. They
+            // exceed the gas limit accepted for a single message, so they are pending directly.
+            let half = BLOCK_GAS_LIMIT / 2;
+            let msgs = vec![
+                create_smsg(&a, &mut wallet, 0, half, 10),
+                create_smsg(&a, &mut wallet, 1, half, 10),
+                create_smsg(&b, &mut wallet, 0, half + 1, 5),
+            ];
+            let mut pending = mpool.pending.write().await;
+            for m in msgs {
+                pending
+                    .entry(*m.from())
+                    .or_insert_with(MsgSet::new)
+                    .add(m, 0, None)
+                    .unwrap();
+            }
+            drop(pending);
+
+            let selected = mpool.select_messages(&ts, 1.0).await.unwrap();
+            let gas: i64 = selected.iter().map(|m| m.gas_limit()).sum();
+            assert!(gas <= BLOCK_GAS_LIMIT);
+            assert_eq!(selected.len(), 2);
+            assert!(selected.iter().all(|m| m.from() == &a));
+        })
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::stringify_rpc_err;
+use rpc::auth::{generate_jwt_secret, ADMIN_PERMISSIONS, JWT_SECRET_LEN};
+use rpc_client::{auth_new, new_client, ApiInfo, API_INFO_KEY};
+use std::fs;
+use std::io;
+use std::path::Path;
+use structopt::StructOpt;
+
+/// Path of the secret used to sign API tokens, relative to the data directory
+const JWT_SECRET_PATH: &str = "/auth/jwt_secret";
+/// Path of the admin API token, relative to the data directory
+pub(super) const TOKEN_PATH: &str = "/token";
+
+#[derive(Debug, StructOpt)]
+pub enum AuthCommands {
+    /// Creates a new API token with the given permission level
+    #[structopt(about = "Create a new API token")]
+    CreateToken {
+        #[structopt(
+            short,
+            long,
+            help = "Permission level of the token: read, write, sign or admin"
+        )]
+        perm: String,
+    },
+
+    /// Prints the API info, with a new token of the given permission level, to be used
+    /// as the `FULLNODE_API_INFO` environment variable
+    #[structopt(about = "Get API info with a new token")]
+    ApiInfo {
+        #[structopt(
+            short,
+            long,
+            help = "Permission level of the token: read, write, sign or admin"
+        )]
+        perm: String,
+    },
+}
+
+impl AuthCommands {
+    pub async fn run(&self, api: &ApiInfo) {
+        let mut client = new_client(api);
+
+        match self {
+            Self::CreateToken { perm } => {
+                let token = auth_new(&mut client, perms_up_to(perm).unwrap())
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}", token);
+            }
+            Self::ApiInfo { perm } => {
+                let token = auth_new(&mut client, perms_up_to(perm).unwrap())
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}={}:{}", API_INFO_KEY, token, api.url);
+            }
+        }
+    }
+}
+
+/// Returns all permissions up to and including the given permission level, as each level
+/// implies the ones below it.
+fn perms_up_to(perm: &str) -> Result<Vec<String>, String> {
+    let idx = ADMIN_PERMISSIONS
+        .iter()
+        .position(|p| *p == perm)
+        .ok_or_else(|| format!("Invalid permission {}", perm))?;
+    Ok(ADMIN_PERMISSIONS[..=idx]
+        .iter()
+        .map(|p| p.to_string())
+        .collect())
+}
+
+/// Loads the secret used to sign API tokens from the data directory, generating and saving
+/// a new one if none exists.
+pub(crate) fn load_or_generate_jwt_secret(data_dir: &str) -> Result<Vec<u8>, io::Error> {
+    let path = format!("{}{}", data_dir, JWT_SECRET_PATH);
+    match fs::read(&path) {
+        Ok(secret) if secret.len() == JWT_SECRET_LEN => Ok(secret),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid JWT secret at {}", path),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let secret = generate_jwt_secret();
+            write_private_file(&path, &secret)?;
+            Ok(secret)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the admin API token to the data directory, for the CLI to use.
+pub(crate) fn write_token(data_dir: &str, token: &str) -> Result<(), io::Error> {
+    write_private_file(&format!("{}{}", data_dir, TOKEN_PATH), token.as_bytes())
+}
+
+/// Writes a file only readable by the current user.
+fn write_private_file(path: &str, contents: &[u8]) -> Result<(), io::Error> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perm_levels() {
+        assert_eq!(perms_up_to("read").unwrap(), vec!["read"]);
+        assert_eq!(perms_up_to("sign").unwrap(), vec!["read", "write", "sign"]);
+        assert_eq!(perms_up_to("admin").unwrap().len(), ADMIN_PERMISSIONS.len());
+        assert!(perms_up_to("root").is_err());
+    }
+}
@@ -3,7 +3,7 @@
 
 use super::stringify_rpc_err;
 use cid::Cid;
-use rpc_client::{block, genesis, head, messages, new_client, read_obj};
+use rpc_client::{block, genesis, head, messages, new_client, read_obj, ApiInfo};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -41,12 +41,11 @@ pub enum ChainCommands {
 }
 
 impl ChainCommands {
-    pub async fn run(&self) {
-        // TODO handle cli config
+    pub async fn run(&self, api: &ApiInfo) {
         match self {
             Self::Block { cid } => {
                 let cid: Cid = cid.parse().unwrap();
-                let mut client = new_client();
+                let mut client = new_client(api);
 
                 let blk = block(&mut client, cid)
                     .await
@@ -55,7 +54,7 @@ impl ChainCommands {
                 println!("{}", serde_json::to_string_pretty(&blk).unwrap());
             }
             Self::Genesis => {
-                let mut client = new_client();
+                let mut client = new_client(api);
 
                 let gen = genesis(&mut client)
                     .await
@@ -64,7 +63,7 @@ impl ChainCommands {
                 println!("{}", serde_json::to_string_pretty(&gen).unwrap());
             }
             Self::Head => {
-                let mut client = new_client();
+                let mut client = new_client(api);
 
                 let canonical = head(&mut client).await.map_err(stringify_rpc_err).unwrap();
                 println!(
@@ -74,7 +73,7 @@ impl ChainCommands {
             }
             Self::Message { cid } => {
                 let cid: Cid = cid.parse().unwrap();
-                let mut client = new_client();
+                let mut client = new_client(api);
 
                 let msg = messages(&mut client, cid)
                     .await
@@ -84,7 +83,7 @@ impl ChainCommands {
             }
             Self::ReadObj { cid } => {
                 let cid: Cid = cid.parse().unwrap();
-                let mut client = new_client();
+                let mut client = new_client(api);
 
                 let obj = read_obj(&mut client, cid)
                     .await
@@ -1,19 +1,67 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use beacon::DrandPublic;
+use beacon::{DrandConfig, DrandPoint, DrandPublic};
+#[cfg(feature = "rocksdb")]
+use db::RocksDbConfig;
 use forest_libp2p::Libp2pConfig;
+use ipld_blockstore::BlockCacheConfig;
+use message_pool::MpoolConfig;
 use serde::Deserialize;
 use utils::get_home_dir;
+
+/// Persistent datastore of the node
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum DbBackend {
+    /// Only available when built with the `rocksdb` feature, enabled by default
+    #[serde(rename = "rocksdb")]
+    RocksDb,
+    /// Only available when built with the `sled` feature
+    #[serde(rename = "sled")]
+    Sled,
+}
+
+impl Default for DbBackend {
+    fn default() -> Self {
+        if cfg!(feature = "rocksdb") {
+            Self::RocksDb
+        } else {
+            Self::Sled
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(default)]
 pub struct Config {
     pub network: Libp2pConfig,
     pub data_dir: String,
     pub genesis_file: Option<String>,
+    pub snapshot_path: Option<String>,
     pub drand_public: DrandPublic,
+    /// Endpoints of the drand network and the timeout of their requests
+    pub drand: DrandConfig,
+    /// Drand networks the chain switches to at network upgrades
+    pub drand_schedule: Vec<DrandPoint>,
+    /// Run a local devnet, using a deterministic mock beacon instead of drand so the node does
+    /// not need to reach the drand network
+    pub devnet: bool,
     pub enable_rpc: bool,
     pub rpc_port: String,
+    /// Port of the WebSocket RPC endpoint, which supports subscriptions
+    pub rpc_ws_port: String,
+    /// Encrypt the keystore with a passphrase, read from the `FOREST_KEYSTORE_PHRASE`
+    /// environment variable.
+    pub encrypt_keystore: bool,
+    /// Limits and replace-by-fee rules of the message pool
+    pub mpool: MpoolConfig,
+    /// Datastore backend, `rocksdb` or `sled`
+    pub db_backend: DbBackend,
+    /// Tuning options of the database, when RocksDb is the backend
+    #[cfg(feature = "rocksdb")]
+    pub rocks_db: RocksDbConfig,
+    /// Cache of the blocks read from the database
+    pub block_cache: BlockCacheConfig,
 }
 
 impl Default for Config {
@@ -22,9 +70,20 @@ impl Default for Config {
             network: Libp2pConfig::default(),
             data_dir: get_home_dir() + "/.forest",
             genesis_file: None,
+            snapshot_path: None,
             drand_public: DrandPublic{coefficient: hex::decode("868f005eb8e6e4ca0a47c8a77ceaa5309a47978a7c71bc5cce96366b5d7a569937c529eeda66c7293784a9402801af31").unwrap()},
+            drand: DrandConfig::default(),
+            drand_schedule: Vec::new(),
+            devnet: false,
             enable_rpc : true,
             rpc_port: "1234".to_string(),
+            rpc_ws_port: "1235".to_string(),
+            encrypt_keystore: false,
+            mpool: MpoolConfig::default(),
+            db_backend: DbBackend::default(),
+            #[cfg(feature = "rocksdb")]
+            rocks_db: RocksDbConfig::default(),
+            block_cache: BlockCacheConfig::default(),
         }
     }
 }
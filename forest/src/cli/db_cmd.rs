@@ -0,0 +1,72 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::{Config, DbBackend};
+use chain::ChainStore;
+use clock::ChainEpoch;
+use db::IterableStore;
+#[cfg(feature = "rocksdb")]
+use db::RocksDb;
+#[cfg(feature = "sled")]
+use db::SledDb;
+use ipld_blockstore::BlockStore;
+use std::sync::Arc;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum DbCommands {
+    /// Deletes the blocks of the local database that are not reachable from the head of the
+    /// chain. The daemon must not be running, as the database is opened directly.
+    #[structopt(about = "Delete unreachable blocks from the database")]
+    Gc {
+        #[structopt(
+            long,
+            default_value = "900",
+            help = "Number of epochs from the head for which state trees, messages and receipts are kept"
+        )]
+        recent_roots: ChainEpoch,
+    },
+}
+
+impl DbCommands {
+    pub async fn run(&self, config: &Config) {
+        match self {
+            Self::Gc { recent_roots } => {
+                let db_path = format!("{}/db", config.data_dir);
+                match config.db_backend {
+                    #[cfg(feature = "rocksdb")]
+                    DbBackend::RocksDb => {
+                        let mut db = RocksDb::with_config(db_path, config.rocks_db.clone());
+                        db.open().unwrap();
+                        collect_garbage(db, *recent_roots);
+                    }
+                    #[cfg(not(feature = "rocksdb"))]
+                    DbBackend::RocksDb => panic!("Forest was built without the rocksdb feature"),
+                    #[cfg(feature = "sled")]
+                    DbBackend::Sled => {
+                        let mut db = SledDb::new(db_path);
+                        db.open().unwrap();
+                        collect_garbage(db, *recent_roots);
+                    }
+                    #[cfg(not(feature = "sled"))]
+                    DbBackend::Sled => panic!("Forest was built without the sled feature"),
+                }
+            }
+        }
+    }
+}
+
+fn collect_garbage<DB>(db: DB, recent_roots: ChainEpoch)
+where
+    DB: BlockStore + IterableStore,
+{
+    let chain_store = ChainStore::new(Arc::new(db));
+    let head = chain_store
+        .heaviest_tipset()
+        .expect("No chain head found in the database");
+    let stats = chain_store.collect_garbage(&head, recent_roots).unwrap();
+    println!(
+        "Kept {} reachable blocks, deleted {} unreachable blocks",
+        stats.marked, stats.swept
+    );
+}
@@ -1,13 +1,12 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use blocks::BlockHeader;
-use blocks::Tipset;
-use chain::ChainStore;
+use blocks::{BlockHeader, Tipset, TipsetKeys};
+use chain::{tipset_by_height, tipset_from_keys, weight, ChainStore};
 use cid::Cid;
 use forest_car::load_car;
 use ipld_blockstore::BlockStore;
-use log::{debug, info};
+use log::{debug, info, warn};
 use state_manager::StateManager;
 use std::error::Error as StdError;
 use std::fs::File;
@@ -51,6 +50,60 @@ where
     Ok((Tipset::new(vec![genesis])?, network_name))
 }
 
+/// Imports a chain snapshot CAR file into the blockstore and sets the snapshot's root tipset
+/// as the heaviest, so that syncing can resume from that point instead of from genesis.
+pub async fn import_chain<BS>(
+    snapshot_path: &str,
+    chain_store: &mut ChainStore<BS>,
+) -> Result<(), Box<dyn StdError>>
+where
+    BS: BlockStore,
+{
+    info!("Importing chain from snapshot: {}", snapshot_path);
+    let file = File::open(snapshot_path)?;
+    let reader = BufReader::new(file);
+    let roots = load_car(chain_store.blockstore(), reader)?;
+
+    // Constructing the tipset checks that all root blocks share epoch and parents
+    let ts = tipset_from_keys(chain_store.blockstore(), &TipsetKeys::new(roots))?;
+    if !chain_store.blockstore().exists(ts.parent_state().key())? {
+        return Err(format!(
+            "Snapshot is missing the parent state root {} of its root tipset",
+            ts.parent_state()
+        )
+        .into());
+    }
+
+    // Snapshot must be built on top of the genesis the node was initialized with
+    let snapshot_genesis = tipset_by_height(chain_store.blockstore(), 0, ts.clone(), true)?;
+    let genesis = chain_store
+        .genesis()?
+        .ok_or_else(|| "Genesis must be initialized before importing a snapshot".to_owned())?;
+    if snapshot_genesis.blocks()[0] != genesis {
+        return Err("Snapshot genesis does not match the genesis of the node".into());
+    }
+
+    if let Some(heaviest) = chain_store.heaviest_tipset() {
+        let snapshot_weight = weight(chain_store.blockstore(), &ts)?;
+        if weight(chain_store.blockstore(), &heaviest)? >= snapshot_weight {
+            warn!(
+                "Heaviest tipset at epoch {} is heavier than snapshot at epoch {}, not updating head",
+                heaviest.epoch(),
+                ts.epoch()
+            );
+            return Ok(());
+        }
+    }
+
+    info!(
+        "Imported snapshot, setting head to epoch {}: {:?}",
+        ts.epoch(),
+        ts.cids()
+    );
+    chain_store.set_heaviest_tipset(Arc::new(ts)).await?;
+    Ok(())
+}
+
 fn process_car<R, BS>(
     reader: BufReader<R>,
     chain_store: &mut ChainStore<BS>,
@@ -86,3 +139,89 @@ where
         Ok(genesis_block)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actor::{power, ActorState, POWER_ACTOR_CODE_ID, STORAGE_POWER_ACTOR_ADDR};
+    use address::Address;
+    use blocks::TipsetKeys;
+    use cid::multihash::{Blake2b256, Identity};
+    use db::{MemoryDB, Store};
+    use state_tree::StateTree;
+    use std::env::temp_dir;
+
+    /// State with power in the network, so the weight of tipsets built on it can be computed
+    fn powered_state(db: &MemoryDB) -> Cid {
+        let empty_map = StateTree::new(db).flush().unwrap();
+        let mut power_state = power::State::new(empty_map.clone(), empty_map);
+        power_state.total_quality_adj_power = 1.into();
+        let power_head = db.put(&power_state, Blake2b256).unwrap();
+
+        let mut tree = StateTree::new(db);
+        let power_actor = ActorState::new(
+            POWER_ACTOR_CODE_ID.clone(),
+            power_head,
+            Default::default(),
+            0,
+        );
+        tree.set_actor(&STORAGE_POWER_ACTOR_ADDR, power_actor)
+            .unwrap();
+        tree.flush().unwrap()
+    }
+
+    fn header(epoch: i64, weight: u64, parents: TipsetKeys, state_root: Cid) -> BlockHeader {
+        BlockHeader::builder()
+            .epoch(epoch)
+            .weight(weight.into())
+            .parents(parents)
+            .messages(Cid::new_from_cbor(&[], Identity))
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(state_root)
+            .miner_address(Address::new_id(0))
+            .build_and_validate()
+            .unwrap()
+    }
+
+    #[async_std::test]
+    async fn import_exported_chain() {
+        // Chain of the genesis and a tipset on top of it, exported to a snapshot
+        let src_db = MemoryDB::default();
+        let state_root = powered_state(&src_db);
+        let gen = header(0, 0, TipsetKeys::default(), state_root.clone());
+        let head = header(1, 10, TipsetKeys::new(vec![gen.cid().clone()]), state_root);
+        let head_ts = Tipset::new(vec![head]).unwrap();
+        let mut src = ChainStore::new(Arc::new(src_db));
+        src.set_genesis(gen.clone()).unwrap();
+        src.put_tipset(&head_ts).await.unwrap();
+
+        let path = format!(
+            "{}/forest_snapshot_{}.car",
+            temp_dir().display(),
+            uuid::Uuid::new_v4()
+        );
+        src.export(&head_ts, 1, File::create(&path).unwrap())
+            .unwrap();
+
+        // Node initialized with only the genesis, as done with the genesis file
+        let mut cs = ChainStore::new(Arc::new(MemoryDB::default()));
+        cs.set_genesis(gen.clone()).unwrap();
+        cs.set_heaviest_tipset(Arc::new(Tipset::new(vec![gen]).unwrap()))
+            .await
+            .unwrap();
+
+        let res = import_chain(&path, &mut cs).await;
+        std::fs::remove_file(&path).unwrap();
+        res.unwrap();
+
+        assert_eq!(cs.heaviest_tipset().unwrap().as_ref(), &head_ts);
+        assert_eq!(
+            tipset_from_keys(cs.blockstore(), head_ts.key()).unwrap(),
+            head_ts
+        );
+        assert!(cs
+            .blockstore()
+            .exists(head_ts.parent_state().key())
+            .unwrap());
+    }
+}
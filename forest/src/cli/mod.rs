@@ -1,19 +1,26 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+mod auth_cmd;
 mod chain_cmd;
 mod config;
+mod db_cmd;
 mod fetch_params_cmd;
 mod genesis;
 mod genesis_cmd;
+mod wallet_cmd;
 
+pub(super) use self::auth_cmd::{load_or_generate_jwt_secret, write_token, AuthCommands};
 pub(super) use self::chain_cmd::ChainCommands;
-pub use self::config::Config;
+pub use self::config::{Config, DbBackend};
+pub(super) use self::db_cmd::DbCommands;
 pub(super) use self::fetch_params_cmd::FetchCommands;
-pub(super) use self::genesis::initialize_genesis;
+pub(super) use self::genesis::{import_chain, initialize_genesis};
 pub(super) use self::genesis_cmd::GenesisCommands;
+pub(super) use self::wallet_cmd::WalletCommands;
 
 use jsonrpc_v2::Error as JsonRpcError;
+use rpc_client::ApiInfo;
 use std::cell::RefCell;
 use std::io;
 use std::process;
@@ -33,6 +40,11 @@ use utils::{read_file_to_string, read_toml};
 pub struct CLI {
     #[structopt(flatten)]
     pub daemon_opts: DaemonOpts,
+    #[structopt(
+        long,
+        help = "RPC API of the node to connect to, formatted as [<token>:]<multiaddr or url>"
+    )]
+    pub api: Option<String>,
     #[structopt(subcommand)]
     pub cmd: Option<Subcommand>,
 }
@@ -52,6 +64,15 @@ pub enum Subcommand {
 
     #[structopt(name = "genesis", about = "Work with blockchain genesis")]
     Genesis(GenesisCommands),
+
+    #[structopt(name = "wallet", about = "Manage wallet")]
+    Wallet(WalletCommands),
+
+    #[structopt(name = "auth", about = "Manage RPC API tokens")]
+    Auth(AuthCommands),
+
+    #[structopt(name = "db", about = "Manage the local database")]
+    Db(DbCommands),
 }
 
 /// Daemon process command line options.
@@ -61,6 +82,11 @@ pub struct DaemonOpts {
     pub config: Option<String>,
     #[structopt(short, long, help = "The genesis CAR file")]
     pub genesis: Option<String>,
+    #[structopt(
+        long,
+        help = "Import a chain snapshot CAR file before starting the daemon"
+    )]
+    pub import_snapshot: Option<String>,
     #[structopt(short, long, help = "Allow rpc to be active or not")]
     pub rpc: Option<bool>,
     #[structopt(short, long, help = "The port used for communication")]
@@ -69,6 +95,11 @@ pub struct DaemonOpts {
     pub kademlia: Option<bool>,
     #[structopt(short, long, help = "Allow MDNS (default = true)")]
     pub mdns: Option<bool>,
+    #[structopt(
+        long,
+        help = "Run a local devnet with a mock beacon instead of drand, for offline networks"
+    )]
+    pub devnet: bool,
 }
 
 impl DaemonOpts {
@@ -85,6 +116,9 @@ impl DaemonOpts {
         if let Some(genesis_file) = &self.genesis {
             cfg.genesis_file = Some(genesis_file.to_owned());
         }
+        if let Some(snapshot_path) = &self.import_snapshot {
+            cfg.snapshot_path = Some(snapshot_path.to_owned());
+        }
         if self.rpc.unwrap_or(cfg.enable_rpc) {
             cfg.enable_rpc = true;
             cfg.rpc_port = self.port.to_owned().unwrap_or(cfg.rpc_port);
@@ -94,6 +128,20 @@ impl DaemonOpts {
 
         cfg.network.kademlia = self.kademlia.unwrap_or(cfg.network.kademlia);
         cfg.network.mdns = self.mdns.unwrap_or(cfg.network.mdns);
+        cfg.devnet |= self.devnet;
+
+        if cfg.devnet {
+            // The default genesis is not a local network, so the devnet must provide its own
+            if cfg.genesis_file.is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Devnet requires the genesis of the local network, set with --genesis",
+                ));
+            }
+            // Nodes of the devnet find each other through MDNS, never through public peers
+            cfg.network.bootstrap_peers.clear();
+            cfg.network.kademlia = false;
+        }
 
         // (where to find these flags, should be easy to do with structops)
 
@@ -101,6 +149,27 @@ impl DaemonOpts {
     }
 }
 
+impl CLI {
+    /// Resolves the RPC API to connect to from, in order of precedence, the `--api` flag,
+    /// the `FULLNODE_API_INFO` environment variable and the configured RPC port.
+    pub fn api_info(&self) -> Result<ApiInfo, String> {
+        if let Some(api) = &self.api {
+            return ApiInfo::parse(api);
+        }
+        if let Some(api) = ApiInfo::from_env() {
+            return api;
+        }
+        let cfg = self.daemon_opts.to_config().map_err(|e| e.to_string())?;
+        let mut api = ApiInfo::from_port(&cfg.rpc_port);
+        // Use the admin token written by the local daemon, if any
+        let token_path = format!("{}{}", cfg.data_dir, auth_cmd::TOKEN_PATH);
+        api.token = read_file_to_string(&token_path)
+            .ok()
+            .map(|t| t.trim().to_owned());
+        Ok(api)
+    }
+}
+
 /// Blocks current thread until ctrl-c is received
 pub(super) async fn block_until_sigint() {
     let (ctrlc_send, ctrlc_oneshot) = futures::channel::oneshot::channel();
@@ -139,3 +208,39 @@ pub(super) fn stringify_rpc_err(e: JsonRpcError) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::MemoryDB;
+    use forest_libp2p::Libp2pService;
+    use libp2p::identity::Keypair;
+
+    #[test]
+    fn devnet_config() {
+        let opts = DaemonOpts::from_iter(&[
+            "forest",
+            "--devnet",
+            "--genesis",
+            "devnet.car",
+            "--kademlia",
+            "true",
+        ]);
+        let cfg = opts.to_config().unwrap();
+        assert!(cfg.devnet);
+        assert_eq!(cfg.genesis_file.as_deref(), Some("devnet.car"));
+        assert!(cfg.network.bootstrap_peers.is_empty());
+        assert!(!cfg.network.kademlia);
+
+        // The network service starts without any peer to bootstrap from
+        Libp2pService::new(
+            cfg.network,
+            Arc::new(MemoryDB::default()),
+            Keypair::generate_ed25519(),
+            "devnet",
+        );
+
+        let opts = DaemonOpts::from_iter(&["forest", "--devnet"]);
+        assert!(opts.to_config().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::stringify_rpc_err;
+use crypto::{Signature, SignatureType};
+use encoding::{from_slice, to_vec};
+use rpc_client::{
+    new_client, wallet_balance, wallet_default_address, wallet_export, wallet_import, wallet_list,
+    wallet_new, wallet_set_default, wallet_sign, wallet_verify, ApiInfo,
+};
+use std::fs;
+use structopt::StructOpt;
+use wallet::json::KeyInfoJson;
+
+#[derive(Debug, StructOpt)]
+pub enum WalletCommands {
+    /// Generates a new key of the given type and stores it in the wallet
+    #[structopt(about = "Generate a new key of the given type (bls or secp256k1)")]
+    New {
+        #[structopt(default_value = "secp256k1", help = "Key type: bls or secp256k1")]
+        key_type: String,
+    },
+
+    /// Lists all addresses in the wallet
+    #[structopt(about = "List wallet addresses")]
+    List,
+
+    /// Prints the balance of an address
+    #[structopt(about = "<Address> Get account balance")]
+    Balance {
+        #[structopt(help = "Input a valid address")]
+        address: String,
+    },
+
+    /// Exports the key info of an address as hex encoded JSON
+    #[structopt(about = "<Address> Export key info")]
+    Export {
+        #[structopt(help = "Input a valid address")]
+        address: String,
+    },
+
+    /// Imports hex encoded JSON key info, as produced by `export`, from a file
+    #[structopt(about = "<Path> Import key info from a file")]
+    Import {
+        #[structopt(help = "Path to a file containing hex encoded key info")]
+        path: String,
+    },
+
+    /// Signs a message with the key of an address and prints the hex encoded signature
+    #[structopt(about = "Sign a message")]
+    Sign {
+        #[structopt(short, help = "Address of the signing key")]
+        address: String,
+        #[structopt(short, help = "Message to sign")]
+        message: String,
+    },
+
+    /// Verifies a hex encoded signature of a message for an address
+    #[structopt(about = "Verify the signature of a message")]
+    Verify {
+        #[structopt(short, help = "Address of the signer")]
+        address: String,
+        #[structopt(short, help = "Message that was signed")]
+        message: String,
+        #[structopt(short, help = "Hex encoded signature")]
+        signature: String,
+    },
+
+    /// Prints the default address of the wallet
+    #[structopt(about = "Get default wallet address")]
+    Default,
+
+    /// Sets the default address of the wallet
+    #[structopt(about = "<Address> Set default wallet address")]
+    SetDefault {
+        #[structopt(help = "Input a valid address")]
+        address: String,
+    },
+}
+
+impl WalletCommands {
+    pub async fn run(&self, api: &ApiInfo) {
+        let mut client = new_client(api);
+
+        match self {
+            Self::New { key_type } => {
+                let sig_type = match key_type.as_str() {
+                    "bls" => SignatureType::BLS,
+                    "secp256k1" => SignatureType::Secp256k1,
+                    _ => panic!("Invalid key type {}, must be bls or secp256k1", key_type),
+                };
+                let addr = wallet_new(&mut client, sig_type)
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}", addr);
+            }
+            Self::List => {
+                let addrs = wallet_list(&mut client)
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                for addr in addrs {
+                    println!("{}", addr);
+                }
+            }
+            Self::Balance { address } => {
+                let balance = wallet_balance(&mut client, address.to_owned())
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}", balance);
+            }
+            Self::Export { address } => {
+                let key_info = wallet_export(&mut client, address.to_owned())
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                let json = serde_json::to_vec(&KeyInfoJson(key_info)).unwrap();
+                println!("{}", hex::encode(json));
+            }
+            Self::Import { path } => {
+                let contents = fs::read_to_string(path).unwrap();
+                let json = hex::decode(contents.trim()).unwrap();
+                let KeyInfoJson(key_info): KeyInfoJson = serde_json::from_slice(&json).unwrap();
+                let addr = wallet_import(&mut client, key_info)
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("Imported key for address {}", addr);
+            }
+            Self::Sign { address, message } => {
+                let sig = wallet_sign(&mut client, address.to_owned(), message.to_owned())
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}", hex::encode(to_vec(&sig).unwrap()));
+            }
+            Self::Verify {
+                address,
+                message,
+                signature,
+            } => {
+                let sig: Signature = from_slice(&hex::decode(signature).unwrap()).unwrap();
+                let valid = wallet_verify(&mut client, address.to_owned(), message.to_owned(), sig)
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}", valid);
+            }
+            Self::Default => {
+                let addr = wallet_default_address(&mut client)
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+                println!("{}", addr);
+            }
+            Self::SetDefault { address } => {
+                wallet_set_default(&mut client, address.to_owned())
+                    .await
+                    .map_err(stringify_rpc_err)
+                    .unwrap();
+            }
+        }
+    }
+}
@@ -1,27 +1,71 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use super::cli::{block_until_sigint, initialize_genesis, Config};
+use super::cli::{
+    block_until_sigint, import_chain, initialize_genesis, load_or_generate_jwt_secret, write_token,
+    Config, DbBackend,
+};
 use actor::EPOCH_DURATION_SECONDS;
-use async_std::sync::RwLock;
-use async_std::task;
-use beacon::{DrandBeacon, DEFAULT_DRAND_URL};
+use async_std::sync::{RwLock, Sender};
+use async_std::task::{self, JoinHandle};
+use beacon::{
+    Beacon, BeaconPoint, BeaconSchedule, DrandBeacon, DrandConfig, DrandPoint, DrandPublic,
+    MockBeacon,
+};
 use chain::ChainStore;
-use chain_sync::ChainSyncer;
+use chain_sync::{BadBlockCache, ChainSyncer, SyncState};
+use db::IterableStore;
+#[cfg(feature = "rocksdb")]
 use db::RocksDb;
-use forest_libp2p::{get_keypair, Libp2pService};
+#[cfg(feature = "sled")]
+use db::SledDb;
+use forest_libp2p::{get_keypair, Libp2pService, NetworkMessage};
+use ipld_blockstore::{BlockStore, CachedBlockStore};
 use libp2p::identity::{ed25519, Keypair};
 use log::{debug, info, trace};
-use message_pool::{MessagePool, MpoolRpcProvider};
-use rpc::{start_rpc, RpcState};
+use message_pool::{handle_gossip_messages, MessagePool, MpoolRpcProvider, Provider};
+use rpc::{auth, relay_head_changes, start_rpc, RpcState};
 use state_manager::StateManager;
+use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 use utils::write_to_file;
-use wallet::PersistentKeyStore;
+use wallet::{EncryptedKeyStore, KeyStore, PersistentKeyStore};
+
+/// Environment variable holding the passphrase of the encrypted keystore
+const KEYSTORE_PHRASE_ENV: &str = "FOREST_KEYSTORE_PHRASE";
 
 /// Starts daemon process
-pub(super) async fn start(config: Config) {
+pub(super) async fn start(config: Config) -> Result<(), String> {
     info!("Starting Forest daemon");
+
+    // Initialize database
+    let db_path = format!("{}/db", config.data_dir);
+    match config.db_backend {
+        #[cfg(feature = "rocksdb")]
+        DbBackend::RocksDb => {
+            let mut db = RocksDb::with_config(db_path, config.rocks_db.clone());
+            db.open().unwrap();
+            run(config, db).await
+        }
+        #[cfg(not(feature = "rocksdb"))]
+        DbBackend::RocksDb => panic!("Forest was built without the rocksdb feature"),
+        #[cfg(feature = "sled")]
+        DbBackend::Sled => {
+            let mut db = SledDb::new(db_path);
+            db.open().unwrap();
+            run(config, db).await
+        }
+        #[cfg(not(feature = "sled"))]
+        DbBackend::Sled => panic!("Forest was built without the sled feature"),
+    }
+}
+
+/// Runs the node services on top of the opened database until ctrl-c is received
+async fn run<DB>(config: Config, db: DB) -> Result<(), String>
+where
+    DB: BlockStore + IterableStore + Send + Sync + 'static,
+{
     let net_keypair = get_keypair(&format!("{}{}", &config.data_dir, "/libp2p/keypair"))
         .unwrap_or_else(|| {
             // Keypair not found, generate and save generated keypair
@@ -40,13 +84,24 @@ pub(super) async fn start(config: Config) {
         });
 
     // Initialize keystore
-    let keystore = Arc::new(RwLock::new(
-        PersistentKeyStore::new(config.data_dir.to_string()).unwrap(),
-    ));
+    let keystore: Box<dyn KeyStore + Send + Sync> = if config.encrypt_keystore {
+        let passphrase = env::var(KEYSTORE_PHRASE_ENV).map_err(|_| {
+            format!(
+                "Encrypted keystore requires a passphrase set in {}",
+                KEYSTORE_PHRASE_ENV
+            )
+        })?;
+        Box::new(
+            EncryptedKeyStore::new(config.data_dir.to_string(), &passphrase)
+                .map_err(|e| format!("Failed to open the encrypted keystore: {}", e))?,
+        )
+    } else {
+        Box::new(PersistentKeyStore::new(config.data_dir.to_string()).unwrap())
+    };
+    let keystore = Arc::new(RwLock::new(keystore));
 
-    // Initialize database
-    let mut db = RocksDb::new(config.data_dir + "/db");
-    db.open().unwrap();
+    let db = CachedBlockStore::with_bloom_filter(db, &config.block_cache)
+        .map_err(|e| format!("Failed to load the block store bloom filter: {}", e))?;
     let db = Arc::new(db);
     let mut chain_store = ChainStore::new(Arc::clone(&db));
 
@@ -54,47 +109,67 @@ pub(super) async fn start(config: Config) {
     let (genesis, network_name) =
         initialize_genesis(&config.genesis_file, &mut chain_store).unwrap();
 
+    // Import chain snapshot, if provided, so syncing starts from its head
+    if let Some(path) = &config.snapshot_path {
+        import_chain(path, &mut chain_store).await.unwrap();
+    }
+
     // Libp2p service setup
     let p2p_service =
         Libp2pService::new(config.network, Arc::clone(&db), net_keypair, &network_name);
     let network_rx = p2p_service.network_receiver();
     let network_send = p2p_service.network_sender();
 
+    // Relay head changes to RPC subscribers
+    let head_changes = relay_head_changes(chain_store.subscribe());
+
     // Initialize mpool
     let subscriber = chain_store.subscribe();
     let provider = MpoolRpcProvider::new(subscriber, Arc::clone(&db));
     let mpool = Arc::new(
-        MessagePool::new(provider, network_name.clone())
-            .await
-            .unwrap(),
+        MessagePool::new_with_republish(
+            provider,
+            network_name.clone(),
+            network_send.clone(),
+            config.mpool,
+        )
+        .await
+        .unwrap(),
     );
 
-    // Get Drand Coefficients
-    let coeff = config.drand_public;
-
-    let beacon = DrandBeacon::new(
-        DEFAULT_DRAND_URL,
-        coeff,
-        genesis.blocks()[0].timestamp(),
-        EPOCH_DURATION_SECONDS as u64,
-    )
-    .await
-    .unwrap();
-
-    // Initialize ChainSyncer
-    let chain_syncer = ChainSyncer::new(
-        chain_store,
-        Arc::new(beacon),
-        network_send.clone(),
-        network_rx,
-        genesis,
-    )
-    .unwrap();
-    let bad_blocks = chain_syncer.bad_blocks_cloned();
-    let sync_state = chain_syncer.sync_state_cloned();
-    let sync_task = task::spawn(async {
-        chain_syncer.start().await.unwrap();
-    });
+    // Initialize ChainSyncer, on a mock beacon in devnet mode so the node runs offline
+    let (sync_task, gossip_msgs_task, bad_blocks, sync_state) = if config.devnet {
+        info!("Running in devnet mode with a mock beacon");
+        let beacon = MockBeacon::new(Duration::from_secs(EPOCH_DURATION_SECONDS as u64));
+        let chain_syncer = ChainSyncer::new(
+            chain_store,
+            Arc::new(BeaconSchedule::single(Arc::new(beacon))),
+            network_send.clone(),
+            network_rx,
+            genesis,
+        )
+        .unwrap();
+        spawn_sync(chain_syncer, Arc::clone(&mpool), network_send.clone())
+    } else {
+        let genesis_ts = genesis.blocks()[0].timestamp();
+        let beacon = drand_schedule(
+            &config.drand,
+            &config.drand_public,
+            &config.drand_schedule,
+            genesis_ts,
+            Arc::clone(&db),
+        )
+        .await?;
+        let chain_syncer = ChainSyncer::new(
+            chain_store,
+            Arc::new(beacon),
+            network_send.clone(),
+            network_rx,
+            genesis,
+        )
+        .unwrap();
+        spawn_sync(chain_syncer, Arc::clone(&mpool), network_send.clone())
+    };
 
     // Start services
     let p2p_task = task::spawn(async {
@@ -102,11 +177,22 @@ pub(super) async fn start(config: Config) {
     });
 
     let rpc_task = if config.enable_rpc {
+        // Load the secret to sign API tokens and save an admin token for the CLI
+        let jwt_secret = load_or_generate_jwt_secret(&config.data_dir).unwrap();
+        let admin_perms = auth::ADMIN_PERMISSIONS
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        let token = auth::create_token(admin_perms, &jwt_secret).unwrap();
+        write_token(&config.data_dir, &token).unwrap();
+
         let db_rpc = StateManager::new(Arc::clone(&db));
         let keystore_rpc = Arc::clone(&keystore);
         let rpc_listen = format!("127.0.0.1:{}", &config.rpc_port);
+        let ws_listen = format!("127.0.0.1:{}", &config.rpc_ws_port);
         Some(task::spawn(async move {
             info!("JSON RPC Endpoint at {}", &rpc_listen);
+            info!("WebSocket RPC Endpoint at {}", &ws_listen);
             start_rpc(
                 RpcState {
                     state_manager: db_rpc,
@@ -116,8 +202,11 @@ pub(super) async fn start(config: Config) {
                     sync_state,
                     network_send,
                     network_name,
+                    jwt_secret,
+                    head_changes,
                 },
                 &rpc_listen,
+                &ws_listen,
             )
             .await;
         }))
@@ -136,10 +225,97 @@ pub(super) async fn start(config: Config) {
     // Cancel all async services
     p2p_task.cancel().await;
     sync_task.cancel().await;
+    gossip_msgs_task.cancel().await;
     if let Some(task) = rpc_task {
         task.cancel().await;
     }
     keystore_write.await;
 
+    info!("Block cache stats: {:?}", db.stats());
     info!("Forest finish shutdown");
+    Ok(())
+}
+
+/// Creates the drand beacons used by the chain, the configured one from genesis and the ones it
+/// switches to at network upgrades
+async fn drand_schedule<DB>(
+    config: &DrandConfig,
+    public: &DrandPublic,
+    schedule: &[DrandPoint],
+    genesis_ts: u64,
+    db: Arc<DB>,
+) -> Result<BeaconSchedule<DrandBeacon<DB>>, String>
+where
+    DB: BlockStore + Send + Sync,
+{
+    let mut beacon_points = vec![BeaconPoint {
+        height: 0,
+        beacon: Arc::new(
+            DrandBeacon::new(
+                config,
+                public.clone(),
+                genesis_ts,
+                EPOCH_DURATION_SECONDS as u64,
+                Arc::clone(&db),
+            )
+            .await
+            .map_err(|e| format!("Failed to create drand beacon: {}", e))?,
+        ),
+    }];
+    for point in schedule {
+        beacon_points.push(BeaconPoint {
+            height: point.height,
+            beacon: Arc::new(
+                DrandBeacon::new(
+                    &point.config,
+                    point.public.clone(),
+                    genesis_ts,
+                    EPOCH_DURATION_SECONDS as u64,
+                    Arc::clone(&db),
+                )
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Failed to create drand beacon at height {}: {}",
+                        point.height, e
+                    )
+                })?,
+            ),
+        });
+    }
+    BeaconSchedule::new(beacon_points).map_err(|e| format!("Invalid drand schedule: {}", e))
+}
+
+/// Spawns the chain syncer and the task adding the messages it receives over gossipsub to the
+/// message pool, returning their handles along with the bad block cache and sync state of the
+/// syncer
+fn spawn_sync<DB, B, T>(
+    mut chain_syncer: ChainSyncer<DB, B>,
+    mpool: Arc<MessagePool<T>>,
+    network_send: Sender<NetworkMessage>,
+) -> (
+    JoinHandle<()>,
+    JoinHandle<()>,
+    Arc<BadBlockCache>,
+    Arc<RwLock<SyncState>>,
+)
+where
+    DB: BlockStore + Send + Sync + 'static,
+    B: Beacon + Send + Sync + 'static,
+    T: Provider + Send + Sync + 'static,
+{
+    let bad_blocks = chain_syncer.bad_blocks_cloned();
+    let sync_state = chain_syncer.sync_state_cloned();
+
+    // Add messages received over gossipsub to the message pool
+    let gossip_msgs_task = task::spawn(handle_gossip_messages(
+        mpool,
+        chain_syncer.network_events(),
+        network_send,
+    ));
+
+    let sync_task = task::spawn(async move {
+        chain_syncer.start().await.unwrap();
+    });
+    (sync_task, gossip_msgs_task, bad_blocks, sync_state)
 }
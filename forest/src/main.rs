@@ -8,6 +8,7 @@ pub(crate) mod paramfetch;
 mod subcommand;
 
 use cli::CLI;
+use std::process;
 use structopt::StructOpt;
 
 #[async_std::main]
@@ -15,13 +16,14 @@ async fn main() {
     logger::setup_logger();
 
     // Capture CLI inputs
-    match CLI::from_args() {
-        CLI {
-            daemon_opts,
-            cmd: None,
-        } => daemon::start(daemon_opts.to_config().unwrap()).await,
-        CLI {
-            cmd: Some(command), ..
-        } => subcommand::process(command).await,
+    let cli = CLI::from_args();
+    match cli.cmd {
+        None => {
+            if let Err(e) = daemon::start(cli.daemon_opts.to_config().unwrap()).await {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(ref command) => subcommand::process(&cli, command).await,
     }
 }
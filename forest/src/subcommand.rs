@@ -1,19 +1,38 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use super::cli::Subcommand;
+use super::cli::{Subcommand, CLI};
+use rpc_client::ApiInfo;
+use std::process;
 
 /// Process CLI subcommand
-pub(super) async fn process(command: Subcommand) {
+pub(super) async fn process(cli: &CLI, command: &Subcommand) {
     match command {
         Subcommand::Fetch(cmd) => {
             cmd.run().await;
         }
         Subcommand::Chain(cmd) => {
-            cmd.run().await;
+            cmd.run(&api_info(cli)).await;
         }
         Subcommand::Genesis(cmd) => {
             cmd.run().await;
         }
+        Subcommand::Wallet(cmd) => {
+            cmd.run(&api_info(cli)).await;
+        }
+        Subcommand::Auth(cmd) => {
+            cmd.run(&api_info(cli)).await;
+        }
+        Subcommand::Db(cmd) => {
+            cmd.run(&cli.daemon_opts.to_config().unwrap()).await;
+        }
     }
 }
+
+/// Resolves the RPC API of the node for the commands talking to it, exiting on an invalid API.
+fn api_info(cli: &CLI) -> ApiInfo {
+    cli.api_info().unwrap_or_else(|e| {
+        eprintln!("Invalid RPC API: {}", e);
+        process::exit(1);
+    })
+}
@@ -9,11 +9,11 @@ use cid::{
     Cid,
 };
 use commcid::{POSEIDON_BLS12_381_A1_FC1, SHA2_256_TRUNC254_PADDED};
-use db::{Error, Store};
+use db::{Batch, Error, Store};
 use encoding::{from_slice, ser::Serialize, to_vec};
 use forest_ipld::Ipld;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error as StdError;
 
 /// Wrapper around `BlockStore` to limit and have control over when values are written.
@@ -36,20 +36,31 @@ where
     }
     /// Flushes the buffered cache based on the root node.
     /// This will recursively traverse the cache and write all data connected by links to this
-    /// root Cid.
+    /// root Cid. All data is committed to the base store in a single batch, so a failed flush
+    /// doesn't leave part of the data written.
     pub fn flush(&mut self, root: &Cid) -> Result<(), Box<dyn StdError>> {
-        write_recursive(self.base, &self.write.borrow(), root)?;
+        let mut batch = self.base.begin();
+        write_recursive(
+            self.base,
+            &self.write.borrow(),
+            root,
+            &mut batch,
+            &mut HashSet::new(),
+        )?;
+        self.base.commit(batch)?;
 
         self.write = Default::default();
         Ok(())
     }
 }
 
-/// Recursively traverses cache through Cid links.
+/// Recursively traverses cache through Cid links, adding the data to write to the batch.
 fn write_recursive<BS>(
     base: &BS,
     cache: &HashMap<Cid, Vec<u8>>,
     cid: &Cid,
+    batch: &mut Batch,
+    written: &mut HashSet<Cid>,
 ) -> Result<(), Box<dyn StdError>>
 where
     BS: BlockStore,
@@ -60,6 +71,11 @@ where
         return Ok(());
     }
 
+    // Skip data already added to the batch through another link
+    if !written.insert(cid.clone()) {
+        return Ok(());
+    }
+
     let raw_cid_bz = cid.to_bytes();
 
     // If root exists in base store already, can skip
@@ -77,17 +93,19 @@ where
     let block: Ipld = from_slice(raw_bz)?;
 
     // Traverse and write linked data recursively
-    for_each_link(&block, &|c| write_recursive(base, cache, c))?;
+    for_each_link(&block, &mut |c| {
+        write_recursive(base, cache, c, batch, written)
+    })?;
 
     // Write the root node to base storage
-    base.write(&raw_cid_bz, raw_bz)?;
+    batch.put(&raw_cid_bz, raw_bz);
     Ok(())
 }
 
 /// Recursively explores Ipld for links and calls a function with a reference to the Cid.
-fn for_each_link<F>(ipld: &Ipld, cb: &F) -> Result<(), Box<dyn StdError>>
+fn for_each_link<F>(ipld: &Ipld, cb: &mut F) -> Result<(), Box<dyn StdError>>
 where
-    F: Fn(&Cid) -> Result<(), Box<dyn StdError>>,
+    F: FnMut(&Cid) -> Result<(), Box<dyn StdError>>,
 {
     match ipld {
         Ipld::Link(c) => cb(&c)?,
@@ -178,6 +196,31 @@ where
     {
         self.base.bulk_delete(keys)
     }
+    fn read_metadata<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.base.read_metadata(key)
+    }
+    fn write_metadata<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.base.write_metadata(key, value)
+    }
+    fn delete_metadata<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.base.delete_metadata(key)
+    }
+    fn begin(&self) -> Batch {
+        self.base.begin()
+    }
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        self.base.commit(batch)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,437 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+#![cfg(feature = "cached")]
+
+use super::BlockStore;
+use cid::{multihash::MultihashDigest, Cid};
+use db::{Batch, BatchOp, Error, IterableStore, Store};
+use encoding::{ser::Serialize, to_vec};
+use log::info;
+use lru::LruCache;
+use parking_lot::{Mutex, RwLock};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error as StdError;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Options of the cache of a `CachedBlockStore`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BlockCacheConfig {
+    /// Maximum number of raw blocks kept in memory, or 0 to disable the cache
+    pub cache_size: usize,
+    /// Keep a bloom filter of the keys of the store, so most lookups of missing keys don't
+    /// reach the store. All keys are loaded in the filter when the store is opened.
+    pub bloom_filter: bool,
+    /// Number of keys the bloom filter is sized for
+    pub bloom_capacity: usize,
+    /// False positive rate of the bloom filter once it holds `bloom_capacity` keys
+    pub bloom_fp_rate: f64,
+}
+
+impl Default for BlockCacheConfig {
+    fn default() -> Self {
+        Self {
+            cache_size: 16 * 1024,
+            bloom_filter: false,
+            bloom_capacity: 10_000_000,
+            bloom_fp_rate: 0.01,
+        }
+    }
+}
+
+/// Counters of the reads of a `CachedBlockStore`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads which went to the store
+    pub misses: u64,
+    /// Reads of missing keys answered by the bloom filter, without reaching the store
+    pub bloom_skips: u64,
+}
+
+/// Bloom filter over the keys of a store. Keys can't be removed, so deleted keys only add
+/// false positives.
+#[derive(Debug)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u64,
+}
+
+impl BloomFilter {
+    fn new(capacity: usize, fp_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let fp_rate = fp_rate.max(f64::MIN_POSITIVE).min(0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-capacity * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = (num_bits as f64 / capacity * ln2).round().max(1.0) as u64;
+        Self {
+            bits: vec![0; ((num_bits + 63) / 64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Bit indexes of the key, derived from two hashes of it
+    fn indexes<'a>(&'a self, key: &[u8]) -> impl Iterator<Item = u64> + 'a {
+        let h1 = hash_with_seed(key, 0);
+        let h2 = hash_with_seed(key, 1) | 1;
+        (0..self.num_hashes).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    fn insert(&mut self, key: &[u8]) {
+        let indexes: Vec<u64> = self.indexes(key).collect();
+        for i in indexes {
+            self.bits[(i / 64) as usize] |= 1 << (i % 64);
+        }
+    }
+
+    fn may_contain(&self, key: &[u8]) -> bool {
+        self.indexes(key)
+            .all(|i| self.bits[(i / 64) as usize] & (1 << (i % 64)) != 0)
+    }
+}
+
+fn hash_with_seed(key: &[u8], seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Cache of the blocks read, keyed by a hash of their key so lookups don't allocate. Entries
+/// keep their key, as keys with the same hash replace each other.
+struct BlockCache {
+    blocks: LruCache<u64, (Vec<u8>, Vec<u8>)>,
+    /// Incremented on every write or delete, so a read doesn't put a value in the cache if the
+    /// store was written to since it read the value
+    generation: u64,
+}
+
+/// Wrapper around a `BlockStore` which keeps the most recently read blocks in memory, so the
+/// blocks read over and over while validating blocks don't all go to the store.
+pub struct CachedBlockStore<BS> {
+    base: BS,
+    cache: Option<Mutex<BlockCache>>,
+    bloom: Option<RwLock<BloomFilter>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bloom_skips: AtomicU64,
+}
+
+impl<BS> CachedBlockStore<BS>
+where
+    BS: BlockStore,
+{
+    /// Wraps the store without a bloom filter, the bloom filter options of the config are
+    /// ignored. Use `with_bloom_filter` to honor them.
+    pub fn new(base: BS, config: &BlockCacheConfig) -> Self {
+        let cache = if config.cache_size > 0 {
+            Some(Mutex::new(BlockCache {
+                blocks: LruCache::new(config.cache_size),
+                generation: 0,
+            }))
+        } else {
+            None
+        };
+        Self {
+            base,
+            cache,
+            bloom: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            bloom_skips: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<BS> CachedBlockStore<BS>
+where
+    BS: BlockStore + IterableStore,
+{
+    /// Wraps the store, loading all of its keys in the bloom filter if it is enabled.
+    pub fn with_bloom_filter(base: BS, config: &BlockCacheConfig) -> Result<Self, Error> {
+        let mut store = Self::new(base, config);
+        if config.bloom_filter {
+            let mut bloom = BloomFilter::new(config.bloom_capacity, config.bloom_fp_rate);
+            let mut keys = 0;
+            store.base.for_each_key(|k| {
+                bloom.insert(k);
+                keys += 1;
+                Ok(())
+            })?;
+            info!("Loaded {} keys in the block store bloom filter", keys);
+            store.bloom = Some(RwLock::new(bloom));
+        }
+        Ok(store)
+    }
+}
+
+impl<BS> CachedBlockStore<BS> {
+    /// Returns the counters of the reads since the store was opened.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            bloom_skips: self.bloom_skips.load(Ordering::Relaxed),
+        }
+    }
+
+    fn cached(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let mut cache = self.cache.as_ref()?.lock();
+        let value = match cache.blocks.get(&hash_with_seed(key, 0)) {
+            Some((k, v)) if k.as_slice() == key => Some(v.clone()),
+            _ => None,
+        };
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    /// Returns the generation of the cache, to pass to `fill` once the value is read.
+    fn generation(&self) -> u64 {
+        self.cache.as_ref().map_or(0, |c| c.lock().generation)
+    }
+
+    /// Puts a value read from the store in the cache, unless the store was written to since
+    /// the generation, in which case the value may be stale.
+    fn fill(&self, key: &[u8], value: &[u8], generation: u64) {
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock();
+            if cache.generation == generation {
+                cache
+                    .blocks
+                    .put(hash_with_seed(key, 0), (key.to_vec(), value.to_vec()));
+            }
+        }
+    }
+
+    /// Returns false if the key is certainly not in the store.
+    fn may_contain(&self, key: &[u8]) -> bool {
+        let contained = match &self.bloom {
+            Some(bloom) => bloom.read().may_contain(key),
+            None => true,
+        };
+        if !contained {
+            self.bloom_skips.fetch_add(1, Ordering::Relaxed);
+        }
+        contained
+    }
+
+    fn written(&self, key: &[u8]) {
+        if let Some(bloom) = &self.bloom {
+            bloom.write().insert(key);
+        }
+        self.evict(key);
+    }
+
+    fn evict(&self, key: &[u8]) {
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock();
+            cache.generation += 1;
+            cache.blocks.pop(&hash_with_seed(key, 0));
+        }
+    }
+}
+
+impl<BS> Store for CachedBlockStore<BS>
+where
+    BS: Store,
+{
+    fn read<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if let Some(value) = self.cached(key) {
+            return Ok(Some(value));
+        }
+        if !self.may_contain(key) {
+            return Ok(None);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let generation = self.generation();
+        let value = self.base.read(key)?;
+        if let Some(value) = &value {
+            self.fill(key, value, generation);
+        }
+        Ok(value)
+    }
+    fn write<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.base.write(key.as_ref(), value)?;
+        self.written(key.as_ref());
+        Ok(())
+    }
+    fn delete<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.base.delete(key.as_ref())?;
+        self.evict(key.as_ref());
+        Ok(())
+    }
+    fn exists<K>(&self, key: K) -> Result<bool, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        if self.cached(key).is_some() {
+            return Ok(true);
+        }
+        if !self.may_contain(key) {
+            return Ok(false);
+        }
+        self.base.exists(key)
+    }
+    fn read_metadata<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.base.read_metadata(key)
+    }
+    fn write_metadata<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.base.write_metadata(key, value)
+    }
+    fn delete_metadata<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.base.delete_metadata(key)
+    }
+    fn begin(&self) -> Batch {
+        self.base.begin()
+    }
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        let mut written = Vec::new();
+        let mut deleted = Vec::new();
+        for op in batch.ops() {
+            match op {
+                BatchOp::Put(key, _) => written.push(key.clone()),
+                BatchOp::Delete(key) => deleted.push(key.clone()),
+                _ => (),
+            }
+        }
+        self.base.commit(batch)?;
+        written.iter().for_each(|key| self.written(key));
+        deleted.iter().for_each(|key| self.evict(key));
+        Ok(())
+    }
+}
+
+impl<BS> IterableStore for CachedBlockStore<BS>
+where
+    BS: IterableStore,
+{
+    fn for_each_key<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        self.base.for_each_key(f)
+    }
+}
+
+impl<BS> BlockStore for CachedBlockStore<BS>
+where
+    BS: BlockStore,
+{
+    fn bulk_put<'a, S, T, V>(&self, values: V, hash: T) -> Result<Vec<Cid>, Box<dyn StdError>>
+    where
+        S: Serialize + 'a,
+        T: MultihashDigest + Clone,
+        V: IntoIterator<Item = &'a S>,
+    {
+        let mut batch = self.begin();
+        let cids: Vec<Cid> = values
+            .into_iter()
+            .map(|v| {
+                let bz = to_vec(v)?;
+                let cid = Cid::new_from_cbor(&bz, hash.clone());
+                batch.put(cid.to_bytes(), bz);
+                Ok(cid)
+            })
+            .collect::<Result<_, Box<dyn StdError>>>()?;
+        self.commit(batch)?;
+
+        Ok(cids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::multihash::Blake2b256;
+    use db::MemoryDB;
+
+    #[test]
+    fn cached_reads() {
+        let store = CachedBlockStore::new(MemoryDB::default(), &Default::default());
+        let cid = store.put(&8u8, Blake2b256).unwrap();
+
+        assert_eq!(store.get::<u8>(&cid).unwrap(), Some(8));
+        assert_eq!(store.get::<u8>(&cid).unwrap(), Some(8));
+        assert_eq!(
+            store.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                bloom_skips: 0
+            }
+        );
+
+        // Deleted blocks are evicted from the cache
+        store.delete(cid.to_bytes()).unwrap();
+        assert_eq!(store.get::<u8>(&cid).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_racing_writes_are_not_cached() {
+        let store = CachedBlockStore::new(MemoryDB::default(), &Default::default());
+        store.write(b"key", b"old").unwrap();
+
+        // a read of the old value finishing after a write must not fill the cache
+        let generation = store.generation();
+        store.write(b"key", b"new").unwrap();
+        store.fill(b"key", b"old", generation);
+        assert_eq!(store.read(b"key").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(store.read(b"key").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(store.stats().hits, 1);
+    }
+
+    #[test]
+    fn bloom_filter_skips_missing_keys() {
+        let base = MemoryDB::default();
+        let existing = base.put(&1u8, Blake2b256).unwrap();
+        let config = BlockCacheConfig {
+            cache_size: 0,
+            bloom_filter: true,
+            bloom_capacity: 1000,
+            ..Default::default()
+        };
+        let store = CachedBlockStore::with_bloom_filter(base, &config).unwrap();
+
+        // Keys in the store before it was wrapped are loaded in the filter
+        assert_eq!(store.get::<u8>(&existing).unwrap(), Some(1));
+        let cids = store.bulk_put(&[2u8, 3u8], Blake2b256).unwrap();
+        for cid in &cids {
+            assert!(store.exists(cid.to_bytes()).unwrap());
+        }
+
+        let missing = Cid::new_from_cbor(&[4], Blake2b256);
+        assert!(!store.exists(missing.to_bytes()).unwrap());
+        assert_eq!(store.stats().bloom_skips, 1);
+        assert_eq!(store.stats().hits, 0);
+    }
+}
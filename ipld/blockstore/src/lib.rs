@@ -2,9 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 mod buffered;
+mod cached;
 
 #[cfg(feature = "buffered")]
 pub use self::buffered::BufferedBlockStore;
+#[cfg(feature = "cached")]
+pub use self::cached::{BlockCacheConfig, CacheStats, CachedBlockStore};
 
 use cid::{multihash::MultihashDigest, Cid};
 use db::{MemoryDB, Store};
@@ -14,6 +17,9 @@ use std::error::Error as StdError;
 #[cfg(feature = "rocksdb")]
 use db::{RocksDb, WriteBatch};
 
+#[cfg(feature = "sled")]
+use db::SledDb;
+
 /// Wrapper for database to handle inserting and retrieving ipld data with Cids
 pub trait BlockStore: Store {
     /// Get bytes from block store by Cid
@@ -83,3 +89,6 @@ impl BlockStore for RocksDb {
         Ok(cids)
     }
 }
+
+#[cfg(feature = "sled")]
+impl BlockStore for SledDb {}
@@ -6,11 +6,11 @@ mod util;
 
 use blockstore::BlockStore;
 use cid::Cid;
-use error::*;
-use forest_encoding::from_slice;
+pub use error::*;
+use forest_encoding::{from_slice, to_vec};
 use serde::{Deserialize, Serialize};
-use std::io::Read;
-use util::{ld_read, read_node};
+use std::io::{Read, Write};
+use util::{ld_read, ld_write, read_node};
 
 /// CAR file header
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -58,11 +58,48 @@ where
     }
 }
 
+/// Writes CAR files to any type that implements Write
+pub struct CarWriter<W> {
+    writer: W,
+}
+
+impl<W> CarWriter<W>
+where
+    W: Write,
+{
+    /// Creates a new CarWriter and writes the CarHeader
+    pub fn new(mut writer: W, header: &CarHeader) -> Result<Self, Error> {
+        if header.roots.is_empty() {
+            return Err(Error::InvalidFile(
+                "CAR header must contain at least one root".to_owned(),
+            ));
+        }
+        let header_bz = to_vec(header).map_err(|e| Error::Other(e.to_string()))?;
+        ld_write(&mut writer, &header_bz)?;
+        Ok(CarWriter { writer })
+    }
+
+    /// Writes an IPLD block, prefixed with its Cid, to the underlying writer
+    pub fn write_block(&mut self, cid: &Cid, data: &[u8]) -> Result<(), Error> {
+        let mut node = cid.to_bytes();
+        node.extend_from_slice(data);
+        ld_write(&mut self.writer, &node)
+    }
+
+    /// Flushes the underlying writer and returns it
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer
+            .flush()
+            .map_err(|e| Error::Other(e.to_string()))?;
+        Ok(self.writer)
+    }
+}
+
 /// IPLD Block
 #[derive(Clone, Debug)]
 pub struct Block {
-    cid: Cid,
-    data: Vec<u8>,
+    pub cid: Cid,
+    pub data: Vec<u8>,
 }
 
 /// Loads a CAR buffer into a BlockStore
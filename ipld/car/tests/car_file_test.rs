@@ -14,3 +14,30 @@ fn load_into_blockstore() {
 
     let _ = load_car(&mut bs, buf_reader).unwrap();
 }
+
+#[test]
+fn write_and_read_back() {
+    let file = File::open("tests/test.car").unwrap();
+    let mut reader = CarReader::new(BufReader::new(file)).unwrap();
+    let header = CarHeader::new(reader.header.roots.clone(), 1);
+
+    let mut blocks = Vec::new();
+    let mut writer = CarWriter::new(Vec::new(), &header).unwrap();
+    while let Some(block) = reader.next_block().unwrap() {
+        writer.write_block(&block.cid, &block.data).unwrap();
+        blocks.push(block);
+    }
+    let buf = writer.finish().unwrap();
+
+    let mut reader = CarReader::new(buf.as_slice()).unwrap();
+    assert_eq!(reader.header.roots, header.roots);
+    for expected in blocks {
+        let block = reader.next_block().unwrap().unwrap();
+        assert_eq!(block.cid, expected.cid);
+        assert_eq!(block.data, expected.data);
+    }
+    assert!(reader.next_block().unwrap().is_none());
+
+    let bs = MemoryDB::default();
+    assert_eq!(load_car(&bs, buf.as_slice()).unwrap(), header.roots);
+}
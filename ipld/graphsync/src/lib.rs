@@ -3,13 +3,18 @@
 
 // TODO evaluate exporting from libp2p mod
 pub mod libp2p;
+mod loader;
 mod message;
+mod request_manager;
 mod response_manager;
 
 #[cfg(test)]
 mod test_utils;
 
+pub use self::loader::BlockStoreLoader;
 pub use self::message::*;
+pub use self::request_manager::*;
+pub use self::response_manager::*;
 
 use cid::Cid;
 use serde::{Deserialize, Serialize};
@@ -115,6 +120,20 @@ impl ResponseStatusCode {
         }
     }
 
+    /// Returns true if the status code terminates the request.
+    pub fn is_terminal(self) -> bool {
+        // Informational codes are below 20
+        self.to_i32() >= 20
+    }
+
+    /// Returns true if the request completed, either fully or partially.
+    pub fn is_success(self) -> bool {
+        match self {
+            Self::RequestCompletedFull | Self::RequestCompletedPartial => true,
+            _ => false,
+        }
+    }
+
     /// Return the status code for a given integer.
     pub fn from_i32(code: i32) -> Self {
         match code {
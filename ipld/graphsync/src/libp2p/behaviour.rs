@@ -2,20 +2,63 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use super::config::GraphSyncConfig;
-use super::handler::GraphSyncHandler;
-use crate::{Extensions, GraphSyncMessage};
+use super::handler::{GraphSyncHandler, GraphSyncHandlerEvent};
+use crate::{
+    CompletedRequest, Extensions, GraphSyncMessage, GraphSyncRequest, GraphSyncResponse,
+    PeerMessageHandler, RequestEvent, RequestID, RequestManager,
+};
+use async_trait::async_trait;
 use cid::Cid;
 use forest_ipld::selector::Selector;
 use futures::task::Context;
 use futures_util::task::Poll;
 use libp2p::core::connection::ConnectionId;
 use libp2p::swarm::{
-    protocols_handler::ProtocolsHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
-    PollParameters,
+    protocols_handler::ProtocolsHandler, DialPeerCondition, NetworkBehaviour,
+    NetworkBehaviourAction, NotifyHandler, PollParameters,
 };
 use libp2p::{Multiaddr, PeerId};
 use log::debug;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Events emitted by the GraphSync behaviour.
+#[derive(Debug)]
+pub enum GraphSyncEvent {
+    /// A peer sent a request, or an update or cancel of one of its requests.
+    Request {
+        peer: PeerId,
+        request: GraphSyncRequest,
+    },
+    /// Part of the response to one of our requests was received.
+    Progress {
+        id: RequestID,
+        peer: PeerId,
+        blocks_received: usize,
+    },
+    /// The response to one of our requests terminated. The received blocks are not stored until
+    /// verified with `store_verified_blocks`.
+    Completed {
+        id: RequestID,
+        request: CompletedRequest,
+    },
+}
+
+impl From<RequestEvent> for GraphSyncEvent {
+    fn from(event: RequestEvent) -> Self {
+        match event {
+            RequestEvent::Progress {
+                id,
+                peer,
+                blocks_received,
+            } => Self::Progress {
+                id,
+                peer,
+                blocks_received,
+            },
+            RequestEvent::Completed { id, request } => Self::Completed { id, request },
+        }
+    }
+}
 
 /// The GraphSync behaviour that gets consumed by the Swarm.
 #[derive(Default)]
@@ -24,10 +67,16 @@ pub struct GraphSync {
     config: GraphSyncConfig,
 
     /// Queue of events to processed.
-    events: VecDeque<NetworkBehaviourAction<GraphSyncMessage, ()>>,
+    events: VecDeque<NetworkBehaviourAction<GraphSyncMessage, GraphSyncEvent>>,
 
-    // TODO just temporary, will probably have to attach some data with peers
+    /// Peers with an established connection.
     peers: HashSet<PeerId>,
+
+    /// Messages to send to peers once they are connected.
+    pending_messages: HashMap<PeerId, Vec<GraphSyncMessage>>,
+
+    /// Requests sent to peers, awaiting their responses.
+    request_manager: RequestManager,
 }
 
 impl GraphSync {
@@ -39,22 +88,79 @@ impl GraphSync {
         }
     }
 
-    /// Initiates GraphSync request to peer given root and selector.
+    /// Initiates GraphSync request to peer given root and selector. Progress and completion of
+    /// the request are emitted as events with the returned id.
     pub fn send_request(
         &mut self,
-        _peer_id: PeerId,
-        _root: Cid,
-        _selector: Selector,
-        _extensions: Extensions,
+        peer_id: PeerId,
+        root: Cid,
+        selector: Selector,
+        extensions: Extensions,
+    ) -> RequestID {
+        let request = self
+            .request_manager
+            .new_request(peer_id.clone(), root, selector, extensions);
+        let id = request.id;
+        let mut message = GraphSyncMessage::default();
+        message.insert_request(request);
+        self.send_message(peer_id, message);
+        id
+    }
+
+    /// Cancels an in progress request, notifying the peer it was sent to. No completion event is
+    /// emitted for a cancelled request.
+    pub fn cancel_request(&mut self, id: RequestID) {
+        if let Some((peer_id, cancel)) = self.request_manager.cancel_request(id) {
+            let mut message = GraphSyncMessage::default();
+            message.insert_request(cancel);
+            self.send_message(peer_id, message);
+        }
+    }
+
+    /// Sends a message to the peer, dialing it if it is not connected.
+    pub fn send_message(&mut self, peer_id: PeerId, message: GraphSyncMessage) {
+        if self.peers.contains(&peer_id) {
+            self.events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id,
+                    event: message,
+                    handler: NotifyHandler::Any,
+                });
+        } else {
+            let pending = self.pending_messages.entry(peer_id.clone()).or_default();
+            if pending.is_empty() {
+                self.events.push_back(NetworkBehaviourAction::DialPeer {
+                    peer_id,
+                    condition: DialPeerCondition::Disconnected,
+                });
+            }
+            pending.push(message);
+        }
+    }
+}
+
+#[async_trait]
+impl PeerMessageHandler for GraphSync {
+    async fn send_response(
+        &mut self,
+        peer: &PeerId,
+        responses: Vec<GraphSyncResponse>,
+        blocks: Vec<(Cid, Vec<u8>)>,
     ) {
-        todo!()
+        let mut message = GraphSyncMessage::default();
+        for response in responses {
+            message.insert_response(response);
+        }
+        for (link, block) in blocks {
+            message.insert_block(link, block);
+        }
+        self.send_message(peer.clone(), message);
     }
 }
 
 impl NetworkBehaviour for GraphSync {
     type ProtocolsHandler = GraphSyncHandler;
-    // TODO this will need to be updated to include data emitted from the GS responses
-    type OutEvent = ();
+    type OutEvent = GraphSyncEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
         GraphSyncHandler::new(
@@ -70,25 +176,66 @@ impl NetworkBehaviour for GraphSync {
     fn inject_connected(&mut self, peer_id: &PeerId) {
         debug!("New peer connected: {:?}", peer_id);
         self.peers.insert(peer_id.clone());
+        for message in self.pending_messages.remove(peer_id).unwrap_or_default() {
+            self.events
+                .push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer_id.clone(),
+                    event: message,
+                    handler: NotifyHandler::Any,
+                });
+        }
     }
 
     fn inject_disconnected(&mut self, peer_id: &PeerId) {
         debug!("Peer disconnected: {:?}", peer_id);
         self.peers.remove(peer_id);
+        for event in self.request_manager.peer_disconnected(peer_id) {
+            self.events
+                .push_back(NetworkBehaviourAction::GenerateEvent(event.into()));
+        }
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        // Requests waiting for the connection fail with the peer
+        self.pending_messages.remove(peer_id);
+        self.inject_disconnected(peer_id);
     }
 
     fn inject_event(
         &mut self,
         peer_id: PeerId,
         _connection: ConnectionId,
-        event: GraphSyncMessage,
+        event: GraphSyncHandlerEvent,
     ) {
-        self.events
-            .push_back(NetworkBehaviourAction::NotifyHandler {
-                peer_id,
-                event,
-                handler: NotifyHandler::Any,
-            });
+        let message = match event {
+            GraphSyncHandlerEvent::Message(message) => message,
+            GraphSyncHandlerEvent::SendFailed(ids) => {
+                // Requests which never reached the peer will not get a response
+                for event in self.request_manager.send_failed(&peer_id, &ids) {
+                    self.events
+                        .push_back(NetworkBehaviourAction::GenerateEvent(event.into()));
+                }
+                return;
+            }
+        };
+        for request in message.requests().values() {
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                GraphSyncEvent::Request {
+                    peer: peer_id.clone(),
+                    request: request.clone(),
+                },
+            ));
+        }
+        for event in self.request_manager.process_message(&peer_id, &message) {
+            self.events
+                .push_back(NetworkBehaviourAction::GenerateEvent(event.into()));
+        }
+        // Peers still responding to requests which failed are told to stop
+        for (peer_id, cancel) in self.request_manager.take_cancels() {
+            let mut message = GraphSyncMessage::default();
+            message.insert_request(cancel);
+            self.send_message(peer_id, message);
+        }
     }
 
     fn poll(
@@ -20,7 +20,8 @@ impl Encoder for GraphSyncCodec {
     type Item = GraphSyncMessage;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let proto_msg = proto::Message::try_from(item).unwrap();
+        let proto_msg = proto::Message::try_from(item)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
         let buf: Vec<u8> = proto_msg.write_to_bytes()?;
 
         self.length_codec.encode(Bytes::from(buf), dst)
@@ -3,24 +3,36 @@
 
 use super::codec::GraphSyncCodec;
 use super::protocol::ProtocolConfig;
-use crate::GraphSyncMessage;
+use crate::{GraphSyncMessage, RequestID};
+use futures::prelude::*;
 use futures_codec::Framed;
 use libp2p::swarm::{
     KeepAlive, NegotiatedSubstream, ProtocolsHandler, ProtocolsHandlerEvent,
     ProtocolsHandlerUpgrErr, SubstreamProtocol,
 };
 use libp2p::{InboundUpgrade, OutboundUpgrade};
-use log::trace;
+use log::{debug, trace};
 use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::io;
+use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
 // TODO move this to config option
 const TIMEOUT: u64 = 10;
 
+/// Events emitted by the handler to the behaviour.
+#[derive(Debug)]
+pub enum GraphSyncHandlerEvent {
+    /// A message was received from the remote.
+    Message(GraphSyncMessage),
+    /// A message could not be sent to the remote and was dropped. Holds the ids of the requests
+    /// of the message, which will not get a response.
+    SendFailed(Vec<RequestID>),
+}
+
 /// Handler implementation for GraphSync protocol.
 pub struct GraphSyncHandler {
     /// Upgrade configuration for the GraphSync protocol.
@@ -29,6 +41,9 @@ pub struct GraphSyncHandler {
     /// Map of current substreams awaiting a response to a GraphSync request.
     inbound_substreams: VecDeque<InboundSubstreamState>,
 
+    /// Substreams the queued messages are being sent on.
+    outbound_substreams: VecDeque<OutboundSubstreamState>,
+
     /// Queue of outbound substreams to open.
     dial_queue: SmallVec<[GraphSyncMessage; 4]>,
 
@@ -36,13 +51,13 @@ pub struct GraphSyncHandler {
     dial_negotiated: u32,
 
     /// Maximum number of concurrent outbound substreams being opened. Value is never modified.
-    _max_dial_negotiated: u32,
+    max_dial_negotiated: u32,
 
     /// Value to return from `connection_keep_alive`.
     keep_alive: KeepAlive,
 
-    /// If `Some`, something bad happened and we should shut down the handler with an error.
-    pending_error: Option<ProtocolsHandlerUpgrErr<io::Error>>,
+    /// Requests of the messages which failed to be sent, to report to the behaviour.
+    failed_requests: VecDeque<Vec<RequestID>>,
 }
 
 impl GraphSyncHandler {
@@ -67,30 +82,42 @@ impl Default for GraphSyncHandler {
         Self {
             listen_protocol: SubstreamProtocol::new(ProtocolConfig::default()),
             inbound_substreams: Default::default(),
+            outbound_substreams: Default::default(),
             dial_queue: Default::default(),
             dial_negotiated: 0,
-            _max_dial_negotiated: 8,
+            max_dial_negotiated: 8,
             keep_alive: KeepAlive::Yes,
-            pending_error: None,
+            failed_requests: Default::default(),
         }
     }
 }
 
-// TODO remove allow dead_code on impl
-#[allow(dead_code, clippy::large_enum_variant)]
+#[allow(clippy::large_enum_variant)]
 /// State of the inbound substream, opened either by us or by the remote.
 enum InboundSubstreamState {
     /// Waiting for a message from the remote. The idle state for an inbound substream.
     WaitingInput(Framed<NegotiatedSubstream, GraphSyncCodec>),
     /// The substream is being closed.
     Closing(Framed<NegotiatedSubstream, GraphSyncCodec>),
-    /// An error occurred during processing.
-    Poisoned,
+}
+
+#[allow(clippy::large_enum_variant)]
+/// State of an outbound substream, which is closed once its message is sent.
+enum OutboundSubstreamState {
+    /// Waiting to send the message to the remote.
+    PendingSend(
+        Framed<NegotiatedSubstream, GraphSyncCodec>,
+        GraphSyncMessage,
+    ),
+    /// Waiting to flush the substream so the message is sent. Holds the requests of the message.
+    PendingFlush(Framed<NegotiatedSubstream, GraphSyncCodec>, Vec<RequestID>),
+    /// The substream is being closed.
+    Closing(Framed<NegotiatedSubstream, GraphSyncCodec>),
 }
 
 impl ProtocolsHandler for GraphSyncHandler {
     type InEvent = GraphSyncMessage;
-    type OutEvent = GraphSyncMessage;
+    type OutEvent = GraphSyncHandlerEvent;
     type Error = io::Error;
     type InboundProtocol = ProtocolConfig;
     type OutboundProtocol = ProtocolConfig;
@@ -112,17 +139,12 @@ impl ProtocolsHandler for GraphSyncHandler {
 
     fn inject_fully_negotiated_outbound(
         &mut self,
-        _out: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
-        _event: Self::OutboundOpenInfo,
+        out: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
+        message: Self::OutboundOpenInfo,
     ) {
         self.dial_negotiated -= 1;
-
-        if self.dial_negotiated == 0 && self.dial_queue.is_empty() {
-            self.keep_alive = KeepAlive::Until(Instant::now() + Duration::from_secs(TIMEOUT));
-        }
-
-        // TODO handle outbound when events are emitted from service
-        // self.events_out.push(out);
+        self.outbound_substreams
+            .push_back(OutboundSubstreamState::PendingSend(out, message));
     }
 
     fn inject_event(&mut self, event: Self::InEvent) {
@@ -131,12 +153,13 @@ impl ProtocolsHandler for GraphSyncHandler {
 
     fn inject_dial_upgrade_error(
         &mut self,
-        _: Self::OutboundOpenInfo,
+        message: Self::OutboundOpenInfo,
         error: ProtocolsHandlerUpgrErr<io::Error>,
     ) {
-        if self.pending_error.is_none() {
-            self.pending_error = Some(error);
-        }
+        // The message is dropped, the connection is kept for the other substreams
+        debug!("Failed to open a substream to send a message: {}", error);
+        self.dial_negotiated -= 1;
+        self.failed_requests.push_back(request_ids(&message));
     }
 
     fn connection_keep_alive(&self) -> KeepAlive {
@@ -146,7 +169,7 @@ impl ProtocolsHandler for GraphSyncHandler {
     #[allow(clippy::type_complexity)]
     fn poll(
         &mut self,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<
         ProtocolsHandlerEvent<
             Self::OutboundProtocol,
@@ -155,6 +178,138 @@ impl ProtocolsHandler for GraphSyncHandler {
             Self::Error,
         >,
     > {
-        todo!()
+        if let Some(ids) = self.failed_requests.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(
+                GraphSyncHandlerEvent::SendFailed(ids),
+            ));
+        }
+
+        // Open a substream for the next queued message
+        if !self.dial_queue.is_empty() && self.dial_negotiated < self.max_dial_negotiated {
+            self.dial_negotiated += 1;
+            return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                protocol: self.listen_protocol.clone(),
+                info: self.dial_queue.remove(0),
+            });
+        }
+
+        // Read the messages of the inbound substreams
+        for _ in 0..self.inbound_substreams.len() {
+            let state = match self.inbound_substreams.pop_front() {
+                Some(state) => state,
+                None => break,
+            };
+            match state {
+                InboundSubstreamState::WaitingInput(mut substream) => {
+                    match substream.poll_next_unpin(cx) {
+                        Poll::Ready(Some(Ok(message))) => {
+                            self.inbound_substreams
+                                .push_back(InboundSubstreamState::WaitingInput(substream));
+                            return Poll::Ready(ProtocolsHandlerEvent::Custom(
+                                GraphSyncHandlerEvent::Message(message),
+                            ));
+                        }
+                        Poll::Ready(Some(Err(e))) => {
+                            trace!("Inbound substream error: {}", e);
+                        }
+                        Poll::Ready(None) => {
+                            self.inbound_substreams
+                                .push_back(InboundSubstreamState::Closing(substream));
+                        }
+                        Poll::Pending => {
+                            self.inbound_substreams
+                                .push_back(InboundSubstreamState::WaitingInput(substream));
+                        }
+                    }
+                }
+                InboundSubstreamState::Closing(mut substream) => {
+                    if let Poll::Pending = Sink::poll_close(Pin::new(&mut substream), cx) {
+                        self.inbound_substreams
+                            .push_back(InboundSubstreamState::Closing(substream));
+                    }
+                }
+            }
+        }
+
+        // Send the messages on the outbound substreams, closing them once sent. Substreams
+        // failing to send their message are dropped and the failure reported.
+        for _ in 0..self.outbound_substreams.len() {
+            let state = match self.outbound_substreams.pop_front() {
+                Some(state) => state,
+                None => break,
+            };
+            let next = match state {
+                OutboundSubstreamState::PendingSend(mut substream, message) => {
+                    match Sink::poll_ready(Pin::new(&mut substream), cx) {
+                        Poll::Ready(Ok(())) => {
+                            let ids = request_ids(&message);
+                            match Sink::start_send(Pin::new(&mut substream), message) {
+                                Ok(()) => {
+                                    Some(OutboundSubstreamState::PendingFlush(substream, ids))
+                                }
+                                Err(e) => {
+                                    debug!("Failed to send message: {}", e);
+                                    self.failed_requests.push_back(ids);
+                                    None
+                                }
+                            }
+                        }
+                        Poll::Ready(Err(e)) => {
+                            debug!("Failed to send message: {}", e);
+                            self.failed_requests.push_back(request_ids(&message));
+                            None
+                        }
+                        Poll::Pending => {
+                            Some(OutboundSubstreamState::PendingSend(substream, message))
+                        }
+                    }
+                }
+                OutboundSubstreamState::PendingFlush(mut substream, ids) => {
+                    match Sink::poll_flush(Pin::new(&mut substream), cx) {
+                        Poll::Ready(Ok(())) => Some(OutboundSubstreamState::Closing(substream)),
+                        Poll::Ready(Err(e)) => {
+                            debug!("Failed to send message: {}", e);
+                            self.failed_requests.push_back(ids);
+                            None
+                        }
+                        Poll::Pending => Some(OutboundSubstreamState::PendingFlush(substream, ids)),
+                    }
+                }
+                OutboundSubstreamState::Closing(mut substream) => {
+                    match Sink::poll_close(Pin::new(&mut substream), cx) {
+                        Poll::Ready(_) => None,
+                        Poll::Pending => Some(OutboundSubstreamState::Closing(substream)),
+                    }
+                }
+            };
+            if let Some(state) = next {
+                self.outbound_substreams.push_back(state);
+            }
+        }
+
+        if let Some(ids) = self.failed_requests.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(
+                GraphSyncHandlerEvent::SendFailed(ids),
+            ));
+        }
+
+        // Keep the connection alive while messages are being sent
+        if self.dial_queue.is_empty()
+            && self.dial_negotiated == 0
+            && self.outbound_substreams.is_empty()
+        {
+            if let KeepAlive::Yes = self.keep_alive {
+                self.keep_alive = KeepAlive::Until(Instant::now() + Duration::from_secs(TIMEOUT));
+            }
+        } else {
+            self.keep_alive = KeepAlive::Yes;
+        }
+
+        Poll::Pending
     }
 }
+
+/// Returns the ids of the requests of a message.
+fn request_ids(message: &GraphSyncMessage) -> Vec<RequestID> {
+    message.requests().keys().copied().collect()
+}
@@ -9,4 +9,5 @@ mod protocol;
 
 pub use self::behaviour::*;
 pub use self::codec::*;
+pub use self::config::*;
 pub use self::handler::*;
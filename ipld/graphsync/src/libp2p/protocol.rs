@@ -24,7 +24,7 @@ impl Default for ProtocolConfig {
     fn default() -> Self {
         Self {
             protocol_id: Cow::Borrowed(b"/ipfs/graphsync/1.0.0"),
-            max_transmit_size: 2048,
+            max_transmit_size: 1 << 20,
         }
     }
 }
@@ -0,0 +1,48 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use async_trait::async_trait;
+use cid::{Cid, Codec};
+use forest_ipld::{selector::LinkResolver, Ipld};
+use ipld_blockstore::BlockStore;
+use std::sync::Arc;
+
+/// A block loader that loads the blocks from a blockstore.
+pub struct BlockStoreLoader<BS> {
+    blockstore: Arc<BS>,
+}
+
+impl<BS> BlockStoreLoader<BS>
+where
+    BS: BlockStore,
+{
+    /// Creates a loader of the blocks in the blockstore.
+    pub fn new(blockstore: Arc<BS>) -> Self {
+        Self { blockstore }
+    }
+
+    /// Loads the encoded block of the link, as it is sent over the wire.
+    pub fn load_block(&self, link: &Cid) -> Result<Option<Vec<u8>>, String> {
+        self.blockstore.get_bytes(link).map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl<BS> LinkResolver for BlockStoreLoader<BS>
+where
+    BS: BlockStore + Send + Sync,
+{
+    async fn load_link(&mut self, link: &Cid) -> Result<Option<Ipld>, String> {
+        self.load_block(link)?
+            .map(|block| decode_block(link, block))
+            .transpose()
+    }
+}
+
+/// Decodes a block into Ipld according to the codec of its link. Raw blocks are loaded as bytes.
+pub(crate) fn decode_block(link: &Cid, block: Vec<u8>) -> Result<Ipld, String> {
+    match link.codec {
+        Codec::Raw => Ok(Ipld::Bytes(block)),
+        _ => forest_encoding::from_slice(&block).map_err(|e| e.to_string()),
+    }
+}
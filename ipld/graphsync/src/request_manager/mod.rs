@@ -0,0 +1,516 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::loader::decode_block;
+use super::{
+    Extensions, GraphSyncMessage, GraphSyncRequest, MetadataItem, RequestID, ResponseStatusCode,
+    EXTENSION_METADATA,
+};
+use async_trait::async_trait;
+use cid::Cid;
+use fnv::FnvHashMap;
+use forest_ipld::{
+    selector::{LinkResolver, Selector},
+    Ipld,
+};
+use ipld_blockstore::BlockStore;
+use libp2p::core::PeerId;
+use log::debug;
+use std::collections::HashMap;
+
+/// Maximum number of bytes of the blocks received for a single request. Requests whose response
+/// exceeds it are failed and cancelled.
+pub const MAX_REQUEST_BYTES: usize = 256 * 1024 * 1024;
+
+/// State of a request sent to a peer, until its response terminates.
+struct InProgressRequest {
+    peer: PeerId,
+    root: Cid,
+    selector: Selector,
+    /// Blocks received for the request, only stored once verified against the selector.
+    blocks: HashMap<Cid, Vec<u8>>,
+    /// Total size of the received blocks.
+    bytes: usize,
+}
+
+/// Request sent to a peer whose response terminated.
+#[derive(Debug)]
+pub struct CompletedRequest {
+    pub peer: PeerId,
+    pub root: Cid,
+    pub selector: Selector,
+    pub status: ResponseStatusCode,
+    /// Blocks received for the request, to be verified with `store_verified_blocks`.
+    pub blocks: HashMap<Cid, Vec<u8>>,
+}
+
+/// Events emitted for the requests of a `RequestManager` as their responses are received.
+#[derive(Debug)]
+pub enum RequestEvent {
+    /// Part of the response was received.
+    Progress {
+        id: RequestID,
+        peer: PeerId,
+        blocks_received: usize,
+    },
+    /// The response terminated, successfully or not.
+    Completed {
+        id: RequestID,
+        request: CompletedRequest,
+    },
+}
+
+/// Issues GraphSync requests and collects the blocks of their responses.
+pub struct RequestManager {
+    next_id: RequestID,
+    requests: FnvHashMap<RequestID, InProgressRequest>,
+    /// Maximum number of bytes of the blocks received for a single request.
+    max_request_bytes: usize,
+    /// Cancel requests to send to the peers still responding to requests which were failed.
+    cancels: Vec<(PeerId, GraphSyncRequest)>,
+}
+
+impl Default for RequestManager {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            requests: FnvHashMap::default(),
+            max_request_bytes: MAX_REQUEST_BYTES,
+            cancels: Vec::new(),
+        }
+    }
+}
+
+impl RequestManager {
+    /// Creates a new request manager.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a request for the DAG under root matching the selector, to be sent to the peer.
+    pub fn new_request(
+        &mut self,
+        peer: PeerId,
+        root: Cid,
+        selector: Selector,
+        extensions: Extensions,
+    ) -> GraphSyncRequest {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.requests.insert(
+            id,
+            InProgressRequest {
+                peer,
+                root: root.clone(),
+                selector: selector.clone(),
+                blocks: HashMap::new(),
+                bytes: 0,
+            },
+        );
+        GraphSyncRequest::new(id, root, selector, 0, Some(extensions))
+    }
+
+    /// Cancels an in progress request, returning the peer and the cancel request to send to it.
+    pub fn cancel_request(&mut self, id: RequestID) -> Option<(PeerId, GraphSyncRequest)> {
+        self.requests
+            .remove(&id)
+            .map(|request| (request.peer, GraphSyncRequest::cancel(id)))
+    }
+
+    /// Returns true if the request is in progress.
+    pub fn is_in_progress(&self, id: RequestID) -> bool {
+        self.requests.contains_key(&id)
+    }
+
+    /// Collects the blocks of the responses of a message received from the peer.
+    pub fn process_message(
+        &mut self,
+        peer: &PeerId,
+        message: &GraphSyncMessage,
+    ) -> Vec<RequestEvent> {
+        let mut events = Vec::new();
+        for (id, response) in message.responses() {
+            let links: Vec<MetadataItem> = match response.extensions.get(EXTENSION_METADATA) {
+                Some(metadata) => match forest_encoding::from_slice(metadata) {
+                    Ok(links) => links,
+                    Err(e) => {
+                        debug!("Invalid metadata in response {} from {}: {}", id, peer, e);
+                        Vec::new()
+                    }
+                },
+                None => Vec::new(),
+            };
+
+            // Blocks sent for another request of the peer are not sent again
+            let mut blocks = Vec::new();
+            for item in links.into_iter().filter(|item| item.block_is_present) {
+                let block = message.blocks().get(&item.link).cloned().or_else(|| {
+                    self.requests
+                        .values()
+                        .filter(|r| &r.peer == peer)
+                        .find_map(|r| r.blocks.get(&item.link).cloned())
+                });
+                if let Some(block) = block {
+                    blocks.push((item.link, block));
+                }
+            }
+
+            let request = match self.requests.get_mut(id) {
+                Some(request) if &request.peer == peer => request,
+                _ => {
+                    debug!(
+                        "Ignoring response {} from {} to an unknown request",
+                        id, peer
+                    );
+                    continue;
+                }
+            };
+            for (link, block) in blocks {
+                let len = block.len();
+                if request.blocks.insert(link, block).is_none() {
+                    request.bytes += len;
+                }
+            }
+
+            if request.bytes > self.max_request_bytes {
+                debug!(
+                    "Response {} from {} exceeds {} bytes, failing the request",
+                    id, peer, self.max_request_bytes
+                );
+                let request = self.requests.remove(id).unwrap();
+                if !response.status.is_terminal() {
+                    self.cancels
+                        .push((request.peer.clone(), GraphSyncRequest::cancel(*id)));
+                }
+                events.push(RequestEvent::Completed {
+                    id: *id,
+                    request: CompletedRequest {
+                        peer: request.peer,
+                        root: request.root,
+                        selector: request.selector,
+                        status: ResponseStatusCode::RequestFailedUnknown,
+                        blocks: HashMap::new(),
+                    },
+                });
+            } else if response.status.is_terminal() {
+                let request = self.requests.remove(id).unwrap();
+                events.push(RequestEvent::Completed {
+                    id: *id,
+                    request: CompletedRequest {
+                        peer: request.peer,
+                        root: request.root,
+                        selector: request.selector,
+                        status: response.status,
+                        blocks: request.blocks,
+                    },
+                });
+            } else {
+                events.push(RequestEvent::Progress {
+                    id: *id,
+                    peer: peer.clone(),
+                    blocks_received: request.blocks.len(),
+                });
+            }
+        }
+        events
+    }
+
+    /// Returns the cancel requests to send for the requests failed while the peer was still
+    /// responding to them.
+    pub fn take_cancels(&mut self) -> Vec<(PeerId, GraphSyncRequest)> {
+        std::mem::take(&mut self.cancels)
+    }
+
+    /// Fails the in progress requests sent to a peer which disconnected.
+    pub fn peer_disconnected(&mut self, peer: &PeerId) -> Vec<RequestEvent> {
+        let ids: Vec<RequestID> = self
+            .requests
+            .iter()
+            .filter(|(_, r)| &r.peer == peer)
+            .map(|(id, _)| *id)
+            .collect();
+        self.send_failed(peer, &ids)
+    }
+
+    /// Fails the in progress requests with the given ids whose message could not be sent to
+    /// the peer.
+    pub fn send_failed(&mut self, peer: &PeerId, ids: &[RequestID]) -> Vec<RequestEvent> {
+        ids.iter()
+            .filter_map(|id| {
+                if self.requests.get(id)?.peer != *peer {
+                    return None;
+                }
+                let request = self.requests.remove(id)?;
+                Some(RequestEvent::Completed {
+                    id: *id,
+                    request: CompletedRequest {
+                        peer: request.peer,
+                        root: request.root,
+                        selector: request.selector,
+                        status: ResponseStatusCode::RequestFailedUnknown,
+                        blocks: request.blocks,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Walks the selector from the root of a completed request, storing the received blocks which
+/// are reached by the walk. Blocks the selector doesn't reach are discarded. Returns the number
+/// of blocks stored.
+pub async fn store_verified_blocks<BS>(
+    blockstore: &BS,
+    request: CompletedRequest,
+) -> Result<usize, String>
+where
+    BS: BlockStore + Send + Sync,
+{
+    let mut stored = 0;
+    let loader = VerifyingLoader {
+        blockstore,
+        blocks: request.blocks,
+        stored: &mut stored,
+    };
+    request
+        .selector
+        .walk_all(&Ipld::Link(request.root), Some(loader), |_, _, _| Ok(()))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(stored)
+}
+
+/// A block loader that loads the blocks received for a request, storing them as they are
+/// traversed, and falls back to the blocks already in the blockstore.
+struct VerifyingLoader<'a, BS> {
+    blockstore: &'a BS,
+    blocks: HashMap<Cid, Vec<u8>>,
+    stored: &'a mut usize,
+}
+
+#[async_trait]
+impl<'a, BS> LinkResolver for VerifyingLoader<'a, BS>
+where
+    BS: BlockStore + Send + Sync,
+{
+    async fn load_link(&mut self, link: &Cid) -> Result<Option<Ipld>, String> {
+        let bytes = match self.blocks.remove(link) {
+            Some(bytes) => {
+                self.blockstore
+                    .write(link.to_bytes(), &bytes)
+                    .map_err(|e| e.to_string())?;
+                *self.stored += 1;
+                bytes
+            }
+            None => match self.blockstore.get_bytes(link).map_err(|e| e.to_string())? {
+                Some(bytes) => bytes,
+                None => return Ok(None),
+            },
+        };
+        decode_block(link, bytes).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils, GraphSyncResponse};
+    use cid::multihash::Blake2b256;
+    use db::MemoryDB;
+
+    fn response_message(
+        id: RequestID,
+        status: ResponseStatusCode,
+        blocks: &[(Cid, Vec<u8>)],
+    ) -> GraphSyncMessage {
+        let metadata: Vec<MetadataItem> = blocks
+            .iter()
+            .map(|(link, _)| MetadataItem {
+                link: link.clone(),
+                block_is_present: true,
+            })
+            .collect();
+        let mut extensions = Extensions::new();
+        extensions.insert(
+            EXTENSION_METADATA.to_owned(),
+            forest_encoding::to_vec(&metadata).unwrap(),
+        );
+
+        let mut message = GraphSyncMessage::default();
+        message.insert_response(GraphSyncResponse::new(id, status, Some(extensions)));
+        for (cid, block) in blocks {
+            message.insert_block(cid.clone(), block.clone());
+        }
+        message
+    }
+
+    #[async_std::test]
+    async fn collect_and_verify_blocks() {
+        let peer = PeerId::random();
+        let leaf = forest_encoding::to_vec(&Ipld::String("leaf".to_owned())).unwrap();
+        let leaf_cid = Cid::new_from_cbor(&leaf, Blake2b256);
+        let root =
+            forest_encoding::to_vec(&Ipld::List(vec![Ipld::Link(leaf_cid.clone())])).unwrap();
+        let root_cid = Cid::new_from_cbor(&root, Blake2b256);
+        // Not reachable from the root, so never stored
+        let (extra, extra_links) = test_utils::random_blocks(1, 10);
+
+        let mut manager = RequestManager::new();
+        let request = manager.new_request(
+            peer.clone(),
+            root_cid.clone(),
+            Selector::ExploreAll {
+                next: Box::new(Selector::Matcher),
+            },
+            Extensions::new(),
+        );
+
+        let events = manager.process_message(
+            &peer,
+            &response_message(
+                request.id,
+                ResponseStatusCode::PartialResponse,
+                &[(root_cid.clone(), root)],
+            ),
+        );
+        match &events[..] {
+            [RequestEvent::Progress {
+                blocks_received: 1, ..
+            }] => (),
+            e => panic!("unexpected events: {:?}", e),
+        }
+
+        // Responses from other peers are ignored
+        let other = response_message(request.id, ResponseStatusCode::RequestCompletedFull, &[]);
+        assert!(manager
+            .process_message(&PeerId::random(), &other)
+            .is_empty());
+
+        let mut events = manager.process_message(
+            &peer,
+            &response_message(
+                request.id,
+                ResponseStatusCode::RequestCompletedFull,
+                &[
+                    (leaf_cid.clone(), leaf),
+                    (extra_links[0].clone(), extra[0].clone()),
+                ],
+            ),
+        );
+        let completed = match events.pop() {
+            Some(RequestEvent::Completed { request, .. }) => request,
+            e => panic!("unexpected event: {:?}", e),
+        };
+        assert_eq!(completed.status, ResponseStatusCode::RequestCompletedFull);
+        assert!(!manager.is_in_progress(request.id));
+
+        let store = MemoryDB::default();
+        assert_eq!(store_verified_blocks(&store, completed).await.unwrap(), 2);
+        assert!(store.get_bytes(&root_cid).unwrap().is_some());
+        assert!(store.get_bytes(&leaf_cid).unwrap().is_some());
+        assert!(store.get_bytes(&extra_links[0]).unwrap().is_none());
+    }
+
+    #[test]
+    fn fail_requests_exceeding_max_bytes() {
+        let peer = PeerId::random();
+        let mut manager = RequestManager {
+            max_request_bytes: 15,
+            ..Default::default()
+        };
+        let request = manager.new_request(
+            peer.clone(),
+            test_utils::random_cid(),
+            Selector::Matcher,
+            Extensions::new(),
+        );
+        let (blocks, links) = test_utils::random_blocks(2, 10);
+
+        let events = manager.process_message(
+            &peer,
+            &response_message(
+                request.id,
+                ResponseStatusCode::PartialResponse,
+                &[(links[0].clone(), blocks[0].clone())],
+            ),
+        );
+        match &events[..] {
+            [RequestEvent::Progress {
+                blocks_received: 1, ..
+            }] => (),
+            e => panic!("unexpected events: {:?}", e),
+        }
+        assert!(manager.take_cancels().is_empty());
+
+        // The second block exceeds the limit, so the request fails and is cancelled
+        let events = manager.process_message(
+            &peer,
+            &response_message(
+                request.id,
+                ResponseStatusCode::PartialResponse,
+                &[(links[1].clone(), blocks[1].clone())],
+            ),
+        );
+        match &events[..] {
+            [RequestEvent::Completed {
+                request: failed, ..
+            }] => {
+                assert_eq!(failed.status, ResponseStatusCode::RequestFailedUnknown);
+                assert!(failed.blocks.is_empty());
+            }
+            e => panic!("unexpected events: {:?}", e),
+        }
+        assert!(!manager.is_in_progress(request.id));
+        assert_eq!(
+            manager.take_cancels(),
+            vec![(peer, GraphSyncRequest::cancel(request.id))]
+        );
+    }
+
+    #[test]
+    fn fail_unsent_requests() {
+        let peer = PeerId::random();
+        let mut manager = RequestManager::new();
+        let request = manager.new_request(
+            peer.clone(),
+            test_utils::random_cid(),
+            Selector::Matcher,
+            Extensions::new(),
+        );
+
+        // Failures reported for another peer don't affect the request
+        assert!(manager
+            .send_failed(&PeerId::random(), &[request.id])
+            .is_empty());
+        match &manager.send_failed(&peer, &[request.id])[..] {
+            [RequestEvent::Completed {
+                id,
+                request: failed,
+            }] => {
+                assert_eq!(*id, request.id);
+                assert_eq!(failed.peer, peer);
+                assert_eq!(failed.status, ResponseStatusCode::RequestFailedUnknown);
+            }
+            e => panic!("unexpected events: {:?}", e),
+        }
+        assert!(!manager.is_in_progress(request.id));
+    }
+
+    #[test]
+    fn cancel_request() {
+        let peer = PeerId::random();
+        let mut manager = RequestManager::new();
+        let request = manager.new_request(
+            peer.clone(),
+            test_utils::random_cid(),
+            Selector::Matcher,
+            Extensions::new(),
+        );
+        assert!(manager.is_in_progress(request.id));
+
+        let (cancel_peer, cancel) = manager.cancel_request(request.id).unwrap();
+        assert_eq!(cancel_peer, peer);
+        assert_eq!(cancel, GraphSyncRequest::cancel(request.id));
+        assert!(manager.cancel_request(request.id).is_none());
+    }
+}
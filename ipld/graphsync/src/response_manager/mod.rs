@@ -8,166 +8,865 @@ mod peer_response_sender;
 mod response_builder;
 
 use link_tracker::LinkTracker;
-use peer_response_sender::{PeerMessageHandler, PeerResponseSender};
+use peer_response_sender::PeerResponseSender;
 use response_builder::ResponseBuilder;
 
+pub use peer_response_sender::PeerMessageHandler;
+
+use super::loader::decode_block;
 use super::{
-    Extensions, GraphSyncRequest, NewRequestPayload, Payload, RequestID, ResponseStatusCode,
+    BlockStoreLoader, Extensions, GraphSyncRequest, GraphSyncResponse, NewRequestPayload, Payload,
+    RequestID, ResponseStatusCode, EXTENSION_DO_NOT_SEND_CIDS, MAX_BLOCK_SIZE,
 };
 use async_trait::async_trait;
 use cid::Cid;
-use forest_ipld::{selector::LinkResolver, Ipld};
+use fnv::FnvHashMap;
+use forest_ipld::{selector::Selector, Ipld, PathSegment};
+use futures::channel::mpsc::{Sender, UnboundedReceiver};
+use futures::future::{self, FutureExt};
+use futures::{select_biased, SinkExt, StreamExt};
 use ipld_blockstore::BlockStore;
 use libp2p::core::PeerId;
-use std::{collections::HashMap, sync::Arc};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Configuration of a `ResponseManager`.
+#[derive(Debug, Clone)]
+pub struct ResponseManagerConfig {
+    /// Maximum number of requests of a single peer responded to at once. Further requests of
+    /// the peer are rejected as busy until one of them terminates.
+    pub max_in_progress_requests_per_peer: usize,
+    /// Maximum number of links traversed for a request before another request gets its turn.
+    pub max_links_per_step: usize,
+}
+
+impl Default for ResponseManagerConfig {
+    fn default() -> Self {
+        Self {
+            max_in_progress_requests_per_peer: 6,
+            max_links_per_step: 100,
+        }
+    }
+}
+
+/// Input of a response manager running on its own task with `ResponseManager::run`.
+#[derive(Debug)]
+pub enum ResponseManagerInput {
+    /// A peer sent a request, or an update or cancel of one of its requests.
+    Request {
+        peer: PeerId,
+        request: GraphSyncRequest,
+    },
+    /// A peer disconnected, dropping its requests.
+    PeerDisconnected(PeerId),
+    /// Pauses a request in progress, until it is unpaused or updated by the peer.
+    Pause { peer: PeerId, id: RequestID },
+    /// Resumes a request paused with `Pause`.
+    Unpause { peer: PeerId, id: RequestID },
+}
+
+/// Message built by a response manager running on its own task, to be sent to the peer.
+#[derive(Debug)]
+pub struct PeerMessage {
+    pub peer: PeerId,
+    pub responses: Vec<GraphSyncResponse>,
+    pub blocks: Vec<(Cid, Vec<u8>)>,
+}
+
+#[async_trait]
+impl PeerMessageHandler for Sender<PeerMessage> {
+    async fn send_response(
+        &mut self,
+        peer: &PeerId,
+        responses: Vec<GraphSyncResponse>,
+        blocks: Vec<(Cid, Vec<u8>)>,
+    ) {
+        let message = PeerMessage {
+            peer: peer.clone(),
+            responses,
+            blocks,
+        };
+        if self.send(message).await.is_err() {
+            debug!("Dropping graphsync message, the receiver is gone");
+        }
+    }
+}
+
+/// State of the response to a request between the steps of its traversal.
+struct InProgressResponse {
+    root: Cid,
+    /// Links left to traverse with the selector applying to their node. The last one is
+    /// traversed next, so the links are traversed in the order of a selector walk.
+    pending: Vec<(Cid, Selector)>,
+    /// Links the peer already has, reported as present without sending their blocks.
+    do_not_send: HashSet<Cid>,
+    paused: bool,
+}
+
+impl InProgressResponse {
+    /// Traverses up to `max_links` of the pending links, sending them and their blocks to the
+    /// peer. Returns true once all the links are traversed.
+    fn traverse<BS>(
+        &mut self,
+        loader: &BlockStoreLoader<BS>,
+        sender: &mut PeerResponseSender,
+        id: RequestID,
+        max_links: usize,
+    ) -> Result<bool, (ResponseStatusCode, String)>
+    where
+        BS: BlockStore,
+    {
+        for _ in 0..max_links {
+            let (link, selector) = match self.pending.pop() {
+                Some(next) => next,
+                None => break,
+            };
+            let block = match loader.load_block(&link) {
+                Ok(Some(block)) => block,
+                // the root is the first link traversed, no other link can refer to it
+                Ok(None) if link == self.root => {
+                    return Err((
+                        ResponseStatusCode::RequestFailedContentNotFound,
+                        "root not found".to_owned(),
+                    ))
+                }
+                Ok(None) => {
+                    sender.send_response(id, link, None);
+                    continue;
+                }
+                Err(e) => return Err((ResponseStatusCode::RequestFailedUnknown, e)),
+            };
+            if block.len() > MAX_BLOCK_SIZE {
+                return Err((
+                    ResponseStatusCode::RequestFailedUnknown,
+                    format!("Block {} exceeds the max block size", link),
+                ));
+            }
+            let node = decode_block(&link, block.clone())
+                .map_err(|e| (ResponseStatusCode::RequestFailedUnknown, e))?;
+            if self.do_not_send.contains(&link) {
+                sender.skip_block(id, link);
+            } else {
+                sender.send_response(id, link, Some(block));
+            }
+
+            let mut links = Vec::new();
+            collect_links(&node, selector, &mut links);
+            self.pending.extend(links.into_iter().rev());
+        }
+        Ok(self.pending.is_empty())
+    }
+}
+
+/// Collects the links reached by the selector within a node, along with the selector applying
+/// to the node they link to, in the order of a selector walk.
+fn collect_links(node: &Ipld, selector: Selector, links: &mut Vec<(Cid, Selector)>) {
+    let segments = match node {
+        Ipld::Link(link) => {
+            links.push((link.clone(), selector));
+            return;
+        }
+        Ipld::Map(map) => selector
+            .interests()
+            .unwrap_or_else(|| map.keys().map(|k| PathSegment::from(k.as_ref())).collect()),
+        Ipld::List(list) => selector
+            .interests()
+            .unwrap_or_else(|| (0..list.len()).map(PathSegment::from).collect()),
+        _ => return,
+    };
+    for segment in segments {
+        let child = match node.lookup_segment(&segment) {
+            Some(child) => child,
+            None => continue,
+        };
+        if let Some(next) = selector.clone().explore(node, &segment) {
+            collect_links(child, next, links);
+        }
+    }
+}
+
+/// Responses in progress for a peer.
+struct PeerResponses {
+    sender: PeerResponseSender,
+    responses: FnvHashMap<RequestID, InProgressResponse>,
+}
 
 /// Handles incoming graphsync requests from the network, initiates selector traversals, and transmits responses.
+/// Requests are traversed in steps taking turns, so a large request doesn't hold up the others,
+/// and can be paused between two steps.
 pub struct ResponseManager {
-    peer_response_senders: HashMap<PeerId, PeerResponseSender>,
+    config: ResponseManagerConfig,
+    peers: HashMap<PeerId, PeerResponses>,
+    /// Requests waiting for their next step.
+    queue: VecDeque<(PeerId, RequestID)>,
 }
 
 impl ResponseManager {
     /// Creates a new response manager.
-    pub fn new() -> Self {
+    pub fn new(config: ResponseManagerConfig) -> Self {
         Self {
-            peer_response_senders: HashMap::new(),
+            config,
+            peers: HashMap::new(),
+            queue: VecDeque::new(),
         }
     }
 
-    /// Returns the response sender associated with the given peer.
-    fn sender_for_peer(&mut self, peer: PeerId) -> &mut PeerResponseSender {
-        self.peer_response_senders
+    /// Returns the responses associated with the given peer.
+    fn responses_for_peer(&mut self, peer: PeerId) -> &mut PeerResponses {
+        self.peers
             .entry(peer.clone())
-            .or_insert_with(|| PeerResponseSender::new(peer))
+            .or_insert_with(|| PeerResponses {
+                sender: PeerResponseSender::new(peer),
+                responses: FnvHashMap::default(),
+            })
     }
 
-    /// Executes the given request.
-    pub async fn execute_request<L, H>(
+    /// Returns true if a request is waiting for its next step.
+    pub fn has_pending(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Processes a request of the peer. New requests are queued to be traversed with `run_step`.
+    pub async fn process_request<H>(
         &mut self,
         peer: PeerId,
         request: GraphSyncRequest,
-        loader: L,
         handler: &mut H,
     ) -> Result<(), String>
     where
-        L: LinkResolver + Send + Sync,
         H: PeerMessageHandler,
     {
         match request.payload {
-            Payload::New(payload) => {
-                self.new_request(peer, request.id, payload, loader, handler)
-                    .await
+            Payload::New(payload) => self.new_request(peer, request.id, payload, handler).await,
+            Payload::Update { extensions } => {
+                self.update_request(&peer, request.id, extensions);
+                Ok(())
+            }
+            Payload::Cancel => {
+                self.cancel_request(&peer, request.id);
+                Ok(())
             }
-            Payload::Update { extensions } => self.update_request(request.id, extensions).await,
-            Payload::Cancel => self.cancel_request(request.id).await,
         }
     }
 
-    /// Executes a new request.
-    async fn new_request<L, H>(
+    /// Queues a new request, unless the peer already has too many requests in progress.
+    async fn new_request<H>(
         &mut self,
-        peer_id: PeerId,
-        request_id: RequestID,
+        peer: PeerId,
+        id: RequestID,
         payload: NewRequestPayload,
-        mut loader: L,
         handler: &mut H,
     ) -> Result<(), String>
     where
-        L: LinkResolver + Send + Sync,
         H: PeerMessageHandler,
     {
-        // TODO: look for the do-not-send-cids extension
-        let NewRequestPayload { root, selector, .. } = payload;
-        let sender = self.sender_for_peer(peer_id);
+        let max_in_progress = self.config.max_in_progress_requests_per_peer;
+        let peer_responses = self.responses_for_peer(peer.clone());
+        if peer_responses.responses.contains_key(&id) {
+            debug!("Ignoring request {} from {} already in progress", id, peer);
+            return Ok(());
+        }
+        if peer_responses.responses.len() >= max_in_progress {
+            debug!(
+                "Too many requests in progress for {}, rejecting {}",
+                peer, id
+            );
+            peer_responses
+                .sender
+                .finish_request_with_error(id, ResponseStatusCode::RequestFailedBusy);
+            return peer_responses.sender.flush(handler).await;
+        }
 
-        let ipld: Ipld = match loader.load_link(&root).await? {
-            Some(ipld) => ipld,
-            None => {
-                sender.finish_request_with_error(
-                    request_id,
-                    ResponseStatusCode::RequestFailedContentNotFound,
+        let NewRequestPayload {
+            root,
+            selector,
+            extensions,
+            ..
+        } = payload;
+        let do_not_send = match do_not_send_cids(&extensions) {
+            Ok(cids) => cids,
+            Err(e) => {
+                debug!(
+                    "Invalid do-not-send-cids in request {} from {}: {}",
+                    id, peer, e
                 );
-                return sender.flush(handler).await;
+                peer_responses
+                    .sender
+                    .finish_request_with_error(id, ResponseStatusCode::RequestFailedUnknown);
+                return peer_responses.sender.flush(handler).await;
             }
         };
+        peer_responses.responses.insert(
+            id,
+            InProgressResponse {
+                root: root.clone(),
+                pending: vec![(root, selector)],
+                do_not_send,
+                paused: false,
+            },
+        );
+        self.queue.push_back((peer, id));
+        Ok(())
+    }
 
-        let intercepted = InterceptedLoader::new(loader, |cid, block| {
-            let data = block
-                .map(|ipld| forest_encoding::to_vec(ipld))
-                .transpose()
-                .map_err(|e| e.to_string())?;
-            sender.send_response(request_id, cid.clone(), data);
-            Ok(())
-        });
+    /// Updates a request in progress. Links of a do-not-send-cids extension are added to the
+    /// links not sent, and a paused request is resumed.
+    fn update_request(&mut self, peer: &PeerId, id: RequestID, extensions: Extensions) {
+        let response = match self
+            .peers
+            .get_mut(peer)
+            .and_then(|p| p.responses.get_mut(&id))
+        {
+            Some(response) => response,
+            None => {
+                debug!("Ignoring update of unknown request {} from {}", id, peer);
+                return;
+            }
+        };
+        match do_not_send_cids(&extensions) {
+            Ok(cids) => response.do_not_send.extend(cids),
+            Err(e) => debug!(
+                "Invalid do-not-send-cids in update {} from {}: {}",
+                id, peer, e
+            ),
+        }
+        if response.paused {
+            response.paused = false;
+            self.queue.push_back((peer.clone(), id));
+        }
+    }
 
-        // we ignore the callback parameters because we're only interested in the
-        // loaded blocks, which the intercepted loader takes care of
-        selector
-            .walk_all(&ipld, Some(intercepted), |_, _, _| Ok(()))
-            .await
-            .map_err(|e| e.to_string())?;
-        sender.flush(handler).await
+    /// Cancels a request in progress. Nothing more is sent for the request.
+    fn cancel_request(&mut self, peer: &PeerId, id: RequestID) {
+        if let Some(peer_responses) = self.peers.get_mut(peer) {
+            if peer_responses.responses.remove(&id).is_some() {
+                peer_responses.sender.cancel_request(id);
+                self.queue.retain(|(p, i)| !(p == peer && *i == id));
+            }
+        }
     }
 
-    /// Updates an ongoing request.
-    async fn update_request(
+    /// Pauses a request in progress before its next step, notifying the peer. The request is
+    /// resumed with `unpause_request`, or when the peer updates it.
+    pub async fn pause_request<H>(
         &mut self,
-        _id: RequestID,
-        _extensions: Extensions,
-    ) -> Result<(), String> {
-        // we can't implement this yet because requests are currently executed in one go
-        todo!()
+        peer: &PeerId,
+        id: RequestID,
+        handler: &mut H,
+    ) -> Result<(), String>
+    where
+        H: PeerMessageHandler,
+    {
+        let peer_responses = self
+            .peers
+            .get_mut(peer)
+            .ok_or_else(|| format!("No request in progress for {}", peer))?;
+        let response = peer_responses
+            .responses
+            .get_mut(&id)
+            .filter(|r| !r.paused)
+            .ok_or_else(|| format!("Request {} from {} is not in progress", id, peer))?;
+        response.paused = true;
+        self.queue.retain(|(p, i)| !(p == peer && *i == id));
+        peer_responses.sender.pause_request(id);
+        peer_responses.sender.flush(handler).await
     }
 
-    /// Cancels an ongoing request.
-    async fn cancel_request(&mut self, _id: RequestID) -> Result<(), String> {
-        // we can't implement this yet because requests are currently executed in one go
-        todo!()
+    /// Resumes a paused request.
+    pub fn unpause_request(&mut self, peer: &PeerId, id: RequestID) -> Result<(), String> {
+        let response = self
+            .peers
+            .get_mut(peer)
+            .and_then(|p| p.responses.get_mut(&id))
+            .filter(|r| r.paused)
+            .ok_or_else(|| format!("Request {} from {} is not paused", id, peer))?;
+        response.paused = false;
+        self.queue.push_back((peer.clone(), id));
+        Ok(())
     }
-}
 
-/// A block loader that wraps another loader and calls a callback whenever
-/// a link is loaded with the cid and the corresponding block.
-struct InterceptedLoader<L, F> {
-    loader: L,
-    f: F,
-}
+    /// Drops the requests of a peer which disconnected.
+    pub fn peer_disconnected(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+        self.queue.retain(|(p, _)| p != peer);
+    }
+
+    /// Runs a step of the traversal of the next request in the queue, sending up to
+    /// `max_links_per_step` links and their blocks to the peer. The request goes back at the end
+    /// of the queue until its traversal is complete, resuming from the links left pending.
+    pub async fn run_step<BS, H>(
+        &mut self,
+        loader: &BlockStoreLoader<BS>,
+        handler: &mut H,
+    ) -> Result<(), String>
+    where
+        BS: BlockStore,
+        H: PeerMessageHandler,
+    {
+        let (peer, id) = match self.queue.pop_front() {
+            Some(next) => next,
+            None => return Ok(()),
+        };
+        let max_links = self.config.max_links_per_step;
+        let peer_responses = match self.peers.get_mut(&peer) {
+            Some(peer_responses) => peer_responses,
+            None => return Ok(()),
+        };
+        let response = match peer_responses.responses.get_mut(&id) {
+            Some(response) => response,
+            None => return Ok(()),
+        };
+        let sender = &mut peer_responses.sender;
 
-impl<L, F> InterceptedLoader<L, F>
-where
-    L: LinkResolver + Send + Sync,
-    F: FnMut(&Cid, Option<&Ipld>) -> Result<(), String> + Send + Sync,
-{
-    fn new(loader: L, f: F) -> Self {
-        Self { loader, f }
+        let result = match response.traverse(loader, sender, id, max_links) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.queue.push_back((peer, id));
+                return sender.flush(handler).await;
+            }
+            Err(e) => Err(e),
+        };
+
+        peer_responses.responses.remove(&id);
+        let sender = &mut peer_responses.sender;
+        match result {
+            Ok(()) => {
+                sender.finish_request(id);
+                sender.flush(handler).await
+            }
+            Err((status, e)) => {
+                sender.finish_request_with_error(id, status);
+                sender.flush(handler).await?;
+                Err(format!(
+                    "Failed to respond to request {} from {}: {}",
+                    id, peer, e
+                ))
+            }
+        }
     }
-}
 
-#[async_trait]
-impl<L, F> LinkResolver for InterceptedLoader<L, F>
-where
-    L: LinkResolver + Send + Sync,
-    F: FnMut(&Cid, Option<&Ipld>) -> Result<(), String> + Send + Sync,
-{
-    async fn load_link(&mut self, link: &Cid) -> Result<Option<Ipld>, String> {
-        let ipld = self.loader.load_link(link).await?;
-        (self.f)(link, ipld.as_ref())?;
-        Ok(ipld)
+    /// Runs the response manager until the input is closed, processing the requests of the
+    /// input and running the steps of their traversals in turns. The messages built are sent
+    /// over the output, so the blocks are loaded outside of the task handling the network.
+    /// Pending inputs are handled before the next step, so a pause takes effect right away.
+    pub async fn run<BS>(
+        mut self,
+        loader: BlockStoreLoader<BS>,
+        mut input: UnboundedReceiver<ResponseManagerInput>,
+        mut output: Sender<PeerMessage>,
+    ) where
+        BS: BlockStore,
+    {
+        loop {
+            let mut step = if self.has_pending() {
+                future::ready(()).left_future()
+            } else {
+                future::pending().right_future()
+            }
+            .fuse();
+
+            select_biased! {
+                msg = input.next() => match msg {
+                    Some(msg) => self.handle_input(msg, &mut output).await,
+                    None => break,
+                },
+                _ = step => {
+                    if let Err(e) = self.run_step(&loader, &mut output).await {
+                        debug!("{}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handles an input of a response manager running with `run`.
+    async fn handle_input(&mut self, msg: ResponseManagerInput, output: &mut Sender<PeerMessage>) {
+        match msg {
+            ResponseManagerInput::Request { peer, request } => {
+                if let Err(e) = self.process_request(peer, request, output).await {
+                    warn!("Failed to process graphsync request: {}", e);
+                }
+            }
+            ResponseManagerInput::PeerDisconnected(peer) => self.peer_disconnected(&peer),
+            ResponseManagerInput::Pause { peer, id } => {
+                if let Err(e) = self.pause_request(&peer, id, output).await {
+                    warn!("Failed to pause graphsync request: {}", e);
+                }
+            }
+            ResponseManagerInput::Unpause { peer, id } => {
+                if let Err(e) = self.unpause_request(&peer, id) {
+                    warn!("Failed to unpause graphsync request: {}", e);
+                }
+            }
+        }
     }
 }
 
-/// A block loader that loads the blocks from a blockstore.
-// TODO: put this type somewhere else, graphsync doesn't need to know about blockstores
-struct BlockStoreLoader<BS> {
-    blockstore: Arc<BS>,
+/// Returns the links of the do-not-send-cids extension, if present.
+fn do_not_send_cids(extensions: &Extensions) -> Result<HashSet<Cid>, String> {
+    match extensions.get(EXTENSION_DO_NOT_SEND_CIDS) {
+        Some(data) => forest_encoding::from_slice::<Vec<Cid>>(data)
+            .map(|cids| cids.into_iter().collect())
+            .map_err(|e| e.to_string()),
+        None => Ok(HashSet::new()),
+    }
 }
 
-#[async_trait]
-impl<BS> LinkResolver for BlockStoreLoader<BS>
-where
-    BS: BlockStore + Send + Sync,
-{
-    async fn load_link(&mut self, link: &Cid) -> Result<Option<Ipld>, String> {
-        self.blockstore.get(link).map_err(|e| e.to_string())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GraphSyncResponse, MetadataItem, EXTENSION_METADATA};
+    use async_std::task;
+    use cid::multihash::Blake2b256;
+    use db::MemoryDB;
+    use forest_ipld::selector::RecursionLimit;
+    use futures::channel::mpsc::{channel, unbounded};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    type Sent = (Vec<GraphSyncResponse>, Vec<(Cid, Vec<u8>)>);
+
+    #[derive(Default)]
+    struct Handler(Vec<Sent>);
+
+    impl Handler {
+        fn take(&mut self) -> Vec<Sent> {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    #[async_trait]
+    impl PeerMessageHandler for Handler {
+        async fn send_response(
+            &mut self,
+            _peer: &PeerId,
+            responses: Vec<GraphSyncResponse>,
+            blocks: Vec<(Cid, Vec<u8>)>,
+        ) {
+            self.0.push((responses, blocks));
+        }
+    }
+
+    /// Stores a root linking to two leaves, returning the root and leaf links.
+    fn store_dag(store: &MemoryDB) -> (Cid, Vec<Cid>) {
+        let leaves: Vec<Cid> = ["left", "right"]
+            .iter()
+            .map(|s| store.put(&Ipld::String(s.to_string()), Blake2b256).unwrap())
+            .collect();
+        let root = Ipld::List(leaves.iter().cloned().map(Ipld::Link).collect());
+        (store.put(&root, Blake2b256).unwrap(), leaves)
+    }
+
+    fn new_request(id: RequestID, root: Cid, extensions: Extensions) -> GraphSyncRequest {
+        let selector = Selector::ExploreAll {
+            next: Box::new(Selector::Matcher),
+        };
+        GraphSyncRequest::new(id, root, selector, 0, Some(extensions))
+    }
+
+    fn metadata(response: &GraphSyncResponse) -> Vec<MetadataItem> {
+        forest_encoding::from_slice(&response.extensions[EXTENSION_METADATA]).unwrap()
+    }
+
+    #[async_std::test]
+    async fn respond_in_steps() {
+        let store = Arc::new(MemoryDB::default());
+        let (root, leaves) = store_dag(&store);
+        let loader = BlockStoreLoader::new(store);
+        let peer = PeerId::random();
+        let mut handler = Handler::default();
+        let mut manager = ResponseManager::new(ResponseManagerConfig {
+            max_links_per_step: 2,
+            ..Default::default()
+        });
+
+        let mut extensions = Extensions::new();
+        extensions.insert(
+            EXTENSION_DO_NOT_SEND_CIDS.to_owned(),
+            forest_encoding::to_vec(&vec![leaves[1].clone()]).unwrap(),
+        );
+        manager
+            .process_request(
+                peer.clone(),
+                new_request(0, root.clone(), extensions),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+        assert!(manager.has_pending());
+
+        // the root and the first leaf are sent in the first step
+        manager.run_step(&loader, &mut handler).await.unwrap();
+        let messages = handler.take();
+        assert_eq!(messages.len(), 1);
+        let (responses, blocks) = &messages[0];
+        assert_eq!(responses[0].status, ResponseStatusCode::PartialResponse);
+        let links: Vec<Cid> = blocks.iter().map(|(link, _)| link.clone()).collect();
+        assert_eq!(links, vec![root.clone(), leaves[0].clone()]);
+        assert!(manager.has_pending());
+
+        // the second leaf is in the metadata but its block is not sent
+        manager.run_step(&loader, &mut handler).await.unwrap();
+        let messages = handler.take();
+        let (responses, blocks) = &messages[0];
+        assert_eq!(
+            responses[0].status,
+            ResponseStatusCode::RequestCompletedFull
+        );
+        assert!(blocks.is_empty());
+        assert_eq!(
+            metadata(&responses[0]),
+            vec![MetadataItem {
+                link: leaves[1].clone(),
+                block_is_present: true
+            }]
+        );
+        assert!(!manager.has_pending());
+    }
+
+    #[async_std::test]
+    async fn resume_traversal_between_steps() {
+        let store = Arc::new(MemoryDB::default());
+        let (middle, mut leaves) = store_dag(&store);
+        let last = store
+            .put(&Ipld::String("last".to_owned()), Blake2b256)
+            .unwrap();
+        let root = store
+            .put(
+                &Ipld::List(vec![Ipld::Link(middle.clone()), Ipld::Link(last.clone())]),
+                Blake2b256,
+            )
+            .unwrap();
+        let loader = BlockStoreLoader::new(store);
+        let peer = PeerId::random();
+        let mut handler = Handler::default();
+        let mut manager = ResponseManager::new(ResponseManagerConfig {
+            max_links_per_step: 1,
+            ..Default::default()
+        });
+        let selector = Selector::ExploreRecursive {
+            limit: RecursionLimit::None,
+            sequence: Box::new(Selector::ExploreAll {
+                next: Box::new(Selector::ExploreRecursiveEdge),
+            }),
+            stop_at: None,
+            current: None,
+        };
+        manager
+            .process_request(
+                peer,
+                GraphSyncRequest::new(0, root.clone(), selector, 0, None),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+
+        // each step sends the next link of the walk, without sending the previous ones again
+        let mut links = Vec::new();
+        while manager.has_pending() {
+            manager.run_step(&loader, &mut handler).await.unwrap();
+            for (_, blocks) in handler.take() {
+                links.extend(blocks.into_iter().map(|(link, _)| link));
+            }
+        }
+        let mut expected = vec![root, middle];
+        expected.append(&mut leaves);
+        expected.push(last);
+        assert_eq!(links, expected);
+    }
+
+    #[async_std::test]
+    async fn respond_on_own_task() {
+        let store = Arc::new(MemoryDB::default());
+        let (root, _) = store_dag(&store);
+        let peer = PeerId::random();
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, mut output_rx) = channel(1);
+        let running = task::spawn(ResponseManager::new(Default::default()).run(
+            BlockStoreLoader::new(store),
+            input_rx,
+            output_tx,
+        ));
+
+        input_tx
+            .unbounded_send(ResponseManagerInput::Request {
+                peer: peer.clone(),
+                request: new_request(0, root, Extensions::new()),
+            })
+            .unwrap();
+        let message = output_rx.next().await.unwrap();
+        assert_eq!(message.peer, peer);
+        assert_eq!(
+            message.responses[0].status,
+            ResponseStatusCode::RequestCompletedFull
+        );
+        assert_eq!(message.blocks.len(), 3);
+
+        // the task ends once its input is closed
+        drop(input_tx);
+        running.await;
+    }
+
+    #[async_std::test]
+    async fn pause_and_unpause_on_own_task() {
+        let store = Arc::new(MemoryDB::default());
+        let (root, _) = store_dag(&store);
+        let peer = PeerId::random();
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, mut output_rx) = channel(1);
+
+        // queued before the task starts, so the request is paused before its first step
+        input_tx
+            .unbounded_send(ResponseManagerInput::Request {
+                peer: peer.clone(),
+                request: new_request(0, root, Extensions::new()),
+            })
+            .unwrap();
+        input_tx
+            .unbounded_send(ResponseManagerInput::Pause {
+                peer: peer.clone(),
+                id: 0,
+            })
+            .unwrap();
+        let running = task::spawn(ResponseManager::new(Default::default()).run(
+            BlockStoreLoader::new(store),
+            input_rx,
+            output_tx,
+        ));
+
+        let message = output_rx.next().await.unwrap();
+        assert_eq!(
+            message.responses[0].status,
+            ResponseStatusCode::RequestPaused
+        );
+        assert!(message.blocks.is_empty());
+        assert!(
+            async_std::future::timeout(Duration::from_millis(100), output_rx.next())
+                .await
+                .is_err()
+        );
+
+        input_tx
+            .unbounded_send(ResponseManagerInput::Unpause {
+                peer: peer.clone(),
+                id: 0,
+            })
+            .unwrap();
+        let message = output_rx.next().await.unwrap();
+        assert_eq!(
+            message.responses[0].status,
+            ResponseStatusCode::RequestCompletedFull
+        );
+        assert_eq!(message.blocks.len(), 3);
+
+        drop(input_tx);
+        running.await;
+    }
+
+    #[async_std::test]
+    async fn reject_busy_and_missing_requests() {
+        let store = Arc::new(MemoryDB::default());
+        let (root, _) = store_dag(&store);
+        let loader = BlockStoreLoader::new(store);
+        let peer = PeerId::random();
+        let mut handler = Handler::default();
+        let mut manager = ResponseManager::new(ResponseManagerConfig {
+            max_in_progress_requests_per_peer: 1,
+            ..Default::default()
+        });
+
+        manager
+            .process_request(
+                peer.clone(),
+                new_request(0, root.clone(), Extensions::new()),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+        manager
+            .process_request(
+                peer.clone(),
+                new_request(1, root, Extensions::new()),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+        let messages = handler.take();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0[0].id, 1);
+        assert_eq!(
+            messages[0].0[0].status,
+            ResponseStatusCode::RequestFailedBusy
+        );
+
+        // requests of other peers are not limited by the requests of the first one
+        let other = PeerId::random();
+        manager
+            .process_request(
+                other,
+                new_request(0, crate::test_utils::random_cid(), Extensions::new()),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+        assert!(handler.take().is_empty());
+
+        manager.run_step(&loader, &mut handler).await.unwrap();
+        assert!(manager.run_step(&loader, &mut handler).await.is_err());
+        let statuses: Vec<ResponseStatusCode> = handler
+            .take()
+            .into_iter()
+            .map(|(responses, _)| responses[0].status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ResponseStatusCode::RequestCompletedFull,
+                ResponseStatusCode::RequestFailedContentNotFound
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn pause_and_cancel_requests() {
+        let store = Arc::new(MemoryDB::default());
+        let (root, _) = store_dag(&store);
+        let loader = BlockStoreLoader::new(store);
+        let peer = PeerId::random();
+        let mut handler = Handler::default();
+        let mut manager = ResponseManager::new(Default::default());
+
+        manager
+            .process_request(
+                peer.clone(),
+                new_request(0, root.clone(), Extensions::new()),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+        manager.pause_request(&peer, 0, &mut handler).await.unwrap();
+        assert!(!manager.has_pending());
+        let messages = handler.take();
+        assert_eq!(messages[0].0[0].status, ResponseStatusCode::RequestPaused);
+
+        // an update from the peer resumes the request
+        manager
+            .process_request(
+                peer.clone(),
+                GraphSyncRequest::update(0, Extensions::new()),
+                &mut handler,
+            )
+            .await
+            .unwrap();
+        assert!(manager.has_pending());
+        assert!(manager.unpause_request(&peer, 0).is_err());
+
+        manager
+            .process_request(peer.clone(), GraphSyncRequest::cancel(0), &mut handler)
+            .await
+            .unwrap();
+        assert!(!manager.has_pending());
+        manager.run_step(&loader, &mut handler).await.unwrap();
+        assert!(handler.take().is_empty());
     }
 }
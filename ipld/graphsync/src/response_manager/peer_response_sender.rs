@@ -38,16 +38,22 @@ impl PeerResponseSender {
             .record_link_traversal(id, link.clone(), block_is_present);
 
         let builder = self.response_builder(block_size);
-        builder.add_link(id, link, block_is_present);
+        builder.add_link(id, link.clone(), block_is_present);
 
         if let Some(block) = block {
-            builder.add_block(block);
+            builder.add_block(link, block);
             true
         } else {
             false
         }
     }
 
+    /// Adds the given link to the response as present without sending its block, because the
+    /// peer asked not to send it.
+    pub fn skip_block(&mut self, id: RequestID, link: Cid) {
+        self.response_builder(0).add_link(id, link, true);
+    }
+
     /// Adds the given extension data to to the response.
     pub fn send_extension_data(&mut self, id: RequestID, extension_data: ExtensionData) {
         // we pass 0 as the block size since we're not adding any blocks to the response
@@ -72,6 +78,12 @@ impl PeerResponseSender {
         self.response_builder(0).complete(id, status);
     }
 
+    /// Forgets the links traversed by the given request ID without sending a response, for
+    /// requests cancelled by the peer.
+    pub fn cancel_request(&mut self, id: RequestID) {
+        self.link_tracker.finish_request(id);
+    }
+
     /// Marks the given request ID as paused.
     pub fn pause_request(&mut self, id: RequestID) {
         self.response_builder(0)
@@ -113,7 +125,7 @@ pub trait PeerMessageHandler {
         &mut self,
         peer: &PeerId,
         responses: Vec<GraphSyncResponse>,
-        blocks: Vec<Vec<u8>>,
+        blocks: Vec<(Cid, Vec<u8>)>,
     );
 }
 
@@ -122,14 +134,16 @@ mod tests {
     use super::*;
     use crate::test_utils;
 
-    struct Handler(Vec<(Vec<GraphSyncResponse>, Vec<Vec<u8>>)>);
+    type Sent = (Vec<GraphSyncResponse>, Vec<(Cid, Vec<u8>)>);
+
+    struct Handler(Vec<Sent>);
 
     impl Handler {
         fn new() -> Self {
             Self(Vec::new())
         }
 
-        fn take(&mut self) -> Vec<(Vec<GraphSyncResponse>, Vec<Vec<u8>>)> {
+        fn take(&mut self) -> Vec<Sent> {
             std::mem::take(&mut self.0)
         }
     }
@@ -140,7 +154,7 @@ mod tests {
             &mut self,
             _peer: &PeerId,
             responses: Vec<GraphSyncResponse>,
-            blocks: Vec<Vec<u8>>,
+            blocks: Vec<(Cid, Vec<u8>)>,
         ) {
             self.0.push((responses, blocks));
         }
@@ -169,7 +183,7 @@ mod tests {
         assert_eq!(responses[0].status, ResponseStatusCode::PartialResponse);
 
         assert_eq!(blocks.len(), 1);
-        assert_eq!(blocks[0], data[0]);
+        assert_eq!(blocks[0], (links[0].clone(), data[0].clone()));
 
         // we traverse the same block as part of a different request while the first request
         // is still in progress, so this one should not be sent
@@ -200,7 +214,7 @@ mod tests {
         assert_eq!(responses[1].status, ResponseStatusCode::PartialResponse);
 
         assert_eq!(blocks.len(), 1);
-        assert_eq!(blocks[0], data[1]);
+        assert_eq!(blocks[0], (links[1].clone(), data[1].clone()));
 
         let is_sent = sender.send_response(request_ids[1], links[3].clone(), Some(data[3].clone()));
         assert!(is_sent);
@@ -226,8 +240,8 @@ mod tests {
         assert_eq!(responses[1].status, ResponseStatusCode::PartialResponse);
 
         assert_eq!(blocks.len(), 2);
-        assert_eq!(blocks[0], data[3]);
-        assert_eq!(blocks[1], data[4]);
+        assert_eq!(blocks[0], (links[3].clone(), data[3].clone()));
+        assert_eq!(blocks[1], (links[4].clone(), data[4].clone()));
 
         // this block has already been sent to the peer but that request has already
         // been completed
@@ -249,7 +263,7 @@ mod tests {
         assert_eq!(responses[0].status, ResponseStatusCode::PartialResponse);
 
         assert_eq!(blocks.len(), 1);
-        assert_eq!(blocks[0], data[0]);
+        assert_eq!(blocks[0], (links[0].clone(), data[0].clone()));
     }
 
     #[async_std::test]
@@ -291,7 +305,7 @@ mod tests {
             assert_eq!(responses[0].status, status);
 
             assert_eq!(blocks.len(), 1);
-            assert_eq!(blocks[0], data[i]);
+            assert_eq!(blocks[0], (links[i].clone(), data[i].clone()));
         }
     }
 
@@ -315,7 +329,7 @@ mod tests {
         assert_eq!(responses[0].status, ResponseStatusCode::PartialResponse);
 
         assert_eq!(blocks.len(), 1);
-        assert_eq!(blocks[0], data[0]);
+        assert_eq!(blocks[0], (links[0].clone(), data[0].clone()));
 
         let extension1 = ExtensionData {
             name: "AppleSauce/McGee".to_string(),
@@ -13,8 +13,8 @@ use fnv::FnvHashMap;
 /// message components once responses are ready to send.
 #[derive(Default)]
 pub struct ResponseBuilder {
-    /// The actual blocks that will be sent to the peer.
-    blocks: Vec<Vec<u8>>,
+    /// The actual blocks that will be sent to the peer, with their links.
+    blocks: Vec<(Cid, Vec<u8>)>,
 
     /// The combined block size of this message, i.e. the sum of the lengths
     /// of all included blocks.
@@ -46,9 +46,9 @@ impl ResponseBuilder {
     }
 
     /// Adds the given block to the message.
-    pub fn add_block(&mut self, block: Vec<u8>) {
+    pub fn add_block(&mut self, link: Cid, block: Vec<u8>) {
         self.block_size += block.len();
-        self.blocks.push(block);
+        self.blocks.push((link, block));
     }
 
     /// Adds the given link and whether its block is present to the response for
@@ -88,7 +88,8 @@ impl ResponseBuilder {
     }
 
     /// Assembles and encodes response data from the added requests, links, and blocks.
-    pub fn build(self) -> Result<(Vec<GraphSyncResponse>, Vec<Vec<u8>>), String> {
+    #[allow(clippy::type_complexity)]
+    pub fn build(self) -> Result<(Vec<GraphSyncResponse>, Vec<(Cid, Vec<u8>)>), String> {
         let mut extensions = self.extensions;
         let completed_responses = self.completed_responses;
 
@@ -143,8 +144,8 @@ mod tests {
 
         builder.complete(request_ids[3], ResponseStatusCode::RequestCompletedFull);
 
-        for block in &data {
-            builder.add_block(block.clone());
+        for (link, block) in links.iter().zip(&data) {
+            builder.add_block(link.clone(), block.clone());
         }
 
         assert_eq!(builder.block_size(), 300);
@@ -163,6 +164,7 @@ mod tests {
         builder.add_extension_data(request_ids[2], extension2.clone());
 
         let (mut responses, blocks) = builder.build().unwrap();
+        let blocks: Vec<_> = blocks.into_iter().map(|(_, block)| block).collect();
         assert_eq!(blocks, data);
         assert_eq!(responses.len(), 4);
         responses.sort_by_key(|r| r.id);
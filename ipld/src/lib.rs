@@ -107,7 +107,8 @@ pub enum Ipld {
 }
 
 impl Ipld {
-    pub(crate) fn lookup_segment(&self, segment: &PathSegment) -> Option<&Self> {
+    /// Returns the node at the segment of a path, if the node is a map or list holding one.
+    pub fn lookup_segment(&self, segment: &PathSegment) -> Option<&Self> {
         match self {
             Self::Map(map) => match segment {
                 PathSegment::String(s) => map.get(s),
@@ -0,0 +1,225 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::errors::Error;
+use super::{KeyInfo, KeyStore, PersistentKeyStore, KEYSTORE_NAME};
+use argon2::{Config, ThreadMode, Variant, Version};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use log::{info, warn};
+use rand::{rngs::OsRng, RngCore};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+const ENCRYPTED_KEYSTORE_NAME: &str = "/keystore.enc";
+
+/// Version of the encrypted keystore file layout, stored as the first byte of the file
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 1 + SALT_LEN + NONCE_LEN;
+
+/// KeyStore that persists data encrypted with a key derived from a passphrase.
+///
+/// The key is derived with Argon2id and the whole serialized keystore is encrypted with
+/// XChaCha20-Poly1305. The file layout is `version || salt || nonce || ciphertext`.
+pub struct EncryptedKeyStore {
+    key_info: HashMap<String, KeyInfo>,
+    location: String,
+    salt: [u8; SALT_LEN],
+    cipher: XChaCha20Poly1305,
+}
+
+impl EncryptedKeyStore {
+    /// Opens the encrypted keystore within the `location` directory using the passphrase.
+    /// If no encrypted keystore exists but a plaintext `keystore.json` does, its keys are
+    /// migrated into a new encrypted keystore and the plaintext file is removed.
+    pub fn new(location: String, passphrase: &str) -> Result<Self, Error> {
+        let path = format!("{}{}", location, ENCRYPTED_KEYSTORE_NAME);
+        match fs::read(&path) {
+            Ok(bz) => Self::decrypt(bz, location, passphrase),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let mut store = Self {
+                    key_info: HashMap::new(),
+                    cipher: derive_cipher(passphrase, &salt)?,
+                    location,
+                    salt,
+                };
+
+                let plaintext_path = format!("{}{}", store.location, KEYSTORE_NAME);
+                if Path::new(&plaintext_path).exists() {
+                    info!("Migrating plaintext keystore to encrypted keystore");
+                    store.key_info = PersistentKeyStore::new(store.location.clone())?.key_info;
+                    store.flush()?;
+                    fs::remove_file(&plaintext_path)?;
+                } else {
+                    warn!("keystore.enc does not exist, initializing new encrypted keystore");
+                }
+                Ok(store)
+            }
+            Err(e) => Err(Error::Other(e.to_string())),
+        }
+    }
+
+    fn decrypt(bz: Vec<u8>, location: String, passphrase: &str) -> Result<Self, Error> {
+        if bz.len() < HEADER_LEN || bz[0] != FORMAT_VERSION {
+            return Err(Error::Other("invalid encrypted keystore file".to_owned()));
+        }
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&bz[1..1 + SALT_LEN]);
+        let nonce = XNonce::from_slice(&bz[1 + SALT_LEN..HEADER_LEN]);
+
+        let cipher = derive_cipher(passphrase, &salt)?;
+        let plaintext = cipher.decrypt(nonce, &bz[HEADER_LEN..]).map_err(|_| {
+            Error::Other("failed to decrypt keystore, passphrase may be incorrect".to_owned())
+        })?;
+        let key_info = serde_json::from_slice(&plaintext)
+            .map_err(|e| Error::Other(format!("failed to deserialize keystore: {}", e)))?;
+
+        Ok(Self {
+            key_info,
+            location,
+            salt,
+            cipher,
+        })
+    }
+
+    /// Encrypts and writes the keystore to disk, using a fresh nonce on every write.
+    pub fn flush(&self) -> Result<(), Error> {
+        fs::create_dir_all(&self.location)?;
+
+        let plaintext = serde_json::to_vec(&self.key_info)
+            .map_err(|e| Error::Other(format!("failed to serialize key info: {}", e)))?;
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| Error::Other("failed to encrypt keystore".to_owned()))?;
+
+        let mut bz = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        bz.push(FORMAT_VERSION);
+        bz.extend_from_slice(&self.salt);
+        bz.extend_from_slice(&nonce);
+        bz.extend_from_slice(&ciphertext);
+
+        // Write to a temporary file first, so a crash never leaves a truncated keystore
+        let path = format!("{}{}", self.location, ENCRYPTED_KEYSTORE_NAME);
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, bz)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Derives the symmetric encryption key from the passphrase using Argon2id.
+fn derive_cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, Error> {
+    let config = Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: 65536,
+        time_cost: 3,
+        lanes: 4,
+        thread_mode: ThreadMode::Parallel,
+        hash_length: 32,
+        ..Config::default()
+    };
+    let key = argon2::hash_raw(passphrase.as_bytes(), salt, &config)
+        .map_err(|e| Error::Other(format!("failed to derive keystore key: {}", e)))?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+impl KeyStore for EncryptedKeyStore {
+    fn list(&self) -> Vec<String> {
+        self.key_info.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    fn get(&self, k: &str) -> Result<KeyInfo, Error> {
+        self.key_info.get(k).cloned().ok_or(Error::KeyInfo)
+    }
+
+    fn put(&mut self, key: String, key_info: KeyInfo) -> Result<(), Error> {
+        if self.key_info.contains_key(&key) {
+            return Err(Error::KeyExists);
+        }
+        self.key_info.insert(key, key_info);
+        self.flush()
+    }
+
+    fn remove(&mut self, key: String) -> Result<KeyInfo, Error> {
+        let key_out = self.key_info.remove(&key).ok_or(Error::KeyInfo)?;
+        self.flush()?;
+        Ok(key_out)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        EncryptedKeyStore::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate;
+    use crypto::SignatureType;
+    use std::env::temp_dir;
+
+    fn test_dir(name: &str) -> String {
+        let dir = format!(
+            "{}/forest_keystore_{}_{}",
+            temp_dir().display(),
+            name,
+            OsRng.next_u64()
+        );
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_key_info() -> KeyInfo {
+        KeyInfo::new(
+            SignatureType::Secp256k1,
+            generate(SignatureType::Secp256k1).unwrap(),
+        )
+    }
+
+    #[test]
+    fn encrypted_roundtrip() {
+        let dir = test_dir("roundtrip");
+        let key_info = test_key_info();
+
+        let mut ks = EncryptedKeyStore::new(dir.clone(), "passphrase").unwrap();
+        ks.put("key".to_owned(), key_info.clone()).unwrap();
+
+        let raw = fs::read(format!("{}{}", dir, ENCRYPTED_KEYSTORE_NAME)).unwrap();
+        let plain = serde_json::to_vec(&ks.key_info).unwrap();
+        assert!(!raw.windows(plain.len()).any(|w| w == plain.as_slice()));
+
+        let ks = EncryptedKeyStore::new(dir.clone(), "passphrase").unwrap();
+        assert_eq!(ks.get("key").unwrap(), key_info);
+
+        assert!(EncryptedKeyStore::new(dir.clone(), "wrong").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_plaintext() {
+        let dir = test_dir("migrate");
+        let key_info = test_key_info();
+
+        let mut plain = PersistentKeyStore::new(dir.clone()).unwrap();
+        plain.key_info.insert("key".to_owned(), key_info.clone());
+        plain.flush().unwrap();
+
+        let ks = EncryptedKeyStore::new(dir.clone(), "passphrase").unwrap();
+        assert_eq!(ks.get("key").unwrap(), key_info);
+        assert!(!Path::new(&format!("{}{}", dir, KEYSTORE_NAME)).exists());
+
+        let ks = EncryptedKeyStore::new(dir.clone(), "passphrase").unwrap();
+        assert_eq!(ks.get("key").unwrap(), key_info);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -11,7 +11,7 @@ use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
 use std::io::{BufReader, BufWriter, ErrorKind};
 
-const KEYSTORE_NAME: &str = "/keystore.json";
+pub(crate) const KEYSTORE_NAME: &str = "/keystore.json";
 
 /// KeyInfo struct, this contains the type of key (stored as a string) and the private key.
 /// note how the private key is stored as a byte vector
@@ -101,6 +101,35 @@ pub trait KeyStore {
     fn put(&mut self, key: String, key_info: KeyInfo) -> Result<(), Error>;
     /// Remove the Key and corresponding key_info from the KeyStore
     fn remove(&mut self, key: String) -> Result<KeyInfo, Error>;
+    /// Persist any pending changes of the KeyStore
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<KS> KeyStore for Box<KS>
+where
+    KS: KeyStore + ?Sized,
+{
+    fn list(&self) -> Vec<String> {
+        (**self).list()
+    }
+
+    fn get(&self, k: &str) -> Result<KeyInfo, Error> {
+        (**self).get(k)
+    }
+
+    fn put(&mut self, key: String, key_info: KeyInfo) -> Result<(), Error> {
+        (**self).put(key, key_info)
+    }
+
+    fn remove(&mut self, key: String) -> Result<KeyInfo, Error> {
+        (**self).remove(key)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        (**self).flush()
+    }
 }
 
 #[derive(Default, Clone, PartialEq, Debug, Eq)]
@@ -222,4 +251,8 @@ impl KeyStore for PersistentKeyStore {
         serde_json::to_writer(file, &self.key_info).map_err(|err| Error::Other(err.to_string()))?;
         Ok(key_out)
     }
+
+    fn flush(&self) -> Result<(), Error> {
+        PersistentKeyStore::flush(self)
+    }
 }
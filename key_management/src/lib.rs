@@ -1,11 +1,13 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+mod encrypted_keystore;
 mod errors;
 mod keystore;
 mod wallet;
 mod wallet_helpers;
 
+pub use encrypted_keystore::*;
 pub use errors::*;
 pub use keystore::*;
 pub use wallet::*;
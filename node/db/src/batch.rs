@@ -0,0 +1,82 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+/// Operation of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    PutMetadata(Vec<u8>, Vec<u8>),
+    DeleteMetadata(Vec<u8>),
+}
+
+/// Writes and deletes which are applied to a store together, once committed with
+/// `Store::commit`. Dropping a batch without committing it aborts it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    ops: Vec<BatchOp>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a write of the value at key.
+    pub fn put<K, V>(&mut self, key: K, value: V)
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.ops
+            .push(BatchOp::Put(key.as_ref().to_vec(), value.as_ref().to_vec()));
+    }
+
+    /// Adds a delete of the value at key.
+    pub fn delete<K>(&mut self, key: K)
+    where
+        K: AsRef<[u8]>,
+    {
+        self.ops.push(BatchOp::Delete(key.as_ref().to_vec()));
+    }
+
+    /// Adds a write of a chain metadata value.
+    pub fn put_metadata<K, V>(&mut self, key: K, value: V)
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.ops.push(BatchOp::PutMetadata(
+            key.as_ref().to_vec(),
+            value.as_ref().to_vec(),
+        ));
+    }
+
+    /// Adds a delete of a chain metadata value.
+    pub fn delete_metadata<K>(&mut self, key: K)
+    where
+        K: AsRef<[u8]>,
+    {
+        self.ops
+            .push(BatchOp::DeleteMetadata(key.as_ref().to_vec()));
+    }
+
+    /// Returns the number of operations in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Returns the operations of the batch, in the order they were added.
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    /// Consumes the batch, returning its operations in the order they were added.
+    pub fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+}
@@ -1,15 +1,24 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+mod batch;
 mod errors;
 mod memory;
 mod rocks;
+mod rocks_config;
+mod sled_db;
 
+pub use batch::{Batch, BatchOp};
 pub use errors::Error;
 pub use memory::MemoryDB;
 
 #[cfg(feature = "rocksdb")]
 pub use rocks::{RocksDb, WriteBatch};
+#[cfg(feature = "rocksdb")]
+pub use rocks_config::{CompactionStyle, CompressionType, RocksDbConfig};
+
+#[cfg(feature = "sled")]
+pub use sled_db::SledDb;
 
 pub trait DatabaseService {
     fn open(&mut self) -> Result<(), Error> {
@@ -48,16 +57,17 @@ pub trait Store {
         keys.iter().map(|key| self.read(key)).collect()
     }
 
-    /// Write slice of KV pairs.
+    /// Write slice of KV pairs, atomically if the store commits batches atomically.
     fn bulk_write<K, V>(&self, values: &[(K, V)]) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
         V: AsRef<[u8]>,
     {
-        values
-            .iter()
-            .map(|(key, value)| self.write(key, value))
-            .collect()
+        let mut batch = self.begin();
+        for (key, value) in values {
+            batch.put(key, value);
+        }
+        self.commit(batch)
     }
 
     /// Bulk delete keys from the data store.
@@ -65,6 +75,64 @@ pub trait Store {
     where
         K: AsRef<[u8]>,
     {
-        keys.iter().map(|key| self.delete(key)).collect()
+        let mut batch = self.begin();
+        for key in keys {
+            batch.delete(key);
+        }
+        self.commit(batch)
+    }
+
+    /// Starts a batch of writes and deletes, which are only applied once committed.
+    fn begin(&self) -> Batch {
+        Batch::new()
+    }
+
+    /// Applies the operations of the batch. Stores which support it apply all of them
+    /// atomically, while this default applies them one after the other.
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Put(key, value) => self.write(key, value)?,
+                BatchOp::Delete(key) => self.delete(key)?,
+                BatchOp::PutMetadata(key, value) => self.write_metadata(key, value)?,
+                BatchOp::DeleteMetadata(key) => self.delete_metadata(key)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a chain metadata value, such as the head or genesis of the chain. Stores which
+    /// don't keep metadata apart from blocks read it like any other value.
+    fn read_metadata<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.read(key)
+    }
+
+    /// Write a chain metadata value.
+    fn write_metadata<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.write(key, value)
     }
+
+    /// Delete a chain metadata value.
+    fn delete_metadata<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.delete(key)
+    }
+}
+
+/// Store whose keys can be iterated over, allowing unreferenced values to be swept
+pub trait IterableStore: Store {
+    /// Calls the closure with every key of the store, stopping at the first error. The closure
+    /// may write to the store, keys written while iterating may or may not be visited.
+    fn for_each_key<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]) -> Result<(), Error>;
 }
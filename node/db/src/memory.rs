@@ -1,26 +1,14 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use super::{DatabaseService, Error, Store};
+use super::{Batch, BatchOp, DatabaseService, Error, IterableStore, Store};
 use parking_lot::RwLock;
-use std::collections::{hash_map::DefaultHasher, HashMap};
-use std::hash::{Hash, Hasher};
+use std::collections::HashMap;
 
 /// A thread-safe `HashMap` wrapper.
 #[derive(Debug)]
 pub struct MemoryDB {
-    db: RwLock<HashMap<u64, Vec<u8>>>,
-}
-
-impl MemoryDB {
-    fn db_index<K>(key: K) -> u64
-    where
-        K: AsRef<[u8]>,
-    {
-        let mut hasher = DefaultHasher::new();
-        key.as_ref().hash::<DefaultHasher>(&mut hasher);
-        hasher.finish()
-    }
+    db: RwLock<HashMap<Vec<u8>, Vec<u8>>>,
 }
 
 impl Clone for MemoryDB {
@@ -49,7 +37,7 @@ impl Store for MemoryDB {
     {
         self.db
             .write()
-            .insert(Self::db_index(key), value.as_ref().to_vec());
+            .insert(key.as_ref().to_vec(), value.as_ref().to_vec());
         Ok(())
     }
 
@@ -57,7 +45,7 @@ impl Store for MemoryDB {
     where
         K: AsRef<[u8]>,
     {
-        self.db.write().remove(&Self::db_index(key));
+        self.db.write().remove(key.as_ref());
         Ok(())
     }
 
@@ -65,13 +53,40 @@ impl Store for MemoryDB {
     where
         K: AsRef<[u8]>,
     {
-        Ok(self.db.read().get(&Self::db_index(key)).cloned())
+        Ok(self.db.read().get(key.as_ref()).cloned())
     }
 
     fn exists<K>(&self, key: K) -> Result<bool, Error>
     where
         K: AsRef<[u8]>,
     {
-        Ok(self.db.read().contains_key(&Self::db_index(key)))
+        Ok(self.db.read().contains_key(key.as_ref()))
+    }
+
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        // Holding the lock for the whole batch keeps readers from seeing part of it
+        let mut db = self.db.write();
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Put(key, value) | BatchOp::PutMetadata(key, value) => {
+                    db.insert(key, value);
+                }
+                BatchOp::Delete(key) | BatchOp::DeleteMetadata(key) => {
+                    db.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl IterableStore for MemoryDB {
+    fn for_each_key<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        // Keys are copied so the closure can write to the store
+        let keys: Vec<Vec<u8>> = self.db.read().keys().cloned().collect();
+        keys.iter().try_for_each(|k| f(k))
     }
 }
@@ -4,8 +4,9 @@
 #![cfg(feature = "rocksdb")]
 
 use super::errors::Error;
-use super::{DatabaseService, Store};
-pub use rocksdb::{Options, WriteBatch, DB};
+use super::rocks_config::RocksDbConfig;
+use super::{Batch, BatchOp, DatabaseService, IterableStore, Store};
+pub use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, WriteBatch, DB};
 use std::env::temp_dir;
 use std::path::{Path, PathBuf};
 
@@ -21,9 +22,23 @@ impl Default for DbStatus {
     }
 }
 
+/// Column family of the chain metadata. Blocks are kept in the default column family, so
+/// they are not mixed with the small and frequently rewritten metadata values.
+const METADATA_COLUMN: &str = "metadata";
+
+/// Column family RocksDb always creates, holding the blocks. It is listed explicitly so it is
+/// opened with the configured options. rocksdb 0.14 does not export this name, later versions
+/// do as `rocksdb::DEFAULT_COLUMN_FAMILY_NAME`, which should be used once the crate is upgraded.
+const DEFAULT_COLUMN_FAMILY_NAME: &str = "default";
+
+/// Metadata keys written to the default column family by versions without the metadata column
+/// family, moved to the metadata column family when the database is opened.
+const LEGACY_METADATA_KEYS: &[&str] = &["head", "gen_block"];
+
 #[derive(Debug, Default)]
 pub struct RocksDb {
     status: DbStatus,
+    config: RocksDbConfig,
 }
 
 /// RocksDb is used as the KV store for Forest
@@ -37,27 +52,61 @@ pub struct RocksDb {
 /// ```
 impl RocksDb {
     pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self::with_config(path, RocksDbConfig::default())
+    }
+
+    /// Creates a database which is opened with the provided options.
+    pub fn with_config<P>(path: P, config: RocksDbConfig) -> Self
     where
         P: AsRef<Path>,
     {
         Self {
             status: DbStatus::Unopened(path.as_ref().to_path_buf()),
+            config,
         }
     }
 
-    /// Initializes the database if uninitialized, does nothing if db is already opened
+    /// Initializes the database if uninitialized, does nothing if db is already opened.
+    /// Metadata left in the default column family by older versions is moved to the metadata
+    /// column family.
     pub fn open(&mut self) -> Result<(), Error> {
         match &self.status {
             DbStatus::Unopened(path) => {
-                let mut db_opts = Options::default();
-                db_opts.create_if_missing(true);
-                self.status = DbStatus::Open(DB::open(&db_opts, path)?);
-                Ok(())
+                let db_opts = self.config.to_options();
+                let columns = vec![
+                    ColumnFamilyDescriptor::new(
+                        DEFAULT_COLUMN_FAMILY_NAME,
+                        self.config.to_options(),
+                    ),
+                    ColumnFamilyDescriptor::new(METADATA_COLUMN, self.config.to_options()),
+                ];
+                self.status = DbStatus::Open(DB::open_cf_descriptors(&db_opts, path, columns)?);
+                self.migrate_legacy_metadata()
             }
             DbStatus::Open(_) => Ok(()),
         }
     }
 
+    /// Moves the metadata written by older versions out of the default column family.
+    fn migrate_legacy_metadata(&self) -> Result<(), Error> {
+        let db = self.db()?;
+        let metadata = self.metadata()?;
+        let mut batch = WriteBatch::default();
+        for key in LEGACY_METADATA_KEYS {
+            if let Some(value) = db.get(key)? {
+                batch.put_cf(metadata, key, value);
+                batch.delete(key);
+            }
+        }
+        if !batch.is_empty() {
+            db.write(batch)?;
+        }
+        Ok(())
+    }
+
     /// Returns reference to db as long as it is initialized
     pub fn db(&self) -> Result<&DB, Error> {
         match &self.status {
@@ -65,6 +114,12 @@ impl RocksDb {
             DbStatus::Open(db) => Ok(db),
         }
     }
+
+    fn metadata(&self) -> Result<&ColumnFamily, Error> {
+        self.db()?
+            .cf_handle(METADATA_COLUMN)
+            .ok_or_else(|| Error::Other("metadata column family is missing".to_owned()))
+    }
 }
 
 impl DatabaseService for RocksDb {
@@ -117,4 +172,53 @@ impl Store for RocksDb {
             .map(|v| v.is_some())
             .map_err(Error::from)
     }
+
+    fn read_metadata<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.db()?
+            .get_cf(self.metadata()?, key)
+            .map_err(Error::from)
+    }
+
+    fn write_metadata<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        Ok(self.db()?.put_cf(self.metadata()?, key, value)?)
+    }
+
+    fn delete_metadata<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        Ok(self.db()?.delete_cf(self.metadata()?, key)?)
+    }
+
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        let metadata = self.metadata()?;
+        let mut write_batch = WriteBatch::default();
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Put(key, value) => write_batch.put(key, value),
+                BatchOp::Delete(key) => write_batch.delete(key),
+                BatchOp::PutMetadata(key, value) => write_batch.put_cf(metadata, key, value),
+                BatchOp::DeleteMetadata(key) => write_batch.delete_cf(metadata, key),
+            }
+        }
+        Ok(self.db()?.write(write_batch)?)
+    }
+}
+
+impl IterableStore for RocksDb {
+    fn for_each_key<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        self.db()?
+            .iterator(IteratorMode::Start)
+            .try_for_each(|(k, _)| f(&k))
+    }
 }
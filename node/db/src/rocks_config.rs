@@ -0,0 +1,107 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+#![cfg(feature = "rocksdb")]
+
+use rocksdb::{BlockBasedOptions, DBCompactionStyle, DBCompressionType, Options};
+use serde::Deserialize;
+
+/// Compression applied to the blocks of the database files
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionType {
+    None,
+    Snappy,
+    Zlib,
+    Bz2,
+    Lz4,
+    Lz4hc,
+    Zstd,
+}
+
+impl From<CompressionType> for DBCompressionType {
+    fn from(compression: CompressionType) -> Self {
+        match compression {
+            CompressionType::None => DBCompressionType::None,
+            CompressionType::Snappy => DBCompressionType::Snappy,
+            CompressionType::Zlib => DBCompressionType::Zlib,
+            CompressionType::Bz2 => DBCompressionType::Bz2,
+            CompressionType::Lz4 => DBCompressionType::Lz4,
+            CompressionType::Lz4hc => DBCompressionType::Lz4hc,
+            CompressionType::Zstd => DBCompressionType::Zstd,
+        }
+    }
+}
+
+/// Strategy used to compact the database files
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionStyle {
+    Level,
+    Universal,
+    Fifo,
+}
+
+impl From<CompactionStyle> for DBCompactionStyle {
+    fn from(style: CompactionStyle) -> Self {
+        match style {
+            CompactionStyle::Level => DBCompactionStyle::Level,
+            CompactionStyle::Universal => DBCompactionStyle::Universal,
+            CompactionStyle::Fifo => DBCompactionStyle::Fifo,
+        }
+    }
+}
+
+/// Tuning options of the RocksDb datastore
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RocksDbConfig {
+    /// Create the database if it does not exist yet, instead of failing to open it
+    pub create_if_missing: bool,
+    /// Number of background threads used for flushes and compactions
+    pub parallelism: i32,
+    /// Size in bytes of the LRU cache of uncompressed blocks
+    pub block_cache_size: usize,
+    /// Maximum number of open files, or -1 to keep all files open
+    pub max_open_files: i32,
+    /// Size in bytes of a memtable before it is flushed to disk
+    pub write_buffer_size: usize,
+    /// One of `none`, `snappy`, `zlib`, `bz2`, `lz4`, `lz4hc` or `zstd`
+    pub compression_type: CompressionType,
+    /// One of `level`, `universal` or `fifo`
+    pub compaction_style: CompactionStyle,
+}
+
+impl Default for RocksDbConfig {
+    fn default() -> Self {
+        Self {
+            create_if_missing: true,
+            parallelism: 4,
+            block_cache_size: 512 * 1024 * 1024,
+            max_open_files: 1024,
+            write_buffer_size: 256 * 1024 * 1024,
+            compression_type: CompressionType::Lz4,
+            compaction_style: CompactionStyle::Level,
+        }
+    }
+}
+
+impl RocksDbConfig {
+    /// Converts the configuration to the options the database and its column families are
+    /// opened with.
+    pub(crate) fn to_options(&self) -> Options {
+        let mut db_opts = Options::default();
+        db_opts.create_if_missing(self.create_if_missing);
+        db_opts.create_missing_column_families(true);
+        db_opts.increase_parallelism(self.parallelism);
+        db_opts.set_max_open_files(self.max_open_files);
+        db_opts.set_write_buffer_size(self.write_buffer_size);
+        db_opts.set_compression_type(self.compression_type.into());
+        db_opts.set_compaction_style(self.compaction_style.into());
+
+        let mut block_opts = BlockBasedOptions::default();
+        block_opts.set_lru_cache(self.block_cache_size);
+        db_opts.set_block_based_table_factory(&block_opts);
+        db_opts
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+#![cfg(feature = "sled")]
+
+use super::errors::Error;
+use super::{Batch, BatchOp, DatabaseService, IterableStore, Store};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::Transactional;
+pub use sled::{Db, Tree};
+use std::env::temp_dir;
+use std::path::{Path, PathBuf};
+
+/// Tree of the chain metadata, kept apart from the blocks in the default tree
+const METADATA_TREE: &str = "metadata";
+
+#[derive(Debug)]
+enum DbStatus {
+    Unopened(PathBuf),
+    Open { db: Db, metadata: Tree },
+}
+
+impl Default for DbStatus {
+    fn default() -> Self {
+        Self::Unopened(Path::new(&temp_dir()).to_path_buf())
+    }
+}
+
+/// Sled is a pure Rust alternative to RocksDb as the KV store for Forest
+///
+/// Usage:
+/// ```no_run
+/// use db::SledDb;
+///
+/// let mut db = SledDb::new("test_db");
+/// db.open();
+/// ```
+#[derive(Debug, Default)]
+pub struct SledDb {
+    status: DbStatus,
+}
+
+impl SledDb {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            status: DbStatus::Unopened(path.as_ref().to_path_buf()),
+        }
+    }
+
+    /// Initializes the database if uninitialized, does nothing if db is already opened
+    pub fn open(&mut self) -> Result<(), Error> {
+        match &self.status {
+            DbStatus::Unopened(path) => {
+                let db = sled::open(path)?;
+                let metadata = db.open_tree(METADATA_TREE)?;
+                self.status = DbStatus::Open { db, metadata };
+                Ok(())
+            }
+            DbStatus::Open { .. } => Ok(()),
+        }
+    }
+
+    /// Returns reference to db as long as it is initialized
+    pub fn db(&self) -> Result<&Db, Error> {
+        match &self.status {
+            DbStatus::Unopened(_) => Err(Error::Unopened),
+            DbStatus::Open { db, .. } => Ok(db),
+        }
+    }
+
+    fn metadata(&self) -> Result<&Tree, Error> {
+        match &self.status {
+            DbStatus::Unopened(_) => Err(Error::Unopened),
+            DbStatus::Open { metadata, .. } => Ok(metadata),
+        }
+    }
+}
+
+impl DatabaseService for SledDb {
+    fn open(&mut self) -> Result<(), Error> {
+        self.open()
+    }
+}
+
+impl Store for SledDb {
+    fn write<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.db()?.insert(key, value.as_ref())?;
+        Ok(())
+    }
+
+    fn delete<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.db()?.remove(key)?;
+        Ok(())
+    }
+
+    fn bulk_write<K, V>(&self, values: &[(K, V)]) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut batch = sled::Batch::default();
+        for (k, v) in values {
+            batch.insert(k.as_ref(), v.as_ref());
+        }
+        Ok(self.db()?.apply_batch(batch)?)
+    }
+
+    fn read<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        Ok(self.db()?.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn exists<K>(&self, key: K) -> Result<bool, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        Ok(self.db()?.contains_key(key)?)
+    }
+
+    fn read_metadata<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        Ok(self.metadata()?.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn write_metadata<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.metadata()?.insert(key, value.as_ref())?;
+        Ok(())
+    }
+
+    fn delete_metadata<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.metadata()?.remove(key)?;
+        Ok(())
+    }
+
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        let ops = batch.into_ops();
+        // Blocks and metadata are in different trees, so they are written in a transaction
+        let res: Result<(), TransactionError<()>> =
+            (&**self.db()?, self.metadata()?).transaction(|(blocks, metadata)| {
+                for op in &ops {
+                    match op {
+                        BatchOp::Put(key, value) => {
+                            blocks.insert(key.as_slice(), value.as_slice())?;
+                        }
+                        BatchOp::Delete(key) => {
+                            blocks.remove(key.as_slice())?;
+                        }
+                        BatchOp::PutMetadata(key, value) => {
+                            metadata.insert(key.as_slice(), value.as_slice())?;
+                        }
+                        BatchOp::DeleteMetadata(key) => {
+                            metadata.remove(key.as_slice())?;
+                        }
+                    }
+                }
+                Ok::<_, ConflictableTransactionError<()>>(())
+            });
+        match res {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+            Err(TransactionError::Abort(())) => {
+                Err(Error::Other("batch transaction aborted".to_owned()))
+            }
+        }
+    }
+}
+
+impl IterableStore for SledDb {
+    fn for_each_key<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        for key in self.db()?.iter().keys() {
+            f(&key?)?;
+        }
+        Ok(())
+    }
+}
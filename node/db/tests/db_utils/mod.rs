@@ -1,15 +1,15 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-#![cfg(feature = "rocksdb")]
+#![cfg(any(feature = "rocksdb", feature = "sled"))]
 
 // Taken from
 // https://github.com/rust-rocksdb/rust-rocksdb/blob/master/tests/util/mod.rs
-use rocksdb::{Options, DB};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Ensures that DB::Destroy is called for this database when DBPath is dropped.
+/// Ensures that the database directory is removed when DBPath is dropped.
 pub struct DBPath {
     pub path: PathBuf,
 }
@@ -34,8 +34,7 @@ impl DBPath {
 
 impl Drop for DBPath {
     fn drop(&mut self) {
-        let opts = Options::default();
-        DB::destroy(&opts, &self.path).unwrap();
+        let _ = fs::remove_dir_all(&self.path);
     }
 }
 
@@ -60,3 +60,21 @@ fn mem_db_bulk_delete() {
     let db = MemoryDB::default();
     subtests::bulk_delete(&db);
 }
+
+#[test]
+fn mem_db_for_each_key() {
+    let db = MemoryDB::default();
+    subtests::for_each_key(&db);
+}
+
+#[test]
+fn mem_db_metadata() {
+    let db = MemoryDB::default();
+    subtests::metadata(&db);
+}
+
+#[test]
+fn mem_db_batch() {
+    let db = MemoryDB::default();
+    subtests::batch(&db);
+}
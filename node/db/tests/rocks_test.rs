@@ -6,7 +6,7 @@
 mod db_utils;
 mod subtests;
 
-use db::RocksDb;
+use db::{CompactionStyle, CompressionType, RocksDb, RocksDbConfig, Store};
 use db_utils::DBPath;
 
 #[test]
@@ -81,3 +81,70 @@ fn rocks_db_bulk_delete() {
     subtests::open(&mut db);
     subtests::bulk_delete(&db);
 }
+
+#[test]
+fn rocks_db_for_each_key() {
+    let path = DBPath::new("for_each_key_rocks_test");
+    let mut db = RocksDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::for_each_key(&db);
+}
+
+#[test]
+fn rocks_db_metadata() {
+    let path = DBPath::new("metadata_rocks_test");
+    let mut db = RocksDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::metadata(&db);
+}
+
+#[test]
+fn rocks_db_metadata_column() {
+    let path = DBPath::new("metadata_column_rocks_test");
+    let mut db = RocksDb::with_config(path.as_ref(), RocksDbConfig::default());
+    subtests::open(&mut db);
+    // Metadata is kept apart from blocks, so it is never iterated over with them
+    db.write_metadata(b"head", [1]).unwrap();
+    assert_eq!(db.read(b"head").unwrap(), None);
+    subtests::for_each_key(&db);
+}
+
+#[test]
+fn rocks_db_legacy_metadata() {
+    let path = DBPath::new("legacy_metadata_rocks_test");
+    let mut db = RocksDb::new(path.as_ref());
+    subtests::open(&mut db);
+    // Metadata written to the default column family by older versions
+    db.write(b"head", [1]).unwrap();
+    db.write(b"gen_block", [2]).unwrap();
+    drop(db);
+
+    // is moved to the metadata column family when the database is opened
+    let mut db = RocksDb::new(path.as_ref());
+    subtests::open(&mut db);
+    assert_eq!(db.read(b"head").unwrap(), None);
+    assert_eq!(db.read(b"gen_block").unwrap(), None);
+    assert_eq!(db.read_metadata(b"head").unwrap(), Some(vec![1]));
+    assert_eq!(db.read_metadata(b"gen_block").unwrap(), Some(vec![2]));
+}
+
+#[test]
+fn rocks_db_config_options() {
+    let path = DBPath::new("config_options_rocks_test");
+    let config = RocksDbConfig {
+        compression_type: CompressionType::Zstd,
+        compaction_style: CompactionStyle::Universal,
+        ..Default::default()
+    };
+    let mut db = RocksDb::with_config(path.as_ref(), config);
+    subtests::open(&mut db);
+    subtests::write(&db);
+}
+
+#[test]
+fn rocks_db_batch() {
+    let path = DBPath::new("batch_rocks_test");
+    let mut db = RocksDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::batch(&db);
+}
@@ -0,0 +1,118 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+#![cfg(feature = "sled")]
+
+mod db_utils;
+mod subtests;
+
+use db::{SledDb, Store};
+use db_utils::DBPath;
+
+#[test]
+fn sled_db_open() {
+    let path = DBPath::new("start_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    // Calling open on opened db should not error
+    subtests::open(&mut db);
+}
+
+#[test]
+fn sled_db_write() {
+    let path = DBPath::new("write_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::write(&db);
+}
+
+#[test]
+fn sled_db_read() {
+    let path = DBPath::new("read_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::read(&db);
+}
+
+#[test]
+fn sled_db_exists() {
+    let path = DBPath::new("exists_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::exists(&db);
+}
+
+#[test]
+fn sled_db_does_not_exist() {
+    let path = DBPath::new("does_not_exists_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::does_not_exist(&db);
+}
+
+#[test]
+fn sled_db_delete() {
+    let path = DBPath::new("delete_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::delete(&db);
+}
+
+#[test]
+fn sled_db_bulk_write() {
+    let path = DBPath::new("bulk_write_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::bulk_write(&db);
+}
+
+#[test]
+fn sled_db_bulk_read() {
+    let path = DBPath::new("bulk_read_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::bulk_read(&db);
+}
+
+#[test]
+fn sled_db_bulk_delete() {
+    let path = DBPath::new("bulk_delete_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::bulk_delete(&db);
+}
+
+#[test]
+fn sled_db_for_each_key() {
+    let path = DBPath::new("for_each_key_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::for_each_key(&db);
+}
+
+#[test]
+fn sled_db_metadata() {
+    let path = DBPath::new("metadata_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::metadata(&db);
+}
+
+#[test]
+fn sled_db_metadata_tree() {
+    let path = DBPath::new("metadata_tree_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    // Metadata is kept apart from blocks, so it is never iterated over with them
+    db.write_metadata(b"head", [1]).unwrap();
+    assert_eq!(db.read(b"head").unwrap(), None);
+    subtests::for_each_key(&db);
+}
+
+#[test]
+fn sled_db_batch() {
+    let path = DBPath::new("batch_sled_test");
+    let mut db = SledDb::new(path.as_ref());
+    subtests::open(&mut db);
+    subtests::batch(&db);
+}
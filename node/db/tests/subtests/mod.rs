@@ -1,7 +1,7 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use db::{DatabaseService, Store};
+use db::{Batch, DatabaseService, IterableStore, Store};
 
 pub fn open<DB>(db: &mut DB)
 where
@@ -107,3 +107,59 @@ where
         assert_eq!(res, false);
     }
 }
+
+pub fn for_each_key<DB>(db: &DB)
+where
+    DB: IterableStore,
+{
+    let keys = [[0], [1], [2]];
+    let values = [[0], [1], [2]];
+    let kvs: Vec<_> = keys.iter().zip(values.iter()).collect();
+    db.bulk_write(&kvs).unwrap();
+    let mut found = Vec::new();
+    db.for_each_key(|k| {
+        found.push(k.to_vec());
+        Ok(())
+    })
+    .unwrap();
+    found.sort();
+    let expected: Vec<Vec<u8>> = keys.iter().map(|k| k.to_vec()).collect();
+    assert_eq!(found, expected);
+}
+
+pub fn metadata<DB>(db: &DB)
+where
+    DB: Store,
+{
+    let key = b"head";
+    let value = [1];
+    db.write_metadata(key, value).unwrap();
+    let res = db.read_metadata(key).unwrap().unwrap();
+    assert_eq!(value.as_ref(), res.as_slice());
+    db.delete_metadata(key).unwrap();
+    assert_eq!(db.read_metadata(key).unwrap(), None);
+}
+
+pub fn batch<DB>(db: &DB)
+where
+    DB: Store,
+{
+    db.write([0], [0]).unwrap();
+    let mut batch = db.begin();
+    batch.put([1], [1]);
+    batch.delete([0]);
+    batch.put_metadata(b"head", [2]);
+    // Nothing is applied until the batch is committed
+    assert_eq!(db.exists([1]).unwrap(), false);
+    assert_eq!(db.exists([0]).unwrap(), true);
+    db.commit(batch).unwrap();
+    assert_eq!(db.read([1]).unwrap(), Some(vec![1]));
+    assert_eq!(db.exists([0]).unwrap(), false);
+    assert_eq!(db.read_metadata(b"head").unwrap(), Some(vec![2]));
+
+    // Dropped batches are never applied
+    let mut aborted = Batch::new();
+    aborted.delete([1]);
+    drop(aborted);
+    assert_eq!(db.exists([1]).unwrap(), true);
+}
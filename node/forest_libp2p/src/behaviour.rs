@@ -7,12 +7,19 @@ use crate::blocksync::{
 use crate::config::Libp2pConfig;
 use crate::hello::{HelloCodec, HelloProtocolName, HelloRequest, HelloResponse};
 use crate::rpc::RPCRequest;
+use async_trait::async_trait;
 use forest_cid::Cid;
+use forest_ipld::selector::Selector;
+use graphsync::libp2p::{GraphSync, GraphSyncConfig, GraphSyncEvent};
+use graphsync::{
+    CompletedRequest, Extensions, GraphSyncMessage, GraphSyncRequest, GraphSyncResponse,
+    PeerMessageHandler, RequestID,
+};
 use libp2p::core::identity::Keypair;
 use libp2p::core::PeerId;
 use libp2p::gossipsub::{
-    error::PublishError, Gossipsub, GossipsubConfig, GossipsubEvent, MessageAuthenticity, Topic,
-    TopicHash, ValidationMode,
+    error::PublishError, Gossipsub, GossipsubConfig, GossipsubEvent, MessageAuthenticity,
+    MessageId, Topic, TopicHash, ValidationMode,
 };
 use libp2p::identify::{Identify, IdentifyEvent};
 use libp2p::kad::record::store::MemoryStore;
@@ -51,6 +58,7 @@ pub struct ForestBehaviour {
     blocksync: RequestResponse<BlockSyncCodec>,
     kademlia: Toggle<Kademlia<MemoryStore>>,
     bitswap: Bitswap,
+    graphsync: GraphSync,
     #[behaviour(ignore)]
     events: Vec<ForestBehaviourEvent>,
     #[behaviour(ignore)]
@@ -65,6 +73,8 @@ pub enum ForestBehaviourEvent {
         source: Option<PeerId>,
         topics: Vec<TopicHash>,
         message: Vec<u8>,
+        message_id: MessageId,
+        propagation_source: PeerId,
     },
     BitswapReceivedBlock(PeerId, Cid, Box<[u8]>),
     BitswapReceivedWant(PeerId, Cid),
@@ -88,6 +98,19 @@ pub enum ForestBehaviourEvent {
         request_id: RequestId,
         response: BlockSyncResponse,
     },
+    GraphSyncRequest {
+        peer: PeerId,
+        request: GraphSyncRequest,
+    },
+    GraphSyncProgress {
+        id: RequestID,
+        peer: PeerId,
+        blocks_received: usize,
+    },
+    GraphSyncCompleted {
+        id: RequestID,
+        request: CompletedRequest,
+    },
 }
 
 impl NetworkBehaviourEventProcess<MdnsEvent> for ForestBehaviour {
@@ -156,11 +179,13 @@ impl NetworkBehaviourEventProcess<BitswapEvent> for ForestBehaviour {
 
 impl NetworkBehaviourEventProcess<GossipsubEvent> for ForestBehaviour {
     fn inject_event(&mut self, message: GossipsubEvent) {
-        if let GossipsubEvent::Message(_, _, message) = message {
+        if let GossipsubEvent::Message(propagation_source, message_id, message) = message {
             self.events.push(ForestBehaviourEvent::GossipMessage {
                 source: message.source,
                 topics: message.topics,
                 message: message.data,
+                message_id,
+                propagation_source,
             })
         }
     }
@@ -284,6 +309,40 @@ impl NetworkBehaviourEventProcess<RequestResponseEvent<BlockSyncRequest, BlockSy
     }
 }
 
+impl NetworkBehaviourEventProcess<GraphSyncEvent> for ForestBehaviour {
+    fn inject_event(&mut self, event: GraphSyncEvent) {
+        self.events.push(match event {
+            GraphSyncEvent::Request { peer, request } => {
+                ForestBehaviourEvent::GraphSyncRequest { peer, request }
+            }
+            GraphSyncEvent::Progress {
+                id,
+                peer,
+                blocks_received,
+            } => ForestBehaviourEvent::GraphSyncProgress {
+                id,
+                peer,
+                blocks_received,
+            },
+            GraphSyncEvent::Completed { id, request } => {
+                ForestBehaviourEvent::GraphSyncCompleted { id, request }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl PeerMessageHandler for ForestBehaviour {
+    async fn send_response(
+        &mut self,
+        peer: &PeerId,
+        responses: Vec<GraphSyncResponse>,
+        blocks: Vec<(Cid, Vec<u8>)>,
+    ) {
+        self.graphsync.send_response(peer, responses, blocks).await
+    }
+}
+
 impl ForestBehaviour {
     /// Consumes the events list when polled.
     fn poll<TBehaviourIn>(
@@ -304,6 +363,8 @@ impl ForestBehaviour {
             validation_mode: ValidationMode::Permissive,
             // Using go gossipsub default, not certain this is intended
             max_transmit_size: 1 << 20,
+            // Messages are only forwarded once validated by their subscribers
+            validate_messages: true,
             ..Default::default()
         };
 
@@ -360,6 +421,7 @@ impl ForestBehaviour {
             bitswap,
             hello: RequestResponse::new(HelloCodec, hp, req_res_config.clone()),
             blocksync: RequestResponse::new(BlockSyncCodec, bp, req_res_config),
+            graphsync: GraphSync::new(GraphSyncConfig::default()),
             events: vec![],
             peers: Default::default(),
         }
@@ -379,6 +441,17 @@ impl ForestBehaviour {
         self.gossipsub.publish(topic, data)
     }
 
+    /// Forwards a validated gossip message to the peers of the mesh. Returns false if the
+    /// message is no longer in the cache.
+    pub fn propagate_message(
+        &mut self,
+        message_id: &MessageId,
+        propagation_source: &PeerId,
+    ) -> bool {
+        self.gossipsub
+            .validate_message(message_id, propagation_source)
+    }
+
     /// Subscribe to a gossip topic.
     pub fn subscribe(&mut self, topic: Topic) -> bool {
         self.gossipsub.subscribe(topic)
@@ -439,4 +512,26 @@ impl ForestBehaviour {
         self.bitswap.cancel_block(&cid);
         Ok(())
     }
+
+    /// Requests the DAG under root matching the selector from a peer over GraphSync.
+    pub fn send_graphsync_request(
+        &mut self,
+        peer_id: PeerId,
+        root: Cid,
+        selector: Selector,
+        extensions: Extensions,
+    ) -> RequestID {
+        self.graphsync
+            .send_request(peer_id, root, selector, extensions)
+    }
+
+    /// Cancels an in progress GraphSync request.
+    pub fn cancel_graphsync_request(&mut self, id: RequestID) {
+        self.graphsync.cancel_request(id)
+    }
+
+    /// Sends a GraphSync message to a peer.
+    pub fn send_graphsync_message(&mut self, peer_id: PeerId, message: GraphSyncMessage) {
+        self.graphsync.send_message(peer_id, message)
+    }
 }
@@ -11,6 +11,19 @@ use std::convert::TryFrom;
 pub const BLOCKS: u64 = 1;
 pub const MESSAGES: u64 = 2;
 
+/// Blocksync response status codes
+pub const STATUS_OK: u64 = 0;
+/// Not all tipsets requested could be fetched, the chain returned is partial
+pub const STATUS_PARTIAL: u64 = 101;
+/// The starting tipset of the request was not found
+pub const STATUS_NOT_FOUND: u64 = 201;
+/// The peer should not make further requests
+pub const STATUS_GO_AWAY: u64 = 202;
+/// Internal error occured while handling the request
+pub const STATUS_INTERNAL_ERROR: u64 = 203;
+/// Request was malformed
+pub const STATUS_BAD_REQUEST: u64 = 204;
+
 /// The payload that gets sent to another node to request for blocks and messages. It get DagCBOR serialized before sending over the wire.
 #[derive(Clone, Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
 pub struct BlockSyncRequest {
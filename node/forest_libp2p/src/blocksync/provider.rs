@@ -0,0 +1,141 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::*;
+use chain::{block_messages_from_cids, read_msg_cids, tipset_from_keys, Error as ChainError};
+use forest_blocks::{Tipset, TipsetKeys};
+use forest_cid::Cid;
+use ipld_blockstore::BlockStore;
+use log::debug;
+use std::collections::HashMap;
+
+/// Maximum number of tipsets served in a single BlockSync response.
+pub const MAX_REQUEST_LENGTH: u64 = 800;
+
+/// Builds a BlockSync response to the request from the tipsets and messages in the blockstore.
+pub fn make_blocksync_response<DB>(db: &DB, request: &BlockSyncRequest) -> BlockSyncResponse
+where
+    DB: BlockStore,
+{
+    let include_blocks = request.options & BLOCKS != 0;
+    let include_messages = request.options & MESSAGES != 0;
+
+    if !include_blocks && !include_messages {
+        return error_response(STATUS_BAD_REQUEST, "no options set");
+    }
+    if request.request_len == 0 {
+        return error_response(STATUS_BAD_REQUEST, "invalid request length of 0");
+    }
+    if request.request_len > MAX_REQUEST_LENGTH {
+        return error_response(
+            STATUS_BAD_REQUEST,
+            &format!(
+                "request length {} over maximum allowed {}",
+                request.request_len, MAX_REQUEST_LENGTH
+            ),
+        );
+    }
+
+    let mut curr = match tipset_from_keys(db, &TipsetKeys::new(request.start.clone())) {
+        Ok(ts) => ts,
+        Err(ChainError::NotFound(_)) => {
+            return error_response(STATUS_NOT_FOUND, "start tipset not found");
+        }
+        Err(e) => return error_response(STATUS_INTERNAL_ERROR, &e.to_string()),
+    };
+
+    let mut chain = Vec::with_capacity(request.request_len as usize);
+    loop {
+        let messages = if include_messages {
+            match compact_messages(db, &curr) {
+                Ok(msgs) => Some(msgs),
+                Err(e) => return error_response(STATUS_INTERNAL_ERROR, &e.to_string()),
+            }
+        } else {
+            None
+        };
+        let blocks = if include_blocks {
+            curr.blocks().to_vec()
+        } else {
+            Vec::new()
+        };
+        chain.push(TipsetBundle { blocks, messages });
+
+        if chain.len() as u64 >= request.request_len || curr.epoch() == 0 {
+            break;
+        }
+
+        curr = match tipset_from_keys(db, curr.parents()) {
+            Ok(ts) => ts,
+            Err(e) => {
+                debug!("Serving partial blocksync chain: {}", e);
+                return BlockSyncResponse {
+                    chain,
+                    status: STATUS_PARTIAL,
+                    message: e.to_string(),
+                };
+            }
+        };
+    }
+
+    BlockSyncResponse {
+        chain,
+        status: STATUS_OK,
+        message: String::new(),
+    }
+}
+
+/// Gathers the messages of all blocks in the tipset, deduplicating messages included in
+/// multiple blocks and recording the indexes each block includes.
+fn compact_messages<DB>(db: &DB, ts: &Tipset) -> Result<CompactedMessages, ChainError>
+where
+    DB: BlockStore,
+{
+    let mut bls_cids: Vec<Cid> = Vec::new();
+    let mut bls_idx: HashMap<Cid, u64> = HashMap::new();
+    let mut bls_msg_includes = Vec::with_capacity(ts.blocks().len());
+    let mut secp_cids: Vec<Cid> = Vec::new();
+    let mut secp_idx: HashMap<Cid, u64> = HashMap::new();
+    let mut secp_msg_includes = Vec::with_capacity(ts.blocks().len());
+
+    for header in ts.blocks() {
+        let (block_bls, block_secp) = read_msg_cids(db, header.messages())?;
+        bls_msg_includes.push(include_indexes(block_bls, &mut bls_cids, &mut bls_idx));
+        secp_msg_includes.push(include_indexes(block_secp, &mut secp_cids, &mut secp_idx));
+    }
+
+    let (bls_msgs, secp_msgs) = block_messages_from_cids(db, &bls_cids, &secp_cids)?;
+
+    Ok(CompactedMessages {
+        bls_msgs,
+        bls_msg_includes,
+        secp_msgs,
+        secp_msg_includes,
+    })
+}
+
+/// Returns the indexes of the block's message Cids within the tipset-wide Cid list,
+/// appending Cids that have not been seen yet.
+fn include_indexes(
+    block_cids: Vec<Cid>,
+    cids: &mut Vec<Cid>,
+    indexes: &mut HashMap<Cid, u64>,
+) -> Vec<u64> {
+    block_cids
+        .into_iter()
+        .map(|c| {
+            *indexes.entry(c.clone()).or_insert_with(|| {
+                cids.push(c);
+                cids.len() as u64 - 1
+            })
+        })
+        .collect()
+}
+
+fn error_response(status: u64, message: &str) -> BlockSyncResponse {
+    BlockSyncResponse {
+        chain: Vec::new(),
+        status,
+        message: message.to_owned(),
+    }
+}
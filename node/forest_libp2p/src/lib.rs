@@ -1,7 +1,7 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-#![recursion_limit = "1024"]
+#![recursion_limit = "2048"]
 
 mod behaviour;
 pub mod blocksync;
@@ -1,16 +1,23 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use super::blocksync::{BlockSyncRequest, BlockSyncResponse};
+use super::blocksync::{make_blocksync_response, BlockSyncRequest, BlockSyncResponse};
 use super::rpc::RPCRequest;
 use super::{ForestBehaviour, ForestBehaviourEvent, Libp2pConfig};
 use crate::hello::{HelloRequest, HelloResponse};
 use async_std::stream;
 use async_std::sync::{channel, Receiver, Sender};
+use async_std::task;
 use forest_cid::{multihash::Blake2b256, Cid};
+use forest_ipld::selector::Selector;
+use futures::channel::mpsc;
 use futures::channel::oneshot::Sender as OneShotSender;
 use futures::select;
 use futures_util::stream::StreamExt;
+use graphsync::{
+    store_verified_blocks, BlockStoreLoader, PeerMessage, PeerMessageHandler, RequestID,
+    ResponseManager, ResponseManagerInput, ResponseStatusCode,
+};
 use ipld_blockstore::BlockStore;
 use libp2p::{
     core,
@@ -28,13 +35,30 @@ use std::sync::Arc;
 use std::time::Duration;
 use utils::read_file_to_vec;
 
-pub use libp2p::gossipsub::Topic;
+pub use libp2p::gossipsub::{MessageId, Topic};
 
 pub const PUBSUB_BLOCK_STR: &str = "/fil/blocks";
 pub const PUBSUB_MSG_STR: &str = "/fil/msgs";
 
 const PUBSUB_TOPICS: [&str; 2] = [PUBSUB_BLOCK_STR, PUBSUB_MSG_STR];
 
+/// Number of invalid gossip messages after which the peer forwarding them is banned
+const MAX_INVALID_GOSSIP: u32 = 10;
+
+/// Number of GraphSync messages built by the response manager waiting to be sent
+const GS_MESSAGE_BUFFER: usize = 30;
+
+/// Outcome of the validation of a gossip message by its subscriber
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageAcceptance {
+    /// The message is valid and is forwarded to the other peers
+    Accept,
+    /// The message is invalid, the peer that forwarded it is penalized
+    Reject,
+    /// The message is not forwarded, but the peer is not penalized
+    Ignore,
+}
+
 /// Events emitted by this Service
 #[derive(Debug, Clone)]
 pub enum NetworkEvent {
@@ -42,6 +66,8 @@ pub enum NetworkEvent {
         source: Option<PeerId>,
         topics: Vec<TopicHash>,
         message: Vec<u8>,
+        message_id: MessageId,
+        propagation_source: PeerId,
     },
     HelloRequest {
         request: HelloRequest,
@@ -83,6 +109,28 @@ pub enum NetworkMessage {
         peer_id: PeerId,
         request: HelloRequest,
     },
+    /// Requests a block over bitswap, the response channel is notified once the block is
+    /// stored in the blockstore
+    BitswapRequest {
+        cid: Cid,
+        response_channel: OneShotSender<()>,
+    },
+    /// Reports the result of the validation of a gossip message. Gossip messages are only
+    /// forwarded to other peers once accepted.
+    ValidationResult {
+        message_id: MessageId,
+        propagation_source: PeerId,
+        acceptance: MessageAcceptance,
+    },
+    /// Requests the DAG under root matching the selector from a peer over GraphSync. The
+    /// response channel is notified once the response is complete and its blocks are verified
+    /// and stored in the blockstore. Dropping the receiver cancels the request.
+    GraphSyncRequest {
+        peer_id: PeerId,
+        root: Cid,
+        selector: Selector,
+        response_channel: OneShotSender<Result<(), String>>,
+    },
 }
 /// The Libp2pService listens to events from the Libp2p swarm.
 pub struct Libp2pService<DB: BlockStore> {
@@ -90,6 +138,12 @@ pub struct Libp2pService<DB: BlockStore> {
     db: Arc<DB>,
     /// Keeps track of Blocksync requests to responses
     bs_request_table: HashMap<RequestId, OneShotSender<BlockSyncResponse>>,
+    /// Keeps track of the pending Bitswap requests for each Cid
+    bitswap_response_channels: HashMap<Cid, Vec<OneShotSender<()>>>,
+    /// Keeps track of GraphSync requests to their response channels
+    gs_request_table: HashMap<RequestID, OneShotSender<Result<(), String>>>,
+    /// Number of invalid gossip messages forwarded by each peer
+    invalid_gossip: HashMap<PeerId, u32>,
     network_receiver_in: Receiver<NetworkMessage>,
     network_sender_in: Sender<NetworkMessage>,
     network_receiver_out: Receiver<NetworkEvent>,
@@ -98,7 +152,7 @@ pub struct Libp2pService<DB: BlockStore> {
 
 impl<DB> Libp2pService<DB>
 where
-    DB: BlockStore,
+    DB: BlockStore + Sync + Send + 'static,
 {
     /// Constructs a Libp2pService
     pub fn new(
@@ -133,8 +187,11 @@ where
 
         Libp2pService {
             swarm,
-            db,
+            db: Arc::clone(&db),
             bs_request_table: HashMap::new(),
+            bitswap_response_channels: HashMap::new(),
+            gs_request_table: HashMap::new(),
+            invalid_gossip: HashMap::new(),
             network_receiver_in,
             network_sender_in,
             network_receiver_out,
@@ -148,6 +205,16 @@ where
         let mut network_stream = self.network_receiver_in.fuse();
         let mut interval = stream::interval(Duration::from_secs(10)).fuse();
 
+        // Responds to the GraphSync requests of peers with the blocks of the blockstore, on its
+        // own task so loading the blocks doesn't hold up the swarm
+        let (gs_requests, gs_input) = mpsc::unbounded();
+        let (gs_output, mut gs_messages) = mpsc::channel(GS_MESSAGE_BUFFER);
+        task::spawn(ResponseManager::new(Default::default()).run(
+            BlockStoreLoader::new(Arc::clone(&self.db)),
+            gs_input,
+            gs_output,
+        ));
+
         loop {
             select! {
                 swarm_event = swarm_stream.next() => match swarm_event {
@@ -160,17 +227,22 @@ where
                         }
                         ForestBehaviourEvent::PeerDisconnected(peer_id) => {
                             debug!("Peer disconnected, {:?}", peer_id);
+                            let _ = gs_requests.unbounded_send(ResponseManagerInput::PeerDisconnected(peer_id));
                         }
                         ForestBehaviourEvent::GossipMessage {
                             source,
                             topics,
                             message,
+                            message_id,
+                            propagation_source,
                         } => {
                             trace!("Got a Gossip Message from {:?}", source);
                             self.network_sender_out.send(NetworkEvent::PubsubMessage {
                                 source,
                                 topics,
-                                message
+                                message,
+                                message_id,
+                                propagation_source,
                             }).await;
                         }
                         ForestBehaviourEvent::HelloRequest { request, channel, .. } => {
@@ -187,13 +259,15 @@ where
                                 response,
                             }).await;
                         }
-                        ForestBehaviourEvent::BlockSyncRequest { channel, .. } => {
-                            // TODO implement blocksync provider
-                            let _ = channel.send(BlockSyncResponse {
-                                chain: vec![],
-                                status: 203,
-                                message: "handling requests not implemented".to_owned(),
-                            }).await;
+                        ForestBehaviourEvent::BlockSyncRequest { request, channel, .. } => {
+                            debug!("Received blocksync request: {:?}", request);
+                            // Walking the requested tipsets reads the database, so the response
+                            // is made off the swarm loop and sent back through its channel
+                            let db = Arc::clone(&self.db);
+                            task::spawn(async move {
+                                let response = make_blocksync_response(db.as_ref(), &request);
+                                let _ = channel.send(response).await;
+                            });
                         }
                         ForestBehaviourEvent::BlockSyncResponse { request_id, response, .. } => {
                             debug!("Received blocksync response (id: {:?})", request_id);
@@ -217,6 +291,11 @@ where
                                     } else {
                                         trace!("saved bitswap block with cid {:?}", cid);
                                     }
+                                    if let Some(chans) = self.bitswap_response_channels.remove(&cid) {
+                                        for chan in chans {
+                                            let _ = chan.send(());
+                                        }
+                                    }
                                     self.network_sender_out.send(NetworkEvent::BitswapBlock{cid}).await;
                                 }
                                 Err(e) => {
@@ -238,6 +317,49 @@ where
                                 trace!("Failed to get data: {}", e.to_string());
                             }
                         },
+                        ForestBehaviourEvent::GraphSyncRequest { peer, request } => {
+                            trace!("Received graphsync request {} from {}", request.id, peer);
+                            let _ = gs_requests.unbounded_send(ResponseManagerInput::Request { peer, request });
+                        }
+                        ForestBehaviourEvent::GraphSyncProgress { id, peer, blocks_received } => {
+                            let canceled = self.gs_request_table.get(&id).map_or(true, |tx| tx.is_canceled());
+                            if canceled {
+                                debug!("Canceling graphsync request {} to {}", id, peer);
+                                self.gs_request_table.remove(&id);
+                                swarm_stream.get_mut().cancel_graphsync_request(id);
+                            } else {
+                                trace!("Received {} blocks for graphsync request {} from {}", blocks_received, id, peer);
+                            }
+                        }
+                        ForestBehaviourEvent::GraphSyncCompleted { id, request } => {
+                            if let Some(tx) = self.gs_request_table.remove(&id) {
+                                // Verifying and storing the blocks reads the database, so it is
+                                // kept off the swarm loop
+                                let db = Arc::clone(&self.db);
+                                task::spawn(async move {
+                                    let status = request.status;
+                                    let result = if status.is_success() {
+                                        let peer = request.peer.clone();
+                                        match store_verified_blocks(db.as_ref(), request).await {
+                                            Ok(stored) => {
+                                                debug!("Stored {} blocks of graphsync request {} from {}", stored, id, peer);
+                                                if status == ResponseStatusCode::RequestCompletedFull {
+                                                    Ok(())
+                                                } else {
+                                                    Err(format!("Graphsync request completed partially: {:?}", status))
+                                                }
+                                            }
+                                            Err(e) => Err(format!("Failed to verify graphsync response: {}", e)),
+                                        }
+                                    } else {
+                                        Err(format!("Graphsync request failed: {:?}", status))
+                                    };
+                                    let _ = tx.send(result);
+                                });
+                            } else {
+                                debug!("GraphSync response receive failed: channel not found");
+                            }
+                        }
                     }
                     None => { break; }
                 },
@@ -256,11 +378,53 @@ where
                             debug!("Sent BS Request with id: {:?}", id);
                             self.bs_request_table.insert(id, response_channel);
                         }
+                        NetworkMessage::BitswapRequest { cid, response_channel } => {
+                            if let Ok(Some(_)) = self.db.get_bytes(&cid) {
+                                let _ = response_channel.send(());
+                            } else if let Err(e) = swarm_stream.get_mut().want_block(cid.clone(), Default::default()) {
+                                warn!("Failed to send bitswap request: {}", e);
+                            } else {
+                                self.bitswap_response_channels.entry(cid).or_default().push(response_channel);
+                            }
+                        }
+                        NetworkMessage::GraphSyncRequest { peer_id, root, selector, response_channel } => {
+                            let id = swarm_stream.get_mut().send_graphsync_request(peer_id, root, selector, Default::default());
+                            debug!("Sent graphsync request with id: {}", id);
+                            self.gs_request_table.insert(id, response_channel);
+                        }
+                        NetworkMessage::ValidationResult { message_id, propagation_source, acceptance } => match acceptance {
+                            MessageAcceptance::Accept => {
+                                swarm_stream.get_mut().propagate_message(&message_id, &propagation_source);
+                            }
+                            MessageAcceptance::Reject => {
+                                let count = self.invalid_gossip.entry(propagation_source.clone()).or_default();
+                                *count += 1;
+                                debug!("Rejected gossip message from {} ({} invalid)", propagation_source, count);
+                                if *count >= MAX_INVALID_GOSSIP {
+                                    warn!("Banning peer {} for forwarding invalid gossip messages", propagation_source);
+                                    self.invalid_gossip.remove(&propagation_source);
+                                    Swarm::ban_peer_id(swarm_stream.get_mut(), propagation_source);
+                                }
+                            }
+                            MessageAcceptance::Ignore => (),
+                        }
                     }
                     None => { break; }
                 },
+                gs_message = gs_messages.next() => if let Some(PeerMessage { peer, responses, blocks }) = gs_message {
+                    swarm_stream.get_mut().send_response(&peer, responses, blocks).await;
+                },
                 interval_event = interval.next() => if interval_event.is_some() {
                     info!("Peers connected: {}", swarm_stream.get_ref().peers().len());
+                    // Drop the channels of timed out bitswap requests and cancel the wants no one awaits
+                    let swarm = swarm_stream.get_mut();
+                    self.bitswap_response_channels.retain(|cid, chans| {
+                        chans.retain(|chan| !chan.is_canceled());
+                        if chans.is_empty() {
+                            let _ = swarm.cancel_block(cid);
+                        }
+                        !chans.is_empty()
+                    });
                 }
             };
         }
@@ -0,0 +1,79 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use db::MemoryDB;
+use forest_address::Address;
+use forest_blocks::BlockHeader;
+use forest_cid::{
+    multihash::{Blake2b256, Identity},
+    Cid,
+};
+use forest_libp2p::blocksync::*;
+use ipld_blockstore::BlockStore;
+
+fn genesis_header(db: &MemoryDB) -> BlockHeader {
+    let header = BlockHeader::builder()
+        .messages(Cid::new_from_cbor(&[], Identity))
+        .message_receipts(Cid::new_from_cbor(&[], Identity))
+        .state_root(Cid::new_from_cbor(&[], Identity))
+        .miner_address(Address::new_id(0))
+        .build_and_validate()
+        .unwrap();
+    db.put(&header, Blake2b256).unwrap();
+    header
+}
+
+#[test]
+fn bad_requests() {
+    let db = MemoryDB::default();
+    let header = genesis_header(&db);
+
+    let res = make_blocksync_response(
+        &db,
+        &BlockSyncRequest {
+            start: vec![header.cid().clone()],
+            request_len: 1,
+            options: 0,
+        },
+    );
+    assert_eq!(res.status, STATUS_BAD_REQUEST);
+
+    let res = make_blocksync_response(
+        &db,
+        &BlockSyncRequest {
+            start: vec![header.cid().clone()],
+            request_len: 0,
+            options: BLOCKS,
+        },
+    );
+    assert_eq!(res.status, STATUS_BAD_REQUEST);
+
+    let res = make_blocksync_response(
+        &db,
+        &BlockSyncRequest {
+            start: vec![Cid::new_from_cbor(&[1], Blake2b256)],
+            request_len: 1,
+            options: BLOCKS,
+        },
+    );
+    assert_eq!(res.status, STATUS_NOT_FOUND);
+}
+
+#[test]
+fn serve_headers() {
+    let db = MemoryDB::default();
+    let header = genesis_header(&db);
+
+    let res = make_blocksync_response(
+        &db,
+        &BlockSyncRequest {
+            start: vec![header.cid().clone()],
+            request_len: 5,
+            options: BLOCKS,
+        },
+    );
+    assert_eq!(res.status, STATUS_OK);
+    assert_eq!(res.chain.len(), 1);
+    assert_eq!(res.chain[0].blocks, vec![header]);
+    assert!(res.chain[0].messages.is_none());
+}
@@ -0,0 +1,22 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::client::{Filecoin, HttpTransport as HTC};
+use jsonrpc_v2::Error as JsonRpcError;
+use jsonrpsee::raw::RawClient;
+
+/// Creates a new API token allowing the given permissions via RPC
+pub async fn auth_new(
+    client: &mut RawClient<HTC>,
+    perms: Vec<String>,
+) -> Result<String, JsonRpcError> {
+    Ok(Filecoin::auth_new(client, perms).await?)
+}
+
+/// Returns the permissions allowed by an API token via RPC
+pub async fn auth_verify(
+    client: &mut RawClient<HTC>,
+    token: String,
+) -> Result<Vec<String>, JsonRpcError> {
+    Ok(Filecoin::auth_verify(client, token).await?)
+}
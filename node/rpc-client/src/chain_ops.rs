@@ -1,12 +1,11 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use super::client::Filecoin;
+use super::client::{Filecoin, HttpTransport as HTC};
 use blocks::{header::json::BlockHeaderJson, tipset_json::TipsetJson};
 use cid::{json::CidJson, Cid};
 use jsonrpc_v2::Error as JsonRpcError;
 use jsonrpsee::raw::RawClient;
-use jsonrpsee::transport::http::HttpTransportClient as HTC;
 use message::unsigned_message::json::UnsignedMessageJson;
 
 /// Returns a block with specified CID fom chain via RPC
@@ -6,9 +6,17 @@
 
 use blocks::{header::json::BlockHeaderJson, tipset_json::TipsetJson};
 use cid::json::CidJson;
+use crypto::signature::json::SignatureJson;
+use futures::prelude::*;
+use jsonrpsee::common::{Request, Response};
 use jsonrpsee::raw::RawClient;
-use jsonrpsee::transport::http::HttpTransportClient;
+use jsonrpsee::transport::TransportClient;
 use message::unsigned_message::json::UnsignedMessageJson;
+use std::collections::VecDeque;
+use std::env;
+use std::pin::Pin;
+use thiserror::Error;
+use wallet::json::KeyInfoJson;
 
 jsonrpsee::rpc_api! {
     pub Filecoin {
@@ -27,13 +35,211 @@ jsonrpsee::rpc_api! {
 
         #[rpc(method = "Filecoin.ChainGetObj", positional_params)]
         fn chain_read_obj(cid: CidJson) -> Vec<u8>;
+
+        /// Wallet
+        #[rpc(method = "Filecoin.WalletNew", positional_params)]
+        fn wallet_new(sig_type: u8) -> String;
+
+        #[rpc(method = "Filecoin.WalletList")]
+        fn wallet_list() -> Vec<String>;
+
+        #[rpc(method = "Filecoin.WalletBalance", positional_params)]
+        fn wallet_balance(address: String) -> String;
+
+        #[rpc(method = "Filecoin.WalletHas", positional_params)]
+        fn wallet_has(address: String) -> bool;
+
+        #[rpc(method = "Filecoin.WalletExport", positional_params)]
+        fn wallet_export(address: String) -> KeyInfoJson;
+
+        #[rpc(method = "Filecoin.WalletImport", positional_params)]
+        fn wallet_import(key_info: KeyInfoJson) -> String;
+
+        #[rpc(method = "Filecoin.WalletSign", positional_params)]
+        fn wallet_sign(address: String, message: String) -> SignatureJson;
+
+        #[rpc(method = "Filecoin.WalletVerify", positional_params)]
+        fn wallet_verify(address: String, message: String, signature: SignatureJson) -> bool;
+
+        #[rpc(method = "Filecoin.WalletDefaultAddress")]
+        fn wallet_default_address() -> String;
+
+        #[rpc(method = "Filecoin.WalletSetDefault", positional_params)]
+        fn wallet_set_default(address: String) -> ();
+
+        /// Auth
+        #[rpc(method = "Filecoin.AuthNew", positional_params)]
+        fn auth_new(perms: Vec<String>) -> String;
+
+        #[rpc(method = "Filecoin.AuthVerify", positional_params)]
+        fn auth_verify(token: String) -> Vec<String>;
     }
 }
 
-// TODO need to handle dynamic port
-const URL: &str = "http://127.0.0.1:1234/rpc/v0";
+/// Environment variable holding the API info of the node, formatted as `[<token>:]<address>`
+pub const API_INFO_KEY: &str = "FULLNODE_API_INFO";
+/// Default port of the RPC server
+pub const DEFAULT_PORT: &str = "1234";
+const DEFAULT_HOST: &str = "127.0.0.1";
+const RPC_PATH: &str = "/rpc/v0";
+
+/// Endpoint and authorization token used to connect to the node's RPC API
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiInfo {
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl ApiInfo {
+    /// API info for the RPC server running locally on the given port
+    pub fn from_port(port: &str) -> Self {
+        Self {
+            url: format!("http://{}:{}{}", DEFAULT_HOST, port, RPC_PATH),
+            token: None,
+        }
+    }
+
+    /// Reads the API info from the `FULLNODE_API_INFO` environment variable, if set
+    pub fn from_env() -> Option<Result<Self, String>> {
+        env::var(API_INFO_KEY).ok().map(|info| Self::parse(&info))
+    }
+
+    /// Parses API info formatted as `[<token>:]<address>`, where the address is either a
+    /// multiaddr such as `/ip4/127.0.0.1/tcp/1234/http` or a `http://` URL.
+    pub fn parse(info: &str) -> Result<Self, String> {
+        let is_address =
+            |s: &str| s.starts_with('/') || s.starts_with("http://") || s.starts_with("https://");
+        let (token, addr) = if is_address(info) {
+            (None, info)
+        } else {
+            match info.find(':') {
+                Some(i) => (Some(info[..i].to_owned()), &info[i + 1..]),
+                None => return Err(format!("Invalid API info: {}", info)),
+            }
+        };
+        let url = if addr.starts_with("http://") || addr.starts_with("https://") {
+            addr.to_owned()
+        } else {
+            multiaddr_to_url(addr)?
+        };
+        Ok(Self { url, token })
+    }
+}
 
-// TODO pass config for URL
-pub fn new_client() -> RawClient<HttpTransportClient> {
-    RawClient::new(HttpTransportClient::new(URL))
+/// Converts a multiaddr of the form `/<ip4|ip6|dns|dns4|dns6>/<host>/tcp/<port>[/http]` to
+/// the url of the RPC endpoint.
+fn multiaddr_to_url(addr: &str) -> Result<String, String> {
+    let parts: Vec<&str> = addr.trim_start_matches('/').split('/').collect();
+    match parts.as_slice() {
+        [proto, host, "tcp", port] | [proto, host, "tcp", port, "http"] => {
+            let host = match *proto {
+                "ip4" | "dns" | "dns4" | "dns6" => host.to_string(),
+                "ip6" => format!("[{}]", host),
+                _ => return Err(format!("Unsupported address protocol: {}", proto)),
+            };
+            Ok(format!("http://{}:{}{}", host, port, RPC_PATH))
+        }
+        _ => Err(format!("Invalid API address: {}", addr)),
+    }
+}
+
+/// Errors of the RPC client HTTP transport
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("HTTP request failed: {0}")]
+    Http(String),
+    #[error("No response available")]
+    NoResponse,
+}
+
+/// HTTP transport for the JSON-RPC client, which attaches the authorization token as a bearer
+/// token to every request.
+pub struct HttpTransport {
+    url: String,
+    token: Option<String>,
+    responses: VecDeque<Response>,
+}
+
+impl HttpTransport {
+    pub fn new(api: &ApiInfo) -> Self {
+        Self {
+            url: api.url.clone(),
+            token: api.token.clone(),
+            responses: VecDeque::new(),
+        }
+    }
+}
+
+impl TransportClient for HttpTransport {
+    type Error = TransportError;
+
+    fn send_request<'s>(
+        &'s mut self,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>> + Send + 's>> {
+        Box::pin(async move {
+            let mut req = surf::post(&self.url)
+                .body_json(&request)
+                .map_err(|e| TransportError::Http(e.to_string()))?;
+            if let Some(token) = &self.token {
+                req = req.set_header("Authorization", format!("Bearer {}", token));
+            }
+            let response: Response = req
+                .recv_json()
+                .await
+                .map_err(|e| TransportError::Http(e.to_string()))?;
+            self.responses.push_back(response);
+            Ok(())
+        })
+    }
+
+    fn next_response<'s>(
+        &'s mut self,
+    ) -> Pin<Box<dyn Future<Output = Result<Response, Self::Error>> + Send + 's>> {
+        let response = self.responses.pop_front().ok_or(TransportError::NoResponse);
+        Box::pin(async move { response })
+    }
+}
+
+/// Creates a new RPC client for the node described by the API info
+pub fn new_client(api: &ApiInfo) -> RawClient<HttpTransport> {
+    RawClient::new(HttpTransport::new(api))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_api_info() {
+        assert_eq!(
+            ApiInfo::parse("/ip4/127.0.0.1/tcp/2345/http").unwrap(),
+            ApiInfo {
+                url: "http://127.0.0.1:2345/rpc/v0".to_owned(),
+                token: None
+            }
+        );
+        assert_eq!(
+            ApiInfo::parse("token:/dns4/node.local/tcp/1234").unwrap(),
+            ApiInfo {
+                url: "http://node.local:1234/rpc/v0".to_owned(),
+                token: Some("token".to_owned())
+            }
+        );
+        assert_eq!(
+            ApiInfo::parse("token:http://10.0.0.1:1234/rpc/v0").unwrap(),
+            ApiInfo {
+                url: "http://10.0.0.1:1234/rpc/v0".to_owned(),
+                token: Some("token".to_owned())
+            }
+        );
+        assert_eq!(
+            ApiInfo::parse("http://10.0.0.1:1234/rpc/v0").unwrap(),
+            ApiInfo {
+                url: "http://10.0.0.1:1234/rpc/v0".to_owned(),
+                token: None
+            }
+        );
+        assert!(ApiInfo::parse("/ip4/127.0.0.1/udp/1234").is_err());
+    }
 }
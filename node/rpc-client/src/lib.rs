@@ -1,8 +1,12 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+mod auth_ops;
 mod chain_ops;
 mod client;
+mod wallet_ops;
 
+pub use self::auth_ops::*;
 pub use self::chain_ops::*;
 pub use self::client::*;
+pub use self::wallet_ops::*;
@@ -0,0 +1,85 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::client::{Filecoin, HttpTransport as HTC};
+use crypto::{signature::json::SignatureJson, Signature, SignatureType};
+use jsonrpc_v2::Error as JsonRpcError;
+use jsonrpsee::raw::RawClient;
+use wallet::{json::KeyInfoJson, KeyInfo};
+
+/// Generates a new address of the given signature type in the wallet via RPC
+pub async fn wallet_new(
+    client: &mut RawClient<HTC>,
+    sig_type: SignatureType,
+) -> Result<String, JsonRpcError> {
+    Ok(Filecoin::wallet_new(client, sig_type as u8).await?)
+}
+
+/// Returns all addresses stored in the wallet via RPC
+pub async fn wallet_list(client: &mut RawClient<HTC>) -> Result<Vec<String>, JsonRpcError> {
+    Ok(Filecoin::wallet_list(client).await?)
+}
+
+/// Returns the balance of an address via RPC
+pub async fn wallet_balance(
+    client: &mut RawClient<HTC>,
+    address: String,
+) -> Result<String, JsonRpcError> {
+    Ok(Filecoin::wallet_balance(client, address).await?)
+}
+
+/// Returns whether the wallet contains the key of an address via RPC
+pub async fn wallet_has(
+    client: &mut RawClient<HTC>,
+    address: String,
+) -> Result<bool, JsonRpcError> {
+    Ok(Filecoin::wallet_has(client, address).await?)
+}
+
+/// Exports the key info of an address in the wallet via RPC
+pub async fn wallet_export(
+    client: &mut RawClient<HTC>,
+    address: String,
+) -> Result<KeyInfo, JsonRpcError> {
+    Ok(Filecoin::wallet_export(client, address).await?.0)
+}
+
+/// Imports key info into the wallet via RPC and returns the corresponding address
+pub async fn wallet_import(
+    client: &mut RawClient<HTC>,
+    key_info: KeyInfo,
+) -> Result<String, JsonRpcError> {
+    Ok(Filecoin::wallet_import(client, KeyInfoJson(key_info)).await?)
+}
+
+/// Signs a message with the key of an address in the wallet via RPC
+pub async fn wallet_sign(
+    client: &mut RawClient<HTC>,
+    address: String,
+    message: String,
+) -> Result<Signature, JsonRpcError> {
+    Ok(Filecoin::wallet_sign(client, address, message).await?.0)
+}
+
+/// Verifies a signature of a message for an address via RPC
+pub async fn wallet_verify(
+    client: &mut RawClient<HTC>,
+    address: String,
+    message: String,
+    signature: Signature,
+) -> Result<bool, JsonRpcError> {
+    Ok(Filecoin::wallet_verify(client, address, message, SignatureJson(signature)).await?)
+}
+
+/// Returns the default address of the wallet via RPC
+pub async fn wallet_default_address(client: &mut RawClient<HTC>) -> Result<String, JsonRpcError> {
+    Ok(Filecoin::wallet_default_address(client).await?)
+}
+
+/// Sets the default address of the wallet via RPC
+pub async fn wallet_set_default(
+    client: &mut RawClient<HTC>,
+    address: String,
+) -> Result<(), JsonRpcError> {
+    Ok(Filecoin::wallet_set_default(client, address).await?)
+}
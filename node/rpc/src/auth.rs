@@ -0,0 +1,175 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Permission to query chain, state and mpool data
+pub const READ: &str = "read";
+/// Permission to submit messages and blocks and manage wallet addresses
+pub const WRITE: &str = "write";
+/// Permission to sign with the keys of the wallet
+pub const SIGN: &str = "sign";
+/// Permission to export and import keys and administer the node
+pub const ADMIN: &str = "admin";
+
+/// All permissions, granted to tokens generated for the node operator
+pub const ADMIN_PERMISSIONS: [&str; 4] = [READ, WRITE, SIGN, ADMIN];
+/// Permissions granted to requests that do not carry a token
+pub const DEFAULT_PERMISSIONS: [&str; 1] = [READ];
+
+/// Length in bytes of the secret used to sign tokens
+pub const JWT_SECRET_LEN: usize = 32;
+
+/// Error type for token operations
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    #[error("Unknown permission: {0}")]
+    UnknownPermission(String),
+    #[error("Expected bearer token in authorization header")]
+    InvalidHeader,
+}
+
+/// Claims of the JSON web tokens, compatible with the tokens issued by Lotus
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    #[serde(rename = "Allow")]
+    allow: Vec<String>,
+}
+
+/// Generates a new random secret to sign tokens with
+pub fn generate_jwt_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; JWT_SECRET_LEN];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Creates a token, signed with the secret, allowing the given permissions
+pub fn create_token(perms: Vec<String>, secret: &[u8]) -> Result<String, Error> {
+    if let Some(p) = perms
+        .iter()
+        .find(|p| !ADMIN_PERMISSIONS.contains(&p.as_str()))
+    {
+        return Err(Error::UnknownPermission(p.clone()));
+    }
+    let token = encode(
+        &Header::default(),
+        &Claims { allow: perms },
+        &EncodingKey::from_secret(secret),
+    )?;
+    Ok(token)
+}
+
+/// Verifies the signature of a token and returns the permissions it allows
+pub fn verify_token(token: &str, secret: &[u8]) -> Result<Vec<String>, Error> {
+    // Tokens are long lived, so they do not contain an expiry
+    let validation = Validation {
+        validate_exp: false,
+        ..Validation::default()
+    };
+    let data = decode::<Claims>(token, &DecodingKey::from_secret(secret), &validation)?;
+    Ok(data.claims.allow)
+}
+
+/// Returns the permissions allowed by the value of an `Authorization` header, which must hold
+/// a bearer token. Requests without the header are only allowed to read.
+pub fn header_permissions(header: Option<&str>, secret: &[u8]) -> Result<Vec<String>, Error> {
+    const BEARER: &str = "Bearer ";
+    match header {
+        None => Ok(DEFAULT_PERMISSIONS.iter().map(|p| p.to_string()).collect()),
+        Some(h) if h.starts_with(BEARER) => verify_token(h[BEARER.len()..].trim(), secret),
+        Some(_) => Err(Error::InvalidHeader),
+    }
+}
+
+/// Returns the permission required to call an RPC method. Methods that are not known
+/// require admin permission.
+pub fn method_permission(method: &str) -> &'static str {
+    match method {
+        // Chain API
+        "Filecoin.ChainGetMessage"
+        | "Filecoin.ChainGetObj"
+        | "Filecoin.ChainHasObj"
+        | "Filecoin.ChainGetBlockMessages"
+        | "Filecoin.ChainGetTipsetByHeight"
+        | "Filecoin.ChainGetGenesis"
+        | "Filecoin.ChainTipsetWeight"
+        | "Filecoin.ChainGetTipset"
+        | "Filecoin.GetRandomness"
+        | "Filecoin.ChainGetBlock"
+        | "Filecoin.ChainHead"
+        | "Filecoin.ChainNotify" => READ,
+        // Message Pool API
+        "Filecoin.MpoolEstimateGasPrice" | "Filecoin.MpoolGetNonce" | "Filecoin.MpoolPending" => {
+            READ
+        }
+        "Filecoin.MpoolSub" => READ,
+        "Filecoin.MpoolPush" => WRITE,
+        "Filecoin.MpoolPushMessage" => SIGN,
+        // Sync API
+        "Filecoin.SyncCheckBad" | "Filecoin.SyncState" => READ,
+        "Filecoin.SyncSubmitBlock" => WRITE,
+        "Filecoin.SyncMarkBad" => ADMIN,
+        // Wallet API
+        "Filecoin.WalletBalance" | "Filecoin.WalletVerify" => READ,
+        "Filecoin.WalletDefaultAddress"
+        | "Filecoin.WalletHas"
+        | "Filecoin.WalletList"
+        | "Filecoin.WalletNew"
+        | "Filecoin.WalletSetDefault" => WRITE,
+        "Filecoin.WalletSign" | "Filecoin.WalletSignMessage" => SIGN,
+        "Filecoin.WalletExport" | "Filecoin.WalletImport" => ADMIN,
+        // Auth API
+        "Filecoin.AuthVerify" => READ,
+        "Filecoin.AuthNew" => ADMIN,
+        // State and Gas API
+        m if m.starts_with("Filecoin.State") || m.starts_with("Filecoin.Gas") => READ,
+        _ => ADMIN,
+    }
+}
+
+/// Checks if the permissions allow calling the RPC method.
+pub fn has_permission(method: &str, perms: &[String]) -> bool {
+    let required = method_permission(method);
+    perms.iter().any(|p| p == required)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_roundtrip() {
+        let secret = generate_jwt_secret();
+        let perms: Vec<String> = vec![READ.to_owned(), WRITE.to_owned()];
+        let token = create_token(perms.clone(), &secret).unwrap();
+        assert_eq!(verify_token(&token, &secret).unwrap(), perms);
+
+        // Token signed with a different secret is rejected
+        assert!(verify_token(&token, &generate_jwt_secret()).is_err());
+        assert!(create_token(vec!["root".to_owned()], &secret).is_err());
+
+        let header = format!("Bearer {}", token);
+        assert_eq!(header_permissions(Some(&header), &secret).unwrap(), perms);
+        assert_eq!(header_permissions(None, &secret).unwrap(), vec![READ]);
+        assert!(header_permissions(Some(&token), &secret).is_err());
+    }
+
+    #[test]
+    fn method_permissions() {
+        let read = vec![READ.to_owned()];
+        assert!(has_permission("Filecoin.ChainHead", &read));
+        assert!(has_permission("Filecoin.StateGetActor", &read));
+        assert!(!has_permission("Filecoin.WalletSign", &read));
+        assert!(!has_permission("Filecoin.WalletExport", &read));
+        assert!(!has_permission("Filecoin.Unknown", &read));
+
+        let admin: Vec<String> = ADMIN_PERMISSIONS.iter().map(|p| p.to_string()).collect();
+        assert!(has_permission("Filecoin.WalletExport", &admin));
+        assert!(has_permission("Filecoin.WalletSign", &admin));
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use crate::auth::{create_token, verify_token};
+use crate::RpcState;
+use blockstore::BlockStore;
+use jsonrpc_v2::{Data, Error as JsonRpcError, Params};
+use wallet::KeyStore;
+
+/// Creates a new token allowing the given permissions
+pub(crate) async fn auth_new<DB, KS>(
+    data: Data<RpcState<DB, KS>>,
+    Params(params): Params<(Vec<String>,)>,
+) -> Result<String, JsonRpcError>
+where
+    DB: BlockStore + Send + Sync + 'static,
+    KS: KeyStore + Send + Sync + 'static,
+{
+    let (perms,) = params;
+    Ok(create_token(perms, &data.jwt_secret)?)
+}
+
+/// Verifies a token and returns the permissions it allows
+pub(crate) async fn auth_verify<DB, KS>(
+    data: Data<RpcState<DB, KS>>,
+    Params(params): Params<(String,)>,
+) -> Result<Vec<String>, JsonRpcError>
+where
+    DB: BlockStore + Send + Sync + 'static,
+    KS: KeyStore + Send + Sync + 'static,
+{
+    let (token,) = params;
+    Ok(verify_token(&token, &data.jwt_secret)?)
+}
@@ -1,25 +1,38 @@
 // Copyright 2020 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod auth;
+mod auth_api;
 mod chain_api;
 mod gas_api;
 mod mpool_api;
 mod state_api;
 mod sync_api;
 mod wallet_api;
+mod ws;
 
 use crate::state_api::*;
+use async_std::net::TcpListener;
 use async_std::sync::{RwLock, Sender};
+use async_std::task;
 use blockstore::BlockStore;
+use chain::HeadChange;
 use chain_sync::{BadBlockCache, SyncState};
+use flo_stream::{MessagePublisher, Publisher, Subscriber};
 use forest_libp2p::NetworkMessage;
+use futures::StreamExt;
 use jsonrpc_v2::{Data, MapRouter, RequestObject, Server};
+use log::debug;
 use message_pool::{MessagePool, MpoolRpcProvider};
 use state_manager::StateManager;
 use std::sync::Arc;
+use tide::http::headers::AUTHORIZATION;
 use tide::{Request, Response, StatusCode};
 use wallet::KeyStore;
 
+/// Capacity of the buffer of each subscriber to relayed head changes
+const HEAD_CHANGE_SINK_CAP: usize = 200;
+
 /// This is where you store persistant data, or at least access to stateful data.
 pub struct RpcState<DB, KS>
 where
@@ -33,27 +46,88 @@ where
     pub sync_state: Arc<RwLock<SyncState>>,
     pub network_send: Sender<NetworkMessage>,
     pub network_name: String,
+    /// Secret used to sign and verify API tokens
+    pub jwt_secret: Vec<u8>,
+    /// Head changes of the chain store, streamed to `Filecoin.ChainNotify` subscribers
+    pub head_changes: Arc<RwLock<Publisher<HeadChange>>>,
+}
+
+/// Relays head changes from a chain store subscription to a publisher that RPC subscribers can
+/// subscribe to. The subscription is consumed by a task for as long as the node runs, so it
+/// never holds up the chain store.
+pub fn relay_head_changes(
+    mut subscriber: Subscriber<HeadChange>,
+) -> Arc<RwLock<Publisher<HeadChange>>> {
+    let publisher = Arc::new(RwLock::new(Publisher::new(HEAD_CHANGE_SINK_CAP)));
+    let relay = publisher.clone();
+    task::spawn(async move {
+        while let Some(change) = subscriber.next().await {
+            relay.write().await.publish(change).await;
+        }
+    });
+    publisher
+}
+
+/// State of the HTTP server, holding the RPC handler and the secret to verify tokens with.
+struct RpcServer {
+    handler: Arc<Server<MapRouter>>,
+    jwt_secret: Vec<u8>,
 }
 
-async fn handle_json_rpc(mut req: Request<Server<MapRouter>>) -> tide::Result {
-    let call: RequestObject = req.body_json().await?;
-    let res = req.state().handle(call).await;
+/// Returns the permissions of the request, from the bearer token in its `Authorization`
+/// header.
+fn request_permissions(req: &Request<RpcServer>) -> Result<Vec<String>, tide::Error> {
+    let header = req
+        .header(&AUTHORIZATION)
+        .map(|v| v.last().as_str());
+    auth::header_permissions(header, &req.state().jwt_secret)
+        .map_err(|e| tide::Error::from_str(StatusCode::Unauthorized, e.to_string()))
+}
+
+async fn handle_json_rpc(mut req: Request<RpcServer>) -> tide::Result {
+    let perms = request_permissions(&req)?;
+    let body: serde_json::Value = req.body_json().await?;
+
+    // Check the permission of the method before dispatching the call
+    let method = body
+        .get("method")
+        .and_then(|m| m.as_str())
+        .unwrap_or_default();
+    if !auth::has_permission(method, &perms) {
+        debug!("Rejected call to {} with permissions {:?}", method, perms);
+        return Err(tide::Error::from_str(
+            StatusCode::Forbidden,
+            format!(
+                "missing permission to invoke '{}' (need '{}')",
+                method,
+                auth::method_permission(method)
+            ),
+        ));
+    }
+
+    let call: RequestObject = serde_json::from_value(body)?;
+    let res = req.state().handler.handle(call).await;
     Ok(Response::new(StatusCode::Ok).body_json(&res)?)
 }
 
-pub async fn start_rpc<DB, KS>(state: RpcState<DB, KS>, rpc_endpoint: &str)
+/// Starts the JSON RPC server, with the HTTP endpoint at `rpc_endpoint` and the WebSocket
+/// endpoint, which also supports subscriptions, at `ws_endpoint`.
+pub async fn start_rpc<DB, KS>(state: RpcState<DB, KS>, rpc_endpoint: &str, ws_endpoint: &str)
 where
     DB: BlockStore + Send + Sync + 'static,
     KS: KeyStore + Send + Sync + 'static,
 {
+    use auth_api::*;
     use chain_api::*;
     use gas_api::*;
     use mpool_api::*;
     use sync_api::*;
     use wallet_api::*;
 
+    let jwt_secret = state.jwt_secret.clone();
+    let state = Arc::new(state);
     let rpc = Server::new()
-        .with_data(Data::new(state))
+        .with_data(Data(state.clone()))
         .with_method(
             "Filecoin.ChainGetMessage",
             chain_api::chain_get_message::<DB, KS>,
@@ -156,9 +230,24 @@ where
             gas_estimate_gas_premium::<DB, KS>,
         )
         .with_method("Filecoin.GasEstimateFeeCap", gas_estimate_fee_cap::<DB, KS>)
+        // Auth API
+        .with_method("Filecoin.AuthNew", auth_new::<DB, KS>)
+        .with_method("Filecoin.AuthVerify", auth_verify::<DB, KS>)
         .finish_unwrapped();
+    let rpc = Arc::new(rpc);
+
+    let ws_listener = TcpListener::bind(ws_endpoint).await.unwrap();
+    task::spawn(ws::serve(
+        ws_listener,
+        state,
+        rpc.clone(),
+        jwt_secret.clone(),
+    ));
 
-    let mut app = tide::Server::with_state(rpc);
+    let mut app = tide::Server::with_state(RpcServer {
+        handler: rpc,
+        jwt_secret,
+    });
     app.at("/rpc/v0").post(handle_json_rpc);
     app.listen(rpc_endpoint).await.unwrap();
 }
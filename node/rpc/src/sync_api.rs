@@ -92,9 +92,10 @@ mod tests {
     use chain::ChainStore;
     use chain_sync::SyncStage;
     use db::{MemoryDB, Store};
+    use flo_stream::Publisher;
     use forest_libp2p::NetworkMessage;
     use futures::StreamExt;
-    use message_pool::{MessagePool, MpoolRpcProvider};
+    use message_pool::{MessagePool, MpoolConfig, MpoolRpcProvider};
     use serde_json::from_str;
     use state_manager::StateManager;
     use std::sync::Arc;
@@ -123,7 +124,7 @@ mod tests {
                 db.as_ref().write(i.key(), bz2).unwrap();
             }
             let provider = MpoolRpcProvider::new(subscriber, cs.db);
-            MessagePool::new(provider, "test".to_string())
+            MessagePool::new(provider, "test".to_string(), MpoolConfig::default())
                 .await
                 .unwrap()
         });
@@ -136,6 +137,8 @@ mod tests {
             sync_state: Default::default(),
             network_send,
             network_name: TEST_NET_NAME.to_owned(),
+            jwt_secret: crate::auth::generate_jwt_secret(),
+            head_changes: Arc::new(RwLock::new(Publisher::new(1))),
         });
         (state, network_rx)
     }
@@ -0,0 +1,531 @@
+// Copyright 2020 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use crate::{auth, RpcState};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::sync::{self as async_sync, Arc, Receiver};
+use async_std::task;
+use async_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use async_tungstenite::tungstenite::http::{header::AUTHORIZATION, StatusCode};
+use async_tungstenite::tungstenite::Message;
+use blocks::tipset_json::TipsetJsonRef;
+use blockstore::BlockStore;
+use chain::{get_heaviest_tipset, HeadChange};
+use flo_stream::MessagePublisher;
+use futures::channel::mpsc::{channel, Sender};
+use futures::{select, FutureExt, SinkExt, Stream, StreamExt};
+use jsonrpc_v2::{MapRouter, RequestObject, Server};
+use log::{debug, info, warn};
+use message::signed_message::json::SignedMessageJsonRef;
+use message_pool::MpoolUpdate;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use wallet::KeyStore;
+
+/// Method of the notifications carrying values of a subscription channel
+const CHANNEL_VALUE: &str = "xrpc.ch.val";
+/// Method of the notification sent when a subscription channel is closed
+const CHANNEL_CLOSE: &str = "xrpc.ch.close";
+/// Maximum number of outgoing messages buffered for a connection. Subscriptions of clients
+/// that fall further behind are closed, so they never hold up the node.
+const OUTGOING_BUFFER: usize = 256;
+/// JSON-RPC error code for calls rejected by the server
+const SERVER_ERROR: i64 = -32000;
+
+/// Head change as sent to `Filecoin.ChainNotify` subscribers
+#[derive(Serialize)]
+struct HeadChangeJson<'a> {
+    #[serde(rename = "Type")]
+    change_type: &'static str,
+    #[serde(rename = "Val")]
+    val: TipsetJsonRef<'a>,
+}
+
+impl<'a> From<&'a HeadChange> for HeadChangeJson<'a> {
+    fn from(change: &'a HeadChange) -> Self {
+        let (change_type, ts) = match change {
+            HeadChange::Current(ts) => ("current", ts),
+            HeadChange::Apply(ts) => ("apply", ts),
+            HeadChange::Revert(ts) => ("revert", ts),
+        };
+        Self {
+            change_type,
+            val: TipsetJsonRef(ts.as_ref()),
+        }
+    }
+}
+
+/// Message pool update as sent to `Filecoin.MpoolSub` subscribers
+#[derive(Serialize)]
+struct MpoolUpdateJson<'a> {
+    #[serde(rename = "Type")]
+    update_type: u8,
+    #[serde(rename = "Message")]
+    message: SignedMessageJsonRef<'a>,
+}
+
+impl<'a> From<&'a MpoolUpdate> for MpoolUpdateJson<'a> {
+    fn from(update: &'a MpoolUpdate) -> Self {
+        let (update_type, msg) = match update {
+            MpoolUpdate::Add(msg) => (0, msg),
+            MpoolUpdate::Remove(msg) => (1, msg),
+        };
+        Self {
+            update_type,
+            message: SignedMessageJsonRef(msg),
+        }
+    }
+}
+
+/// Accepts WebSocket connections on the listener and serves JSON-RPC calls and subscriptions
+/// over them.
+pub(crate) async fn serve<DB, KS>(
+    listener: TcpListener,
+    state: Arc<RpcState<DB, KS>>,
+    handler: Arc<Server<MapRouter>>,
+    jwt_secret: Vec<u8>,
+) where
+    DB: BlockStore + Send + Sync + 'static,
+    KS: KeyStore + Send + Sync + 'static,
+{
+    let jwt_secret = Arc::new(jwt_secret);
+    let next_channel = Arc::new(AtomicU64::new(0));
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                let conn = handle_connection(
+                    stream,
+                    state.clone(),
+                    handler.clone(),
+                    jwt_secret.clone(),
+                    next_channel.clone(),
+                );
+                task::spawn(async move {
+                    if let Err(e) = conn.await {
+                        debug!("WebSocket connection closed with error: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept WebSocket connection: {}", e),
+        }
+    }
+}
+
+async fn handle_connection<DB, KS>(
+    stream: TcpStream,
+    state: Arc<RpcState<DB, KS>>,
+    handler: Arc<Server<MapRouter>>,
+    jwt_secret: Arc<Vec<u8>>,
+    next_channel: Arc<AtomicU64>,
+) -> Result<(), String>
+where
+    DB: BlockStore + Send + Sync + 'static,
+    KS: KeyStore + Send + Sync + 'static,
+{
+    // Permissions are checked once, from the headers of the handshake
+    let mut perms = Vec::new();
+    let callback = |req: &Request, resp: Response| -> Result<Response, ErrorResponse> {
+        let header = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+        match auth::header_permissions(header, &jwt_secret) {
+            Ok(p) => {
+                perms = p;
+                Ok(resp)
+            }
+            Err(e) => {
+                let mut err = ErrorResponse::new(Some(e.to_string()));
+                *err.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(err)
+            }
+        }
+    };
+    let ws = async_tungstenite::accept_hdr_async(stream, callback)
+        .await
+        .map_err(|e| e.to_string())?;
+    let (mut ws_sink, mut ws_stream) = ws.split();
+    // Dropped when the connection ends, which stops the subscriptions of the connection
+    let (_open, closed) = async_sync::channel::<()>(1);
+
+    // All responses and notifications are sent through a single buffer, so subscription tasks
+    // never write to the socket concurrently
+    let (mut out_tx, mut out_rx) = channel::<String>(OUTGOING_BUFFER);
+    task::spawn(async move {
+        while let Some(msg) = out_rx.next().await {
+            if ws_sink.send(Message::Text(msg)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(msg) = ws_stream.next().await {
+        let text = match msg.map_err(|e| e.to_string())? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let response = match serde_json::from_str::<Value>(&text) {
+            Ok(call) => {
+                handle_call(
+                    call,
+                    &perms,
+                    &state,
+                    &handler,
+                    &next_channel,
+                    &out_tx,
+                    &closed,
+                )
+                .await
+            }
+            Err(e) => Some(error_response(Value::Null, &e.to_string())),
+        };
+        if let Some(response) = response {
+            out_tx
+                .send(response.to_string())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles a single call, returning the response if the call is not a notification.
+async fn handle_call<DB, KS>(
+    call: Value,
+    perms: &[String],
+    state: &RpcState<DB, KS>,
+    handler: &Server<MapRouter>,
+    next_channel: &AtomicU64,
+    out_tx: &Sender<String>,
+    closed: &Receiver<()>,
+) -> Option<Value>
+where
+    DB: BlockStore + Send + Sync + 'static,
+    KS: KeyStore + Send + Sync + 'static,
+{
+    let id = call.get("id").cloned().unwrap_or(Value::Null);
+    let method = call
+        .get("method")
+        .and_then(|m| m.as_str())
+        .unwrap_or_default()
+        .to_owned();
+    if !auth::has_permission(&method, perms) {
+        let msg = format!(
+            "missing permission to invoke '{}' (need '{}')",
+            method,
+            auth::method_permission(&method)
+        );
+        return Some(error_response(id, &msg));
+    }
+
+    match method.as_str() {
+        "Filecoin.ChainNotify" => {
+            let chan = next_channel.fetch_add(1, Ordering::Relaxed);
+            let subscriber = state.head_changes.write().await.subscribe();
+            // Subscribers first receive the current head, followed by every change of it
+            let current = match get_heaviest_tipset(state.state_manager.get_block_store_ref()) {
+                Ok(Some(ts)) => vec![HeadChange::Current(Arc::new(ts))],
+                Ok(None) => Vec::new(),
+                Err(e) => return Some(error_response(id, &e.to_string())),
+            };
+            task::spawn(forward(
+                chan,
+                futures::stream::iter(current).chain(subscriber),
+                |change: &HeadChange| json!([HeadChangeJson::from(change)]),
+                out_tx.clone(),
+                closed.clone(),
+            ));
+            Some(json!({ "jsonrpc": "2.0", "result": chan, "id": id }))
+        }
+        "Filecoin.MpoolSub" => {
+            let chan = next_channel.fetch_add(1, Ordering::Relaxed);
+            let subscriber = state.mpool.subscribe().await;
+            task::spawn(forward(
+                chan,
+                subscriber,
+                |update: &MpoolUpdate| json!(MpoolUpdateJson::from(update)),
+                out_tx.clone(),
+                closed.clone(),
+            ));
+            Some(json!({ "jsonrpc": "2.0", "result": chan, "id": id }))
+        }
+        _ => {
+            let call: RequestObject = match serde_json::from_value(call) {
+                Ok(call) => call,
+                Err(e) => return Some(error_response(id, &e.to_string())),
+            };
+            match serde_json::to_value(handler.handle(call).await) {
+                // Notifications have an empty response, which is not sent
+                Ok(Value::Null) => None,
+                Ok(res) => Some(res),
+                Err(e) => Some(error_response(id, &e.to_string())),
+            }
+        }
+    }
+}
+
+/// Forwards the values of a subscription to the connection, as notifications on the channel.
+/// Stops when the connection is closed or when the client cannot keep up.
+async fn forward<T, S, F>(
+    chan: u64,
+    mut values: S,
+    to_json: F,
+    mut out_tx: Sender<String>,
+    closed: Receiver<()>,
+) where
+    S: Stream<Item = T> + Unpin,
+    F: Fn(&T) -> Value,
+{
+    loop {
+        let value = select! {
+            value = values.next().fuse() => match value {
+                Some(value) => value,
+                None => break,
+            },
+            // Nothing is ever sent, the receiver errors once the connection ends
+            _ = closed.recv().fuse() => return,
+        };
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": CHANNEL_VALUE,
+            "params": [chan, to_json(&value)],
+        });
+        if let Err(e) = out_tx.try_send(notification.to_string()) {
+            if e.is_full() {
+                info!("Closing subscription {}, client is not keeping up", chan);
+                break;
+            }
+            // Connection is closed
+            return;
+        }
+    }
+    // Unsubscribe before waiting for room in the buffer to notify the client of the close
+    drop(values);
+    let close = json!({ "jsonrpc": "2.0", "method": CHANNEL_CLOSE, "params": [chan] });
+    let _ = out_tx.send(close.to_string()).await;
+}
+
+fn error_response(id: Value, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": { "code": SERVER_ERROR, "message": message },
+        "id": id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay_head_changes;
+    use actor::{init, ActorState, ACCOUNT_ACTOR_CODE_ID, INIT_ACTOR_ADDR, INIT_ACTOR_CODE_ID};
+    use address::Address;
+    use amt::Amt;
+    use async_std::future;
+    use async_std::sync::RwLock;
+    use async_tungstenite::async_std::connect_async;
+    use blocks::{BlockHeader, Tipset, TxMeta};
+    use chain::ChainStore;
+    use cid::{
+        multihash::{Blake2b256, Identity},
+        Cid,
+    };
+    use crypto::SignatureType;
+    use db::MemoryDB;
+    use encoding::Cbor;
+    use message::{SignedMessage, UnsignedMessage};
+    use message_pool::{MessagePool, MpoolConfig, MpoolRpcProvider};
+    use num_bigint::BigInt;
+    use state_manager::StateManager;
+    use state_tree::StateTree;
+    use std::time::Duration;
+    use wallet::{MemKeyStore, Wallet};
+
+    /// Tipset of a single header at the epoch with the messages, on top of the state
+    fn tipset_with_state(epoch: i64, messages: Cid, state_root: Cid) -> Tipset {
+        let header = BlockHeader::builder()
+            .messages(messages)
+            .message_receipts(Cid::new_from_cbor(&[], Identity))
+            .state_root(state_root)
+            .miner_address(Address::new_id(0))
+            .epoch(epoch)
+            .build_and_validate()
+            .unwrap();
+        Tipset::new(vec![header]).unwrap()
+    }
+
+    /// Tipset of a single header at the epoch, which can be persisted in the chain store
+    fn tipset(epoch: i64) -> Tipset {
+        let empty = Cid::new_from_cbor(&[], Identity);
+        tipset_with_state(epoch, empty.clone(), empty)
+    }
+
+    /// Serves the state over WebSocket, returning the state along with the address to connect to
+    async fn start_server(
+        cs: &mut ChainStore<MemoryDB>,
+    ) -> (Arc<RpcState<MemoryDB, MemKeyStore>>, String) {
+        let db = cs.db.clone();
+        let (network_send, _network_rx) = async_std::sync::channel(5);
+        let provider = MpoolRpcProvider::new(cs.subscribe(), db.clone());
+        let mpool = MessagePool::new(provider, "test".to_owned(), MpoolConfig::default())
+            .await
+            .unwrap();
+        let state = Arc::new(RpcState {
+            state_manager: StateManager::new(db),
+            keystore: Arc::new(RwLock::new(MemKeyStore::new())),
+            mpool: Arc::new(mpool),
+            bad_blocks: Default::default(),
+            sync_state: Default::default(),
+            network_send,
+            network_name: "test".to_owned(),
+            jwt_secret: auth::generate_jwt_secret(),
+            head_changes: relay_head_changes(cs.subscribe()),
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(Server::new().finish_unwrapped());
+        let jwt_secret = state.jwt_secret.clone();
+        task::spawn(serve(listener, state.clone(), handler, jwt_secret));
+        (state, format!("ws://{}", addr))
+    }
+
+    /// Reads the next text message of the connection as JSON
+    async fn next_json<S>(ws_stream: &mut S) -> Value
+    where
+        S: Stream<Item = Result<Message, async_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        loop {
+            if let Message::Text(text) = ws_stream.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn chain_notify_relays_head_changes() {
+        let db = Arc::new(MemoryDB::default());
+        let mut cs = ChainStore::new(db.clone());
+        cs.put_tipset(&tipset(1)).await.unwrap();
+        let (state, url) = start_server(&mut cs).await;
+
+        let (ws, _) = connect_async(url).await.unwrap();
+        let (mut ws_sink, mut ws_stream) = ws.split();
+        let call =
+            json!({ "jsonrpc": "2.0", "method": "Filecoin.ChainNotify", "params": [], "id": 1 });
+        ws_sink.send(Message::Text(call.to_string())).await.unwrap();
+
+        // The response and the notification of the current head can arrive in any order
+        let mut chan = None;
+        let mut current = None;
+        for _ in 0..2 {
+            let msg = next_json(&mut ws_stream).await;
+            if msg["id"] == 1 {
+                chan = Some(msg["result"].clone());
+            } else {
+                current = Some(msg);
+            }
+        }
+        let chan = chan.unwrap();
+        let current = current.unwrap();
+        assert_eq!(current["method"], CHANNEL_VALUE);
+        assert_eq!(current["params"][0], chan);
+        assert_eq!(current["params"][1][0]["Type"], "current");
+        assert_eq!(current["params"][1][0]["Val"]["Height"], 1);
+
+        // Changes of the chain store head are relayed to the subscriber
+        cs.set_heaviest_tipset(Arc::new(tipset(2))).await.unwrap();
+        let change = next_json(&mut ws_stream).await;
+        assert_eq!(change["method"], CHANNEL_VALUE);
+        assert_eq!(change["params"][0], chan);
+        assert_eq!(change["params"][1][0]["Type"], "current");
+        assert_eq!(change["params"][1][0]["Val"]["Height"], 2);
+
+        // Closing the connection stops the subscription, without waiting for another change
+        ws_sink.close().await.unwrap();
+        future::timeout(Duration::from_secs(5), async {
+            while state.head_changes.read().await.count_subscribers() > 0 {
+                task::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[async_std::test]
+    async fn mpool_sub_relays_added_messages() {
+        let mut wallet = Wallet::new(MemKeyStore::new());
+        let sender = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        // State in which the sender has funds to send messages
+        let db = Arc::new(MemoryDB::default());
+        let mut tree = StateTree::new(db.as_ref());
+        let address_map = StateTree::new(db.as_ref()).flush().unwrap();
+        let init_state = db
+            .put(
+                &init::State::new(address_map, "test".to_owned()),
+                Blake2b256,
+            )
+            .unwrap();
+        let init_actor = ActorState::new(
+            INIT_ACTOR_CODE_ID.clone(),
+            init_state,
+            Default::default(),
+            0,
+        );
+        tree.set_actor(&INIT_ACTOR_ADDR, init_actor).unwrap();
+        let sender_id = tree.register_new_address(&sender).unwrap();
+        let sender_actor = ActorState::new(
+            ACCOUNT_ACTOR_CODE_ID.clone(),
+            Cid::new_from_cbor(&[], Identity),
+            BigInt::from(10u64.pow(18)),
+            0,
+        );
+        tree.set_actor(&sender_id, sender_actor).unwrap();
+        let state_root = tree.flush().unwrap();
+
+        // The pool reads the messages of the head to find the sequence of the sender
+        let empty_amt = Amt::<Cid, _>::new_from_slice(db.as_ref(), &[]).unwrap();
+        let meta = TxMeta {
+            bls_message_root: empty_amt.clone(),
+            secp_message_root: empty_amt,
+        };
+        let messages = db.put(&meta, Blake2b256).unwrap();
+
+        let mut cs = ChainStore::new(db.clone());
+        cs.put_tipset(&tipset_with_state(1, messages, state_root))
+            .await
+            .unwrap();
+        let (state, url) = start_server(&mut cs).await;
+
+        let (ws, _) = connect_async(url).await.unwrap();
+        let (mut ws_sink, mut ws_stream) = ws.split();
+        let call =
+            json!({ "jsonrpc": "2.0", "method": "Filecoin.MpoolSub", "params": [], "id": 1 });
+        ws_sink.send(Message::Text(call.to_string())).await.unwrap();
+        let response = next_json(&mut ws_stream).await;
+        assert_eq!(response["id"], 1);
+        let chan = response["result"].clone();
+
+        // Messages added to the pool are relayed to the subscriber
+        let umsg = UnsignedMessage::builder()
+            .to(Address::new_id(1001))
+            .from(sender)
+            .sequence(0)
+            .gas_limit(1000)
+            .gas_fee_cap(BigInt::from(100))
+            .build()
+            .unwrap();
+        let sig = wallet.sign(&sender, &umsg.marshal_cbor().unwrap()).unwrap();
+        let msg = SignedMessage::new_from_parts(umsg, sig).unwrap();
+        state.mpool.add(&msg).await.unwrap();
+
+        let update = next_json(&mut ws_stream).await;
+        assert_eq!(update["method"], CHANNEL_VALUE);
+        assert_eq!(update["params"][0], chan);
+        assert_eq!(update["params"][1]["Type"], 0);
+        assert_eq!(update["params"][1]["Message"]["Message"]["Nonce"], 0);
+    }
+}
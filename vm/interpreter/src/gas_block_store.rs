@@ -3,7 +3,7 @@
 
 use super::gas_tracker::{GasTracker, PriceList};
 use cid::{multihash::MultihashDigest, Cid};
-use db::{Error, Store};
+use db::{Batch, Error, Store};
 use forest_encoding::{de::DeserializeOwned, from_slice, ser::Serialize, to_vec};
 use ipld_blockstore::BlockStore;
 use std::cell::RefCell;
@@ -112,6 +112,31 @@ where
     {
         self.store.bulk_delete(keys)
     }
+    fn read_metadata<K>(&self, key: K) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.store.read_metadata(key)
+    }
+    fn write_metadata<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.store.write_metadata(key, value)
+    }
+    fn delete_metadata<K>(&self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.store.delete_metadata(key)
+    }
+    fn begin(&self) -> Batch {
+        self.store.begin()
+    }
+    fn commit(&self, batch: Batch) -> Result<(), Error> {
+        self.store.commit(batch)
+    }
 }
 
 #[cfg(test)]